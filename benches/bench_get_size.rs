@@ -17,7 +17,7 @@ pub struct Level2 {
 
 fn main() {
     let level1 = Level1 { data : vec![1, 2, 3] };
-    let level2 = Level2 { level1 : level1, msg : "Hello".to_string() };
+    let level2 = Level2 { level1, msg : "Hello".to_string() };
 
     println!("Bench get_size");
 
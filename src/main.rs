@@ -19,7 +19,7 @@ fn main() {
     let static_size = std::mem::size_of::<Level1>();
     println!("Size of level1 struct: {} (static size: {})", size, static_size);
 
-    let mut level2 = Level2 { level1 : level1, msg : "Hello".to_string() };
+    let mut level2 = Level2 { level1, msg : "Hello".to_string() };
     
     let size = level2.get_size();
     println!("Size of level2 struct: {}", size);
@@ -1,43 +1,87 @@
 use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use rand::Rng;
-use serde::{Deserialize, Serialize};
-use storage::{FifoFileCache, MockRequest, WriteResponse};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use serde::Serialize;
+use storage::workload::{
+    KeyDistribution, Op, SizeDistribution, TestValue, WorkloadRng, WorkloadSpec,
+};
+use storage::{Crc32, FifoFileCache, Storage, SyncMode, WriteResponse};
 
-/// It's mock the kv workload for storage bench.
-/// First it generates a lot of random key,value pairs.
-/// The value is the key's hash
-/// Then it start write and read threads to do the kv workload
-/// The write thread will random pick a key,value pair and write it to the storage
-/// The read thread will random pick a key follow zipf distribution and read it from the storage
+// It's mock the kv workload for storage bench.
+// First it generates a lot of random key,value pairs.
+// The value is the key's hash
+// Then it start write and read threads to do the kv workload
+// The write thread will random pick a key,value pair and write it to the storage
+// The read thread will random pick a key follow zipf distribution and read it from the storage
+//
+// The bench runs under criterion with three groups: write only, read only on a fully
+// populated cache, and the mixed workload above. The concurrent read group compares the
+// version check with seqlock reads at 64 readers, the shared read group deserializing a
+// large value on every read with sharing it through read_arc, the cold read group reads
+// random values with the page cache of the file dropped before each read, next to the
+// same reads on a warm file (Linux only, filesystems like tmpfs ignore the drop). The
+// validation group reads while a writer recycles pages, with the version check only, the
+// checksum only and both, counting the stale records served next to the hit latency. The
+// buffered write group compares writes with a page sized write buffer to unbuffered ones,
+// and counts the write syscalls of each at 1000 writes per second (Linux only). The
+// switch slack group writes values of two sizes to tiny pages, switching pages on demand
+// and right after the writes leaving less than a small value in the page. Set STORAGE_BENCH_TRACE to a csv path
+// to record the latency of every operation of the mixed workload. The parameters of
+// the run and its environment are written to run_meta.json next to it, and as comment
+// lines at the top of the csv. The analyze_trace example summarizes the csv.
 
 const CACHE_SIZE: usize = 10_000;
+// The geometry of the caches of the write, read and mixed benches
+const PAGE_SIZE: usize = 4096;
+const PAGE_COUNT: usize = 1024;
 const READER_COUNT: usize = 8;
+const READS_PER_WRITE: u64 = 20;
+// 280 bytes value is the most common value size in real cache workload
+const VALUE_SIZE: usize = 280;
+// Pages of the switch tail bench, they hold three values so switches are frequent
+const TINY_PAGE_SIZE: usize = 1024;
+// Concurrent writers of the durable write bench
+const DURABLE_WRITER_COUNT: usize = 8;
+// Values of the shared read bench, large enough for deserializing to dominate
+const LARGE_VALUE_SIZE: usize = 64 * 1024;
+// Readers of the concurrent read bench
+const CONCURRENT_READER_COUNT: usize = 64;
+// Pages of the validation bench, few enough for its writer to recycle them during the
+// reads
+const VALIDATION_PAGE_COUNT: usize = 64;
+// Keys of the validation bench, about twice the records its cache holds
+const VALIDATION_KEY_COUNT: usize = 2000;
+// The pace of the syscall count of the buffered write bench
+const PACED_WRITES_PER_SEC: u64 = 1000;
+// The two sizes of the values of the switch slack bench, the small ones are the slack
+const SMALL_VALUE_SIZE: usize = 48;
+const BIG_VALUE_SIZE: usize = 700;
+// How much slower than the median the p999 of the tiny page writes may be
+const TAIL_FACTOR: u32 = 100;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
-struct TestValue {
-    check_sum: u32,
-    value: Vec<u8>,
-}
-
-impl TestValue {
-    fn new() -> Self {
-        let mut rng = rand::thread_rng();
-        // 280 bytes value is the most common value size in real cache workload
-        let value: Vec<u8> = (0..280).map(|_| rng.gen()).collect();
-        let check_sum = crc32fast::hash(&value);
-        Self { check_sum, value }
+// The keys of the cache map with values of VALUE_SIZE, `seed` should differ between
+// the threads
+fn workload(write_ratio: f64, seed: u64) -> WorkloadSpec {
+    WorkloadSpec {
+        key_count: CACHE_SIZE as u64,
+        key_dist: KeyDistribution::Uniform,
+        value_size_dist: SizeDistribution::Fixed(VALUE_SIZE),
+        write_ratio,
+        seed,
     }
+}
 
-    fn validate(&self) {
-        let check_sum = crc32fast::hash(&self.value);
-        assert_eq!(check_sum, self.check_sum);
-    }
+fn new_value(rng: &mut WorkloadRng) -> TestValue {
+    TestValue::generate(VALUE_SIZE, rng)
 }
-impl storage::Value for TestValue {}
 
+#[allow(dead_code)]
 enum CacheItenInner {
     Memory(TestValue),
     File(WriteResponse),
@@ -57,22 +101,84 @@ impl Default for CacheItem {
 }
 
 impl CacheItem {
-    fn update_file(&self, reponse: WriteResponse) {
+    // Write the value and point the item at it under the item's lock, so inserts of the
+    // same key can't leave the item on the older record: once it returns, reads of the key
+    // get this value or a newer one. Returns the response and how long the write took.
+    fn insert(&self, file_cache: &FifoFileCache, value: TestValue) -> (WriteResponse, Duration) {
         let mut inner = self.inner.write().unwrap();
-        *inner = CacheItenInner::File(reponse);
+        let start = std::time::Instant::now();
+        let response = file_cache.write(value).unwrap();
+        let elapsed = start.elapsed();
+        *inner = CacheItenInner::File(response.clone());
+        (response, elapsed)
     }
 
-    fn read(&self, file_cache: &FifoFileCache) -> Option<(TestValue, WriteResponse)> {
+    fn read(&self, file_cache: &FifoFileCache) -> Lookup {
         let inner = self.inner.read().unwrap();
         match &*inner {
-            CacheItenInner::Memory(_) => None,
-            CacheItenInner::File(reponse) => {
-                let value = file_cache.read(&reponse)?;
-                Some((value, reponse.clone()))
+            CacheItenInner::Memory(_) => Lookup::UnknownSizeMiss,
+            CacheItenInner::File(reponse) => match file_cache.read(reponse).unwrap() {
+                Some(value) => Lookup::Hit(value, reponse.clone()),
+                None => Lookup::Miss(reponse.length),
+            },
+            CacheItenInner::Invalid => Lookup::UnknownSizeMiss,
+        }
+    }
+}
+
+enum Lookup {
+    Hit(TestValue, WriteResponse),
+    // The entry was evicted, the size of the value is known from the response
+    Miss(usize),
+    // The key was never written, so we don't know how large the value would be
+    UnknownSizeMiss,
+}
+
+#[derive(Default)]
+struct HitStats {
+    hits: AtomicU64,
+    hit_bytes: AtomicU64,
+    misses: AtomicU64,
+    miss_bytes: AtomicU64,
+    unknown_size_misses: AtomicU64,
+}
+
+impl HitStats {
+    fn record(&self, lookup: &Lookup) {
+        match lookup {
+            Lookup::Hit(_, reponse) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                self.hit_bytes
+                    .fetch_add(reponse.length as u64, Ordering::Relaxed);
+            }
+            Lookup::Miss(length) => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                self.miss_bytes.fetch_add(*length as u64, Ordering::Relaxed);
+            }
+            Lookup::UnknownSizeMiss => {
+                self.unknown_size_misses.fetch_add(1, Ordering::Relaxed);
             }
-            CacheItenInner::Invalid => None,
         }
     }
+
+    fn print_summary(&self) {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let hit_bytes = self.hit_bytes.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let miss_bytes = self.miss_bytes.load(Ordering::Relaxed);
+        let unknown_size_misses = self.unknown_size_misses.load(Ordering::Relaxed);
+        let reads = hits + misses + unknown_size_misses;
+        println!(
+            "reads: {}, hits: {}, misses: {}, unknown-size misses: {}",
+            reads, hits, misses, unknown_size_misses
+        );
+        println!("object hit ratio: {:.4}", hits as f64 / reads.max(1) as f64);
+        // Unknown-size misses are left out, we can't tell how many bytes they would cost
+        println!(
+            "byte hit ratio: {:.4} (excluding unknown-size misses)",
+            hit_bytes as f64 / (hit_bytes + miss_bytes).max(1) as f64
+        );
+    }
 }
 
 struct Cache {
@@ -106,20 +212,21 @@ fn write_thread(
     cache: Arc<FifoFileCache>,
     cache_map: Arc<Cache>,
     write_count: u64,
-    trace_sender: std::sync::mpsc::Sender<OperationTrace>,
+    trace_sender: Option<Sender<OperationTrace>>,
 ) {
-    let mut rng = rand::thread_rng();
+    let mut ops = workload(1.0, 0).ops();
     for _ in 0..write_count {
-        let key = rng.gen_range(0..CACHE_SIZE as u64);
-        let value = TestValue::new();
+        let Some(Op::Set(key, size)) = ops.next() else {
+            unreachable!("the writer only writes");
+        };
+        let value = TestValue::generate(size, ops.rng());
         value.validate();
-        let start = std::time::Instant::now();
-        let response = cache.write(value);
-        let elapsed = start.elapsed();
-        trace_sender
-            .send(OperationTrace::Write(response.clone(), elapsed))
-            .unwrap();
-        cache_map.items.get(&key).unwrap().update_file(response);
+        let (response, elapsed) = cache_map.items.get(&key).unwrap().insert(&cache, value);
+        if let Some(trace_sender) = &trace_sender {
+            trace_sender
+                .send(OperationTrace::Write(response, elapsed))
+                .unwrap();
+        }
     }
 }
 
@@ -127,19 +234,25 @@ fn read_thread(
     cache: Arc<FifoFileCache>,
     cache_map: Arc<Cache>,
     read_count: u64,
-    trace_sender: std::sync::mpsc::Sender<OperationTrace>,
+    seed: u64,
+    hit_stats: Arc<HitStats>,
+    trace_sender: Option<Sender<OperationTrace>>,
 ) {
-    let mut rng = rand::thread_rng();
-    for _ in 0..read_count {
-        let key = rng.gen_range(0..CACHE_SIZE as u64);
+    for op in workload(0.0, seed).ops().take(read_count as usize) {
+        let Op::Get(key) = op else {
+            unreachable!("the readers only read");
+        };
         let start = std::time::Instant::now();
         let item = cache_map.items.get(&key).unwrap();
-        let value = item.read(&cache);
-        if let Some((value, reponse)) = value {
+        let lookup = item.read(&cache);
+        hit_stats.record(&lookup);
+        if let Lookup::Hit(value, reponse) = lookup {
             let elapsed = start.elapsed();
-            trace_sender
-                .send(OperationTrace::Read(reponse, elapsed))
-                .unwrap();
+            if let Some(trace_sender) = &trace_sender {
+                trace_sender
+                    .send(OperationTrace::Read(reponse, elapsed))
+                    .unwrap();
+            }
             value.validate();
         }
     }
@@ -149,8 +262,10 @@ fn read_thread(
 // The file can be used to analyze the performance of the storage
 // The csv file has the following columns:
 // operation_type, page_id, page_offset, version, duration
-fn write_trace(receiver: std::sync::mpsc::Receiver<OperationTrace>) {
-    let mut writer = csv::Writer::from_path("trace.csv").unwrap();
+fn write_trace(path: PathBuf, meta: &RunMeta, receiver: Receiver<OperationTrace>) {
+    let mut file = std::fs::File::create(path).unwrap();
+    meta.write_csv_header(&mut file).unwrap();
+    let mut writer = csv::Writer::from_writer(file);
     for trace in receiver {
         match trace {
             OperationTrace::Read(reponse, duration) => {
@@ -180,23 +295,120 @@ fn write_trace(receiver: std::sync::mpsc::Receiver<OperationTrace>) {
     }
 }
 
-fn main() {
-    let dir = tempfile::tempdir().unwrap();
+fn new_cache(dir: &tempfile::TempDir) -> Arc<FifoFileCache> {
     let path = dir.path().join("test_read_write");
-    let page_size = 4096;
-    let capacity = page_size * 1024;
-    let cache = Arc::new(FifoFileCache::new(path.clone(), page_size, capacity));
-    let cache_map = Arc::new(generate_cache());
+    Arc::new(FifoFileCache::new(path, PAGE_SIZE, PAGE_SIZE * PAGE_COUNT))
+}
 
-    let (trace_sender, trace_receiver) = std::sync::mpsc::channel();
+// The parameters and environment of a run, to compare runs across machines and commits
+#[derive(Clone, Serialize)]
+struct RunMeta {
+    args: Vec<String>,
+    // The workload seeds are the writer's and each reader's index
+    writer_seed: u64,
+    reader_seeds: Vec<u64>,
+    key_count: usize,
+    value_size: usize,
+    reader_count: usize,
+    reads_per_write: u64,
+    page_size: usize,
+    page_count: usize,
+    crate_version: &'static str,
+    git_hash: Option<String>,
+    os: String,
+    // The filesystem holding the cache file, from /proc/self/mounts
+    filesystem: Option<String>,
+    cpu_count: usize,
+    // Unix timestamps in seconds
+    start_time: u64,
+    end_time: Option<u64>,
+}
 
-    let trace_handle = std::thread::spawn(move || {
-        write_trace(trace_receiver);
-    });
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+// The type of the filesystem of the longest mount point containing `path`
+fn filesystem_type(path: &Path) -> Option<String> {
+    let path = path.canonicalize().ok()?;
+    let mounts = std::fs::read_to_string("/proc/self/mounts").ok()?;
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().skip(1);
+            Some((fields.next()?, fields.next()?))
+        })
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.len())
+        .map(|(_, fs_type)| fs_type.to_string())
+}
+
+impl RunMeta {
+    fn probe(cache_dir: &Path) -> Self {
+        let git_hash = std::process::Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+        let kernel = std::fs::read_to_string("/proc/sys/kernel/osrelease").ok();
+        let os = match kernel {
+            Some(release) => format!("{} {}", std::env::consts::OS, release.trim()),
+            None => std::env::consts::OS.to_string(),
+        };
+        Self {
+            args: std::env::args().collect(),
+            writer_seed: 0,
+            reader_seeds: (1..=READER_COUNT as u64).collect(),
+            key_count: CACHE_SIZE,
+            value_size: VALUE_SIZE,
+            reader_count: READER_COUNT,
+            reads_per_write: READS_PER_WRITE,
+            page_size: PAGE_SIZE,
+            page_count: PAGE_COUNT,
+            crate_version: env!("CARGO_PKG_VERSION"),
+            git_hash,
+            os,
+            filesystem: filesystem_type(cache_dir),
+            cpu_count: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            start_time: unix_time(),
+            end_time: None,
+        }
+    }
 
-    let write_count = CACHE_SIZE as u64 * 10;
-    let read_count = CACHE_SIZE as u64 * 200;
+    // One `# key: value` line per field, the end time isn't known yet
+    fn write_csv_header(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        let serde_json::Value::Object(fields) = serde_json::to_value(self).unwrap() else {
+            unreachable!("RunMeta is a struct");
+        };
+        for (key, value) in fields.iter().filter(|(_, value)| !value.is_null()) {
+            writeln!(writer, "# {}: {}", key, value)?;
+        }
+        Ok(())
+    }
+
+    // Write run_meta.json next to the trace
+    fn write_json(&self, trace_path: &Path) -> std::io::Result<()> {
+        let path = trace_path.with_file_name("run_meta.json");
+        let json = serde_json::to_string_pretty(self).unwrap();
+        std::fs::write(path, json)
+    }
+}
 
+// One writer and READER_COUNT readers, each reader does READS_PER_WRITE reads for every
+// write. Returns the wall time of the whole workload.
+fn mixed_workload(
+    cache: &Arc<FifoFileCache>,
+    cache_map: &Arc<Cache>,
+    write_count: u64,
+    hit_stats: &Arc<HitStats>,
+    trace_sender: &Option<Sender<OperationTrace>>,
+) -> Duration {
+    let start = Instant::now();
     let write_handle = {
         let cache = cache.clone();
         let cache_map = cache_map.clone();
@@ -206,25 +418,592 @@ fn main() {
         })
     };
 
-    let read_cache = cache.clone();
-    let read_cache_map = cache_map.clone();
+    let read_count = write_count * READS_PER_WRITE;
     let read_handles = (0..READER_COUNT)
-        .map(|_| {
-            let cache = read_cache.clone();
-            let cache_map = read_cache_map.clone();
+        .map(|reader| {
+            let cache = cache.clone();
+            let cache_map = cache_map.clone();
+            let hit_stats = hit_stats.clone();
             let trace_sender = trace_sender.clone();
+            let seed = reader as u64 + 1;
             std::thread::spawn(move || {
-                read_thread(cache, cache_map, read_count, trace_sender);
+                read_thread(cache, cache_map, read_count, seed, hit_stats, trace_sender);
             })
         })
         .collect::<Vec<_>>();
 
     write_handle.join().unwrap();
-    println!("write thread finished");
     for handle in read_handles {
-        println!("read thread finished");
         handle.join().unwrap();
     }
-    trace_sender.send(OperationTrace::Finish).unwrap();
-    trace_handle.join().unwrap();
+    start.elapsed()
+}
+
+fn bench_write(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = new_cache(&dir);
+    let mut rng = WorkloadRng::new(0);
+    let mut group = c.benchmark_group("write");
+    group.throughput(Throughput::Bytes(VALUE_SIZE as u64));
+    group.bench_function("write", |b| {
+        b.iter_batched(
+            || new_value(&mut rng),
+            |value| cache.write(value).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+fn bench_read(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = new_cache(&dir);
+    let cache_map = generate_cache();
+    // Every key gets a value, the cache is large enough to keep them all
+    let mut rng = WorkloadRng::new(0);
+    for item in cache_map.items.values() {
+        item.insert(&cache, new_value(&mut rng));
+    }
+
+    let mut group = c.benchmark_group("read");
+    group.throughput(Throughput::Bytes(VALUE_SIZE as u64));
+    group.bench_function("read", |b| {
+        let mut ops = workload(0.0, 1).ops();
+        b.iter(|| {
+            let Some(Op::Get(key)) = ops.next() else {
+                unreachable!("the workload only reads");
+            };
+            match cache_map.items.get(&key).unwrap().read(&cache) {
+                Lookup::Hit(value, _) => value,
+                _ => panic!("all the values should be cached"),
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_mixed(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = new_cache(&dir);
+    let cache_map = Arc::new(generate_cache());
+    let hit_stats = Arc::new(HitStats::default());
+
+    // The trace is only written when a path is given, it slows the workload down
+    let mut meta = RunMeta::probe(dir.path());
+    let trace = std::env::var_os("STORAGE_BENCH_TRACE").map(|path| {
+        let path = PathBuf::from(path);
+        let (trace_sender, trace_receiver) = channel();
+        let handle = {
+            let (path, meta) = (path.clone(), meta.clone());
+            std::thread::spawn(move || write_trace(path, &meta, trace_receiver))
+        };
+        (trace_sender, handle, path)
+    });
+    let trace_sender = trace.as_ref().map(|(sender, _, _)| sender.clone());
+
+    let mut group = c.benchmark_group("mixed");
+    group.sample_size(10);
+    group.bench_function("1_writer_8_readers", |b| {
+        b.iter_custom(|iters| mixed_workload(&cache, &cache_map, iters, &hit_stats, &trace_sender))
+    });
+    group.finish();
+
+    drop(trace_sender);
+    if let Some((trace_sender, handle, path)) = trace {
+        trace_sender.send(OperationTrace::Finish).unwrap();
+        handle.join().unwrap();
+        meta.end_time = Some(unix_time());
+        meta.write_json(&path).unwrap();
+    }
+
+    hit_stats.print_summary();
+    let stats = cache.stats();
+    println!(
+        "storage object hit ratio: {:.4}, byte hit ratio: {:.4}",
+        stats.object_hit_ratio(),
+        stats.byte_hit_ratio()
+    );
+}
+
+fn bench_switch_tail(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test_switch_tail");
+    let cache = FifoFileCache::new(path, TINY_PAGE_SIZE, TINY_PAGE_SIZE * 1024);
+    let mut latencies = Vec::new();
+    let mut rng = WorkloadRng::new(0);
+    let mut group = c.benchmark_group("switch_tail");
+    group.throughput(Throughput::Bytes(VALUE_SIZE as u64));
+    group.bench_function("write", |b| {
+        b.iter_custom(|iters| {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                let value = new_value(&mut rng);
+                let start = Instant::now();
+                cache.write(value).unwrap();
+                let elapsed = start.elapsed();
+                latencies.push(elapsed);
+                total += elapsed;
+            }
+            total
+        })
+    });
+    group.finish();
+
+    // Filtered out
+    if latencies.is_empty() {
+        return;
+    }
+    latencies.sort();
+    let median = latencies[latencies.len() / 2];
+    let p999 = latencies[latencies.len() * 999 / 1000];
+    let stats = cache.stats();
+    println!(
+        "tiny page writes median: {:?}, p999: {:?}, delayed by switches: {} of {}",
+        median, p999, stats.switch_delayed_writes, stats.writes_total
+    );
+    assert!(
+        p999 <= median * TAIL_FACTOR,
+        "p999 {:?} is more than {}x the median {:?}",
+        p999,
+        TAIL_FACTOR,
+        median
+    );
 }
+
+fn bench_switch_slack(c: &mut Criterion) {
+    let mut group = c.benchmark_group("switch_slack");
+    for (name, slack) in [("on_demand", 0), ("eager", SMALL_VALUE_SIZE)] {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_switch_slack");
+        let cache = FifoFileCache::builder(path, TINY_PAGE_SIZE, TINY_PAGE_SIZE * 1024)
+            .switch_slack(slack)
+            .build();
+        let mut latencies = Vec::new();
+        let mut rng = WorkloadRng::new(0);
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                let mut total = Duration::ZERO;
+                for _ in 0..iters {
+                    let size = match rng.below(2) {
+                        0 => SMALL_VALUE_SIZE,
+                        _ => BIG_VALUE_SIZE,
+                    };
+                    let value = TestValue::generate(size, &mut rng);
+                    let start = Instant::now();
+                    cache.write(value).unwrap();
+                    let elapsed = start.elapsed();
+                    latencies.push(elapsed);
+                    total += elapsed;
+                }
+                total
+            })
+        });
+
+        // Filtered out
+        if latencies.is_empty() {
+            continue;
+        }
+        latencies.sort();
+        let stats = cache.stats();
+        println!(
+            "{} switches, bimodal writes median: {:?}, p99: {:?}, p999: {:?}, eager: {}, on demand: {}",
+            name,
+            latencies[latencies.len() / 2],
+            latencies[latencies.len() * 99 / 100],
+            latencies[latencies.len() * 999 / 1000],
+            stats.eager_switches,
+            stats.on_demand_switches
+        );
+    }
+    group.finish();
+}
+
+// Run `iters` writes split over the writer threads, return the time they took
+fn durable_writes(cache: &FifoFileCache, iters: u64) -> Duration {
+    let per_thread = iters.div_ceil(DURABLE_WRITER_COUNT as u64);
+    let start = Instant::now();
+    std::thread::scope(|s| {
+        for writer in 0..DURABLE_WRITER_COUNT {
+            s.spawn(move || {
+                let mut rng = WorkloadRng::new(writer as u64);
+                for _ in 0..per_thread {
+                    cache.write(new_value(&mut rng)).unwrap();
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn bench_durable_write(c: &mut Criterion) {
+    let modes = [
+        ("every_write", SyncMode::EveryWrite),
+        (
+            "group_commit",
+            SyncMode::GroupCommit {
+                max_batch: DURABLE_WRITER_COUNT,
+                max_delay: Duration::from_millis(1),
+            },
+        ),
+    ];
+    let mut group = c.benchmark_group("durable_write");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(VALUE_SIZE as u64));
+    for (name, sync_mode) in modes {
+        let dir = tempfile::tempdir().unwrap();
+        let page_size = 4096;
+        let cache = FifoFileCache::builder(dir.path().join(name), page_size, page_size * 1024)
+            .sync_mode(sync_mode)
+            .build();
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| durable_writes(&cache, iters))
+        });
+        let stats = cache.stats();
+        println!(
+            "{}: {} syncs for {} writes",
+            name, stats.syncs, stats.writes_total
+        );
+    }
+    group.finish();
+}
+
+// Run `iters` reads split over the reader threads, return the time they took
+fn concurrent_reads(cache: &FifoFileCache, responses: &[WriteResponse], iters: u64) -> Duration {
+    let per_thread = iters.div_ceil(CONCURRENT_READER_COUNT as u64);
+    let start = Instant::now();
+    std::thread::scope(|s| {
+        for reader in 0..CONCURRENT_READER_COUNT {
+            s.spawn(move || {
+                let mut rng = WorkloadRng::new(reader as u64 + 1);
+                for _ in 0..per_thread {
+                    let response = &responses[rng.below(responses.len() as u64) as usize];
+                    let value: Option<TestValue> = cache.read(response).unwrap();
+                    assert!(value.is_some());
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn bench_concurrent_read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("concurrent_read");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(VALUE_SIZE as u64));
+    for (name, seqlock_reads) in [("version", false), ("seqlock", true)] {
+        let dir = tempfile::tempdir().unwrap();
+        let cache =
+            FifoFileCache::builder(dir.path().join(name), PAGE_SIZE, PAGE_SIZE * PAGE_COUNT)
+                .seqlock_reads(seqlock_reads)
+                .build();
+        let mut rng = WorkloadRng::new(0);
+        let responses: Vec<WriteResponse> = (0..CACHE_SIZE)
+            .map(|_| cache.write(new_value(&mut rng)).unwrap())
+            .collect();
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| concurrent_reads(&cache, &responses, iters))
+        });
+    }
+    group.finish();
+}
+
+// Read one large value over and over, deserializing it on every read against sharing
+// it through read_arc while the previous Arc is held
+fn bench_shared_read(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let page_size = LARGE_VALUE_SIZE * 2;
+    let cache = FifoFileCache::new(dir.path().join("shared_read"), page_size, page_size * 4);
+    let value = TestValue::generate(LARGE_VALUE_SIZE, &mut WorkloadRng::new(0));
+    let response = cache.write(value).unwrap();
+
+    let mut group = c.benchmark_group("shared_read");
+    group.throughput(Throughput::Bytes(LARGE_VALUE_SIZE as u64));
+    group.bench_function("read", |b| {
+        b.iter(|| {
+            let value: TestValue = cache.read(&response).unwrap().unwrap();
+            value
+        })
+    });
+    group.bench_function("read_arc", |b| {
+        let mut held: Option<Arc<TestValue>> = None;
+        b.iter(|| {
+            let value: Arc<TestValue> = cache.read_arc(&response).unwrap().unwrap();
+            held.replace(value)
+        })
+    });
+    group.finish();
+}
+
+// Write the dirty pages of the file back and ask the kernel to drop its cached pages, so
+// the next read of the file goes to the disk
+#[cfg(target_os = "linux")]
+fn drop_page_cache(path: &Path) {
+    use std::os::fd::AsRawFd;
+
+    let file = std::fs::File::open(path).unwrap();
+    file.sync_all().unwrap();
+    let ret = unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    assert_eq!(ret, 0, "posix_fadvise failed");
+}
+
+// Random reads of a populated cache, on a warm file and with the page cache dropped
+// before each read. Dropping it isn't timed
+#[cfg(target_os = "linux")]
+fn bench_cold_read(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("cold_read");
+    let cache = FifoFileCache::new(path.clone(), PAGE_SIZE, PAGE_SIZE * PAGE_COUNT);
+    let mut rng = WorkloadRng::new(0);
+    let responses: Vec<WriteResponse> = (0..CACHE_SIZE)
+        .map(|_| cache.write(new_value(&mut rng)).unwrap())
+        .collect();
+
+    let mut group = c.benchmark_group("cold_read");
+    group.throughput(Throughput::Bytes(VALUE_SIZE as u64));
+    for (name, cold) in [("warm", false), ("cold", true)] {
+        let mut rng = WorkloadRng::new(1);
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || {
+                    if cold {
+                        drop_page_cache(&path);
+                    }
+                    &responses[rng.below(responses.len() as u64) as usize]
+                },
+                |response| {
+                    let value: TestValue = cache.read(response).unwrap().unwrap();
+                    value
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bench_cold_read(_c: &mut Criterion) {}
+
+// What the reads of a validation mode returned, a read is either a hit, a miss or a
+// stale record served as the value of its key
+#[derive(Default)]
+struct ValidationCounts {
+    hits: u64,
+    hit_time: Duration,
+    misses: u64,
+    stale_served: u64,
+}
+
+#[derive(Serialize)]
+struct ValidationResult {
+    mode: &'static str,
+    reads: u64,
+    hits: u64,
+    mean_hit_ns: u64,
+    misses: u64,
+    stale_served: u64,
+    // The share of the stale reads that missed instead of serving the wrong value, only
+    // stale records miss in this bench
+    stale_detection: f64,
+}
+
+impl ValidationResult {
+    fn new(mode: &'static str, counts: &ValidationCounts) -> Self {
+        let stale = counts.misses + counts.stale_served;
+        Self {
+            mode,
+            reads: counts.hits + stale,
+            hits: counts.hits,
+            mean_hit_ns: (counts.hit_time.as_nanos() / counts.hits.max(1) as u128) as u64,
+            misses: counts.misses,
+            stale_served: counts.stale_served,
+            stale_detection: counts.misses as f64 / stale.max(1) as f64,
+        }
+    }
+}
+
+// The latest record of each key with the checksum of its value
+type Keys = Vec<RwLock<(WriteResponse, u32)>>;
+
+// Overwrite random keys until `stop` is set
+fn validation_writer(cache: &FifoFileCache, keys: &Keys, stop: &AtomicBool) {
+    let mut rng = WorkloadRng::new(0);
+    while !stop.load(Ordering::Relaxed) {
+        let key = rng.below(keys.len() as u64) as usize;
+        let value = new_value(&mut rng);
+        let check_sum = value.check_sum;
+        let response = cache.write(value).unwrap();
+        *keys[key].write().unwrap() = (response, check_sum);
+    }
+}
+
+// Read `iters` random keys while a writer recycles pages, return the time the reads took
+fn validation_reads(
+    cache: &FifoFileCache,
+    keys: &Keys,
+    rng: &mut WorkloadRng,
+    iters: u64,
+    counts: &mut ValidationCounts,
+) -> Duration {
+    let stop = AtomicBool::new(false);
+    std::thread::scope(|s| {
+        s.spawn(|| validation_writer(cache, keys, &stop));
+        let mut total = Duration::ZERO;
+        for _ in 0..iters {
+            let key = rng.below(keys.len() as u64) as usize;
+            let (response, check_sum) = keys[key].read().unwrap().clone();
+            let start = Instant::now();
+            let result: Result<Option<TestValue>, _> = cache.read(&response);
+            let elapsed = start.elapsed();
+            total += elapsed;
+            match result {
+                Ok(Some(value))
+                    if value.check_sum == check_sum
+                        && crc32fast::hash(&value.value) == check_sum =>
+                {
+                    counts.hits += 1;
+                    counts.hit_time += elapsed;
+                }
+                Ok(Some(_)) => counts.stale_served += 1,
+                // Bytes that aren't a whole record may fail to deserialize
+                Ok(None) | Err(_) => counts.misses += 1,
+            }
+        }
+        stop.store(true, Ordering::Relaxed);
+        total
+    })
+}
+
+fn bench_validation(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let mut meta = RunMeta::probe(dir.path());
+    let mut results = Vec::new();
+    let mut group = c.benchmark_group("validation");
+    group.throughput(Throughput::Bytes(VALUE_SIZE as u64));
+    let modes = [
+        ("version", true, false),
+        ("checksum", false, true),
+        ("both", true, true),
+    ];
+    for (mode, version_check, checksum) in modes {
+        let mut builder = FifoFileCache::builder(
+            dir.path().join(mode),
+            PAGE_SIZE,
+            PAGE_SIZE * VALIDATION_PAGE_COUNT,
+        )
+        .version_check(version_check);
+        if checksum {
+            builder = builder.checksum(Crc32);
+        }
+        let cache = builder.build();
+        let mut rng = WorkloadRng::new(0);
+        let keys: Keys = (0..VALIDATION_KEY_COUNT)
+            .map(|_| {
+                let value = new_value(&mut rng);
+                let check_sum = value.check_sum;
+                RwLock::new((cache.write(value).unwrap(), check_sum))
+            })
+            .collect();
+
+        let mut counts = ValidationCounts::default();
+        let mut rng = WorkloadRng::new(1);
+        group.bench_function(mode, |b| {
+            b.iter_custom(|iters| validation_reads(&cache, &keys, &mut rng, iters, &mut counts))
+        });
+        results.push(ValidationResult::new(mode, &counts));
+    }
+    group.finish();
+
+    println!(
+        "{:<10} {:>10} {:>12} {:>12} {:>15}",
+        "mode", "reads", "mean hit ns", "stale served", "stale detection"
+    );
+    for result in &results {
+        println!(
+            "{:<10} {:>10} {:>12} {:>12} {:>15.4}",
+            result.mode,
+            result.reads,
+            result.mean_hit_ns,
+            result.stale_served,
+            result.stale_detection
+        );
+    }
+    // Written next to the trace, with the parameters of the run
+    if let Some(path) = std::env::var_os("STORAGE_BENCH_TRACE") {
+        meta.end_time = Some(unix_time());
+        let summary = serde_json::json!({ "meta": meta, "validation": results });
+        let path = PathBuf::from(path).with_file_name("validation.json");
+        std::fs::write(path, serde_json::to_string_pretty(&summary).unwrap()).unwrap();
+    }
+}
+
+// The write syscalls of the process so far, from /proc/self/io
+#[cfg(target_os = "linux")]
+fn write_syscalls() -> u64 {
+    let io = std::fs::read_to_string("/proc/self/io").unwrap();
+    io.lines()
+        .find_map(|line| line.strip_prefix("syscw: "))
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap()
+}
+
+// Write for one second at PACED_WRITES_PER_SEC, return the write syscalls per write
+#[cfg(target_os = "linux")]
+fn paced_write_syscalls(cache: &FifoFileCache, rng: &mut WorkloadRng) -> f64 {
+    let interval = Duration::from_secs(1) / PACED_WRITES_PER_SEC as u32;
+    let start = Instant::now();
+    let before = write_syscalls();
+    for i in 0..PACED_WRITES_PER_SEC {
+        cache.write(new_value(rng)).unwrap();
+        if let Some(wait) =
+            (start + interval * (i as u32 + 1)).checked_duration_since(Instant::now())
+        {
+            std::thread::sleep(wait);
+        }
+    }
+    (write_syscalls() - before) as f64 / PACED_WRITES_PER_SEC as f64
+}
+
+fn bench_buffered_write(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let mut group = c.benchmark_group("buffered_write");
+    group.throughput(Throughput::Bytes(VALUE_SIZE as u64));
+    for (name, write_buffer) in [("unbuffered", 0), ("buffered", PAGE_SIZE)] {
+        let cache =
+            FifoFileCache::builder(dir.path().join(name), PAGE_SIZE, PAGE_SIZE * PAGE_COUNT)
+                .write_buffer(write_buffer)
+                .build();
+        let mut rng = WorkloadRng::new(0);
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || new_value(&mut rng),
+                |value| cache.write(value).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+        #[cfg(target_os = "linux")]
+        println!(
+            "{}: {:.3} write syscalls per write at {} writes/s",
+            name,
+            paced_write_syscalls(&cache, &mut rng),
+            PACED_WRITES_PER_SEC
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_write,
+    bench_read,
+    bench_mixed,
+    bench_switch_tail,
+    bench_switch_slack,
+    bench_durable_write,
+    bench_concurrent_read,
+    bench_shared_read,
+    bench_cold_read,
+    bench_validation,
+    bench_buffered_write
+);
+criterion_main!(benches);
@@ -0,0 +1,175 @@
+// Summarize the operation trace of the mixed workload bench, the csv written to the path
+// in STORAGE_BENCH_TRACE:
+//
+//     cargo run -p storage --example analyze_trace -- trace.csv
+//
+// It prints the count and the mean, p50 and p99 duration of each operation type, the
+// reads per write and the pages accessed the most. Only the hits of the readers are in
+// the trace, a miss isn't recorded.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use serde::Deserialize;
+
+// The pages listed in TraceSummary::hottest_pages
+const HOTTEST_PAGES: usize = 5;
+
+// A row of the trace, the duration is in microseconds
+#[derive(Deserialize)]
+struct Trace {
+    operation_type: String,
+    page_id: u64,
+    duration: u64,
+}
+
+#[derive(Debug, PartialEq)]
+struct OperationSummary {
+    count: usize,
+    mean_us: f64,
+    p50_us: u64,
+    p99_us: u64,
+}
+
+#[derive(Debug, PartialEq)]
+struct TraceSummary {
+    // By operation type, "read" and "write" for the bench
+    operations: BTreeMap<String, OperationSummary>,
+    // None without writes
+    reads_per_write: Option<f64>,
+    // The page ids with their access count, the most accessed first
+    hottest_pages: Vec<(u64, usize)>,
+}
+
+// The nearest rank percentile of sorted durations
+fn percentile(sorted: &[u64], percent: usize) -> u64 {
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+fn analyze_trace(path: &Path) -> Result<TraceSummary, csv::Error> {
+    // The parameters of the run are comment lines at the top
+    let mut reader = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .from_path(path)?;
+    let mut durations: HashMap<String, Vec<u64>> = HashMap::new();
+    let mut page_accesses: HashMap<u64, usize> = HashMap::new();
+    for row in reader.deserialize() {
+        let trace: Trace = row?;
+        durations
+            .entry(trace.operation_type)
+            .or_default()
+            .push(trace.duration);
+        *page_accesses.entry(trace.page_id).or_default() += 1;
+    }
+
+    let operations: BTreeMap<_, _> = durations
+        .into_iter()
+        .map(|(operation, mut durations)| {
+            durations.sort_unstable();
+            let summary = OperationSummary {
+                count: durations.len(),
+                mean_us: durations.iter().sum::<u64>() as f64 / durations.len() as f64,
+                p50_us: percentile(&durations, 50),
+                p99_us: percentile(&durations, 99),
+            };
+            (operation, summary)
+        })
+        .collect();
+    let count = |operation: &str| operations.get(operation).map_or(0, |s| s.count);
+    let reads_per_write = match count("write") {
+        0 => None,
+        writes => Some(count("read") as f64 / writes as f64),
+    };
+    let mut hottest_pages: Vec<_> = page_accesses.into_iter().collect();
+    hottest_pages
+        .sort_unstable_by_key(|&(page_id, accesses)| (std::cmp::Reverse(accesses), page_id));
+    hottest_pages.truncate(HOTTEST_PAGES);
+    Ok(TraceSummary {
+        operations,
+        reads_per_write,
+        hottest_pages,
+    })
+}
+
+fn main() {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("usage: analyze_trace <trace.csv>");
+        std::process::exit(2);
+    };
+    let summary = match analyze_trace(Path::new(&path)) {
+        Ok(summary) => summary,
+        Err(e) => {
+            eprintln!("failed to read {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    println!("operation  count      mean_us    p50_us  p99_us");
+    for (operation, s) in &summary.operations {
+        println!(
+            "{:<10} {:<10} {:<10.1} {:<7} {}",
+            operation, s.count, s.mean_us, s.p50_us, s.p99_us
+        );
+    }
+    match summary.reads_per_write {
+        Some(ratio) => println!("reads per write: {:.2}", ratio),
+        None => println!("reads per write: no writes"),
+    }
+    println!("hottest pages:");
+    for (page_id, accesses) in &summary.hottest_pages {
+        println!("  page {}: {} accesses", page_id, accesses);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{analyze_trace, OperationSummary, TraceSummary};
+
+    #[test]
+    fn test_analyze_trace() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.csv");
+        let mut csv = String::from("# page_size: 4096\n");
+        csv.push_str("operation_type,page_id,page_offset,version,duration\n");
+        // 100 reads of 1 to 100us over pages 0 to 3, 4 writes to page 3
+        for i in 0..100 {
+            csv.push_str(&format!("read,{},0,1,{}\n", i % 4, i + 1));
+        }
+        for duration in [10, 20, 30, 40] {
+            csv.push_str(&format!("write,3,0,1,{}\n", duration));
+        }
+        std::fs::write(&path, csv).unwrap();
+
+        let summary = analyze_trace(&path).unwrap();
+        let operations = BTreeMap::from([
+            (
+                "read".to_string(),
+                OperationSummary {
+                    count: 100,
+                    mean_us: 50.5,
+                    p50_us: 50,
+                    p99_us: 99,
+                },
+            ),
+            (
+                "write".to_string(),
+                OperationSummary {
+                    count: 4,
+                    mean_us: 25.0,
+                    p50_us: 20,
+                    p99_us: 40,
+                },
+            ),
+        ]);
+        assert_eq!(
+            summary,
+            TraceSummary {
+                operations,
+                reads_per_write: Some(25.0),
+                hottest_pages: vec![(3, 29), (0, 25), (1, 25), (2, 25)],
+            }
+        );
+    }
+}
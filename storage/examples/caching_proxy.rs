@@ -0,0 +1,199 @@
+// A tiny HTTP caching layer over the cache, one thread per connection on std sockets:
+//
+//     GET /k/{key}     the cached value, on a miss the content is generated, cached and served
+//     PUT /k/{key}     cache the request body under the key
+//     DELETE /k/{key}  forget the key, its record stays in the file until its page is recycled
+//     GET /stats       the counters of the cache as JSON
+//
+// The cache has no keys, the key of each record is kept in a map next to it. There is no
+// TTL either, an entry lives until the writer recycles its page. The generated content
+// stands in for an upstream server.
+//
+//     cargo run -p storage --example caching_proxy [-- --demo]
+//
+// With --demo it sends a few requests to itself, prints the responses and exits.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use storage::{FifoFileCache, Storage, WriteResponse};
+
+const PAGE_SIZE: usize = 64 * 1024;
+const PAGE_COUNT: usize = 64;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Body(Vec<u8>);
+
+impl storage::Value for Body {}
+
+struct Proxy {
+    cache: FifoFileCache,
+    keys: Mutex<HashMap<String, WriteResponse>>,
+}
+
+impl Proxy {
+    fn get(&self, key: &str) -> (u16, Vec<u8>) {
+        let cached = self.keys.lock().unwrap().get(key).cloned();
+        if let Some(response) = cached {
+            match self.cache.read(&response) {
+                Ok(Some(Body(value))) => return (200, value),
+                Ok(None) => {}
+                Err(e) => return (500, e.to_string().into_bytes()),
+            }
+        }
+        // Missed or evicted, fetch it "upstream"
+        let value = format!("generated content of {}\n", key).into_bytes();
+        self.put(key, value.clone());
+        (200, value)
+    }
+
+    fn put(&self, key: &str, value: Vec<u8>) -> (u16, Vec<u8>) {
+        match self.cache.write(Body(value)) {
+            Ok(response) => {
+                self.keys.lock().unwrap().insert(key.to_string(), response);
+                (204, Vec::new())
+            }
+            Err(e) => (500, e.to_string().into_bytes()),
+        }
+    }
+
+    fn delete(&self, key: &str) -> (u16, Vec<u8>) {
+        match self.keys.lock().unwrap().remove(key) {
+            Some(_) => (204, Vec::new()),
+            None => (404, Vec::new()),
+        }
+    }
+
+    fn handle(&self, method: &str, path: &str, body: Vec<u8>) -> (u16, Vec<u8>) {
+        if (method, path) == ("GET", "/stats") {
+            return (200, self.cache.stats().to_json().into_bytes());
+        }
+        let Some(key) = path.strip_prefix("/k/").filter(|key| !key.is_empty()) else {
+            return (404, Vec::new());
+        };
+        match method {
+            "GET" => self.get(key),
+            "PUT" => self.put(key, body),
+            "DELETE" => self.delete(key),
+            _ => (405, Vec::new()),
+        }
+    }
+}
+
+// One request per connection, the body is read up to its Content-Length
+fn serve(proxy: &Proxy, stream: TcpStream) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let (status, body) = proxy.handle(method, path, body);
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason(status),
+        body.len()
+    )?;
+    stream.write_all(&body)
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    }
+}
+
+// Send a request and return the status line and the body
+fn request(address: &str, method: &str, path: &str, body: &[u8]) -> (String, String) {
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\n\r\n",
+        method,
+        path,
+        address,
+        body.len()
+    )
+    .unwrap();
+    stream.write_all(body).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let status = head.lines().next().unwrap_or("").to_string();
+    (status, body.to_string())
+}
+
+fn main() {
+    let demo = std::env::args().any(|arg| arg == "--demo");
+    let dir = tempfile::tempdir().unwrap();
+    let proxy = Arc::new(Proxy {
+        cache: FifoFileCache::new(
+            dir.path().join("caching_proxy"),
+            PAGE_SIZE,
+            PAGE_SIZE * PAGE_COUNT,
+        ),
+        keys: Mutex::default(),
+    });
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    println!("listening on http://{}", address);
+
+    let server = {
+        let proxy = proxy.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { continue };
+                let proxy = proxy.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = serve(&proxy, stream) {
+                        eprintln!("connection failed: {}", e);
+                    }
+                });
+            }
+        })
+    };
+    if !demo {
+        server.join().unwrap();
+        return;
+    }
+
+    let steps: [(&str, &str, &[u8]); 6] = [
+        ("GET", "/k/alpha", b""),
+        ("PUT", "/k/beta", b"hello proxy"),
+        ("GET", "/k/beta", b""),
+        ("DELETE", "/k/beta", b""),
+        ("DELETE", "/k/beta", b""),
+        ("GET", "/stats", b""),
+    ];
+    for (method, path, body) in steps {
+        let (status, body) = request(&address, method, path, body);
+        println!("{} {} -> {} {}", method, path, status, body.trim_end());
+    }
+}
@@ -0,0 +1,45 @@
+// Reads and writes through a tower middleware stack, here a timeout on every call.
+//
+//     cargo run -p storage --example tower_service --features tower
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use storage::{CacheRequest, CacheResponse, CacheService, FifoFileCache, Value};
+use tower::timeout::TimeoutLayer;
+use tower::{ServiceBuilder, ServiceExt};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Greeting(String);
+
+impl Value for Greeting {}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = Arc::new(FifoFileCache::new(
+        dir.path().join("tower_service"),
+        4096,
+        4096 * 16,
+    ));
+    let service = ServiceBuilder::new()
+        .layer(TimeoutLayer::new(Duration::from_millis(5)))
+        .service(CacheService::new(cache));
+
+    let written = service
+        .clone()
+        .oneshot(CacheRequest::Write(Greeting("hello tower".to_string())))
+        .await
+        .expect("write failed or timed out");
+    let CacheResponse::WriteResult(response) = written else {
+        unreachable!()
+    };
+    println!("written: {:?}", response);
+
+    match service.oneshot(CacheRequest::Read(response)).await {
+        Ok(CacheResponse::ReadResult(value)) => println!("read: {:?}", value),
+        Ok(CacheResponse::WriteResult(_)) => unreachable!(),
+        Err(e) => println!("read failed or timed out: {}", e),
+    }
+}
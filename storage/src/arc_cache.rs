@@ -0,0 +1,112 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+
+use crate::{FifoFileCache, PageID, PageOffset, StorageError, Value, WriteResponse};
+
+type ArcKey = (PageID, PageOffset, u64);
+
+// The values handed out by `read_arc` that are still alive somewhere. Dead entries are
+// swept whenever the map has doubled since the last sweep.
+#[derive(Default)]
+pub(crate) struct ArcCache {
+    values: HashMap<ArcKey, Weak<dyn Any + Send + Sync>>,
+    sweep_at: usize,
+}
+
+impl ArcCache {
+    fn get<V: Send + Sync + 'static>(&self, key: &ArcKey) -> Option<Arc<V>> {
+        let value = self.values.get(key)?.upgrade()?;
+        value.downcast().ok()
+    }
+
+    // Return the value already shared by a concurrent reader if there's one
+    fn insert<V: Send + Sync + 'static>(&mut self, key: ArcKey, value: Arc<V>) -> Arc<V> {
+        if let Some(shared) = self.get(&key) {
+            return shared;
+        }
+        let erased: Arc<dyn Any + Send + Sync> = value.clone();
+        self.values.insert(key, Arc::downgrade(&erased));
+        if self.values.len() > self.sweep_at {
+            self.values.retain(|_, value| value.strong_count() > 0);
+            self.sweep_at = (self.values.len() * 2).max(64);
+        }
+        value
+    }
+}
+
+impl FifoFileCache {
+    /// Read a value shared with the other readers of the same record.
+    ///
+    /// As long as an `Arc` returned for a record is alive, reading the record again
+    /// returns the same `Arc` without deserializing (the page version is still checked).
+    /// Once every `Arc` is dropped the next read deserializes again.
+    pub fn read_arc<V>(&self, request: &WriteResponse) -> Result<Option<Arc<V>>, StorageError>
+    where
+        V: Value + Send + Sync + 'static,
+    {
+        let key = (request.page_id, request.page_offset, request.version);
+        let shared = self.arc_cache.lock().unwrap().get::<V>(&key);
+        if let Some(value) = shared {
+            let version =
+                self.pages[request.page_id as usize].load(std::sync::atomic::Ordering::Relaxed);
+            if version != request.version {
+                self.stats.record_miss(request.length);
+                return Ok(None);
+            }
+            self.stats.record_hit(request.length);
+            self.record_access(request.page_id);
+            return Ok(Some(value));
+        }
+
+        let Some(buffer) = self.read_record(request)? else {
+            return Ok(None);
+        };
+        let value: V = crate::value::deserialize(&buffer)?;
+        let value = self.arc_cache.lock().unwrap().insert(key, Arc::new(value));
+        Ok(Some(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Barrier};
+
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage};
+
+    #[test]
+    fn test_read_arc_shared() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_read_arc_shared"), 8, 8 * 2);
+        let response = cache.write(TestValue::from(7)).unwrap();
+
+        let barrier = Barrier::new(2);
+        let (first, second): (Arc<TestValue>, Arc<TestValue>) = std::thread::scope(|s| {
+            let read = || {
+                barrier.wait();
+                cache.read_arc(&response).unwrap().unwrap()
+            };
+            let first = s.spawn(read);
+            let second = s.spawn(read);
+            (first.join().unwrap(), second.join().unwrap())
+        });
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(first.value, 7);
+
+        // Once all the Arcs are gone the record is deserialized and shared afresh
+        drop(first);
+        drop(second);
+        let third: Arc<TestValue> = cache.read_arc(&response).unwrap().unwrap();
+        let fourth: Arc<TestValue> = cache.read_arc(&response).unwrap().unwrap();
+        assert!(Arc::ptr_eq(&third, &fourth));
+        assert_eq!(third.value, 7);
+
+        // A live Arc doesn't keep a recycled record readable
+        cache.write(TestValue::from(8)).unwrap();
+        cache.write(TestValue::from(9)).unwrap();
+        assert!(cache.read_arc::<TestValue>(&response).unwrap().is_none());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{FifoFileCache, StorageError, WriteResponse};
+
+impl FifoFileCache {
+    // Append the serialized values under a single lock acquisition. On error the records
+    // before the failing one may have been written.
+    pub(crate) fn write_records(
+        &self,
+        records: Vec<Vec<u8>>,
+    ) -> Result<Vec<WriteResponse>, StorageError> {
+        let values = self.write_through.as_ref().map(|_| records.clone());
+        let records = records
+            .into_iter()
+            .map(|data| self.encode_record(data))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut manager = self.lock_manager();
+        let responses = records
+            .into_iter()
+            .map(|data| self.append_record(&mut manager, data))
+            .collect();
+        let responses: Vec<WriteResponse> = self.finish_write(manager, responses)?;
+        if let Some(values) = values {
+            // All of them are handed to the store, the first failure is returned
+            let mut result = Ok(());
+            for (response, value) in responses.iter().zip(values) {
+                result = result.and(self.write_through(response, value));
+            }
+            result?;
+        }
+        Ok(responses)
+    }
+
+    // Read the records sorted by their position in the file, a run of records that
+    // follow each other on the same page is read at once. The results are in the order
+    // of `requests`.
+    pub(crate) fn read_records(
+        &self,
+        requests: &[WriteResponse],
+    ) -> Result<Vec<Option<Vec<u8>>>, StorageError> {
+        let mut order = Vec::with_capacity(requests.len());
+        for (i, request) in requests.iter().enumerate() {
+            if self.check_request(request)? {
+                order.push(i);
+            }
+        }
+        order.sort_by_key(|&i| (requests[i].page_id, requests[i].page_offset));
+        let mut results = vec![None; requests.len()];
+
+        let mut run_start = 0;
+        while run_start < order.len() {
+            let first = &requests[order[run_start]];
+            let mut run_end = run_start + 1;
+            let mut end = first.page_offset + first.length as u64;
+            while let Some(&i) = order.get(run_end) {
+                let next = &requests[i];
+                if next.page_id != first.page_id
+                    || next.version != first.version
+                    || next.page_offset != end
+                {
+                    break;
+                }
+                end += next.length as u64;
+                run_end += 1;
+            }
+
+            let mut buffer = vec![0; (end - first.page_offset) as usize];
+            self.read_exact_at(&mut buffer, first.page_id, first.page_offset)?;
+            for &i in &order[run_start..run_end] {
+                let request = &requests[i];
+                let start = (request.page_offset - first.page_offset) as usize;
+                let record = buffer[start..start + request.length].to_vec();
+                results[i] = self.verify_record(request, record)?;
+            }
+            run_start = run_end;
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage, WriteResponse};
+
+    // Generic code gets the batched path of the storage it's given
+    fn round_trip<S: Storage<TestValue>>(storage: &S, values: Vec<u64>) -> Vec<WriteResponse>
+    where
+        S::Error: std::fmt::Debug,
+    {
+        let values = values.into_iter().map(TestValue::from).collect();
+        storage.write_many(values).unwrap()
+    }
+
+    #[test]
+    fn test_write_read_many() {
+        let dir = tempdir().unwrap();
+        // 4 pages of 3 values
+        let cache = FifoFileCache::new(dir.path().join("test_write_read_many"), 24, 24 * 4);
+        let responses = round_trip(&cache, (0..9).collect());
+        assert_eq!(responses.len(), 9);
+        assert_eq!(cache.stats().writes_total, 9);
+        for (i, response) in responses.iter().enumerate() {
+            assert_eq!(response.page_id, i as u64 / 3);
+            assert_eq!(response.page_offset, i as u64 % 3 * 8);
+        }
+
+        // Out of order and spanning pages, with a duplicate
+        let order = [5, 0, 3, 4, 1, 8, 2, 7, 6, 4];
+        let requests: Vec<WriteResponse> = order.iter().map(|&i| responses[i].clone()).collect();
+        let values: Vec<Option<TestValue>> = cache.read_many(&requests).unwrap();
+        for (value, &i) in values.iter().zip(&order) {
+            assert_eq!(value.as_ref().unwrap().value, i as u64);
+        }
+
+        // Recycling page 0 turns its records into misses
+        round_trip(&cache, (9..13).collect());
+        let values: Vec<Option<TestValue>> = cache.read_many(&responses[..6]).unwrap();
+        assert!(values[..3].iter().all(Option::is_none));
+        assert!(values[3..].iter().all(Option::is_some));
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use storage::workload::{KeyDistribution, Op, SizeDistribution, WorkloadRng, WorkloadSpec};
+use storage::{Crc32, FifoFileCache, Storage, WriteResponse};
+
+// A long running torture test of the cache:
+// It writes keyed values whose payload is derived from (key, sequence), reads random
+// keys back and checks them against an oracle of the last write of each key. A hit
+// must return exactly the value written, and a miss is a violation when the entry is
+// definitely still live. The cache is dropped and reopened from the same file every
+// `--restart-every` operations.
+//
+// The cache has no recovery yet, a reopened cache starts empty, so the oracle is
+// cleared on restart. A violation prints the diagnostics and exits with status 1.
+//
+// Usage: cargo run -p storage --bin soak -- [--hours H] [--seconds S] [--path P]
+//        [--restart-every N] [--seed S]
+
+const PAGE_SIZE: usize = 64 * 1024;
+const PAGE_COUNT: usize = 64;
+const KEY_COUNT: u64 = 50_000;
+const MAX_PAYLOAD: usize = 4096;
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct SoakValue {
+    key: u64,
+    sequence: u64,
+    payload: Vec<u8>,
+}
+
+impl storage::Value for SoakValue {}
+
+impl SoakValue {
+    // The payload only depends on the key, the sequence and the size, so a read can be
+    // checked without keeping the values around
+    fn new(key: u64, sequence: u64, size: usize) -> Self {
+        let mut rng = WorkloadRng::new(key ^ sequence.rotate_left(32));
+        let payload = (0..size).map(|_| rng.next_u64() as u8).collect();
+        Self {
+            key,
+            sequence,
+            payload,
+        }
+    }
+}
+
+struct Args {
+    duration: Duration,
+    path: PathBuf,
+    restart_every: u64,
+    seed: u64,
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        duration: Duration::from_secs(60),
+        path: std::env::temp_dir().join(format!("cache-soak-{}", process::id())),
+        restart_every: 1_000_000,
+        seed: 0x5eed,
+    };
+    let mut iter = std::env::args().skip(1);
+    while let Some(flag) = iter.next() {
+        let value = iter.next().unwrap_or_else(|| usage(&flag));
+        match flag.as_str() {
+            "--hours" => {
+                let hours: f64 = value.parse().unwrap_or_else(|_| usage(&flag));
+                args.duration = Duration::from_secs_f64(hours * 3600.0);
+            }
+            "--seconds" => {
+                let seconds: f64 = value.parse().unwrap_or_else(|_| usage(&flag));
+                args.duration = Duration::from_secs_f64(seconds);
+            }
+            "--path" => args.path = PathBuf::from(value),
+            "--restart-every" => {
+                args.restart_every = value.parse().unwrap_or_else(|_| usage(&flag));
+            }
+            "--seed" => args.seed = value.parse().unwrap_or_else(|_| usage(&flag)),
+            _ => usage(&flag),
+        }
+    }
+    args
+}
+
+fn usage(flag: &str) -> ! {
+    eprintln!("invalid argument {}", flag);
+    eprintln!("usage: soak [--hours H] [--seconds S] [--path P] [--restart-every N] [--seed S]");
+    process::exit(2);
+}
+
+fn open(path: &Path) -> FifoFileCache {
+    FifoFileCache::builder(path.to_path_buf(), PAGE_SIZE, PAGE_SIZE * PAGE_COUNT)
+        .checksum(Crc32)
+        .build()
+}
+
+struct Entry {
+    response: WriteResponse,
+    sequence: u64,
+    size: usize,
+    // The write count when it was written
+    written_at: u64,
+}
+
+struct Soak {
+    cache: FifoFileCache,
+    oracle: HashMap<u64, Entry>,
+    writes: u64,
+    reads: u64,
+    restarts: u64,
+    // The number of writes before which an entry can't be recycled. Each page holds at
+    // least PAGE_SIZE / max_record records, and an entry is only recycled once all the
+    // other pages were filled.
+    live_window: u64,
+}
+
+impl Soak {
+    fn violation(&self, key: u64, message: String) -> ! {
+        eprintln!("invariant violated for key {}: {}", key, message);
+        if let Some(entry) = self.oracle.get(&key) {
+            eprintln!(
+                "  oracle: {:?} sequence {} written at write {} ({} writes ago)",
+                entry.response,
+                entry.sequence,
+                entry.written_at,
+                self.writes - entry.written_at
+            );
+        }
+        eprintln!(
+            "  writes {} reads {} restarts {}",
+            self.writes, self.reads, self.restarts
+        );
+        eprintln!("  {:?}", self.cache.snapshot());
+        process::exit(1);
+    }
+
+    fn write(&mut self, key: u64, size: usize) {
+        let sequence = self.oracle.get(&key).map_or(0, |entry| entry.sequence + 1);
+        let response = self
+            .cache
+            .write(SoakValue::new(key, sequence, size))
+            .unwrap_or_else(|e| self.violation(key, format!("write failed: {}", e)));
+        let entry = Entry {
+            response,
+            sequence,
+            size,
+            written_at: self.writes,
+        };
+        self.oracle.insert(key, entry);
+        self.writes += 1;
+    }
+
+    fn read(&mut self, key: u64) {
+        let Some(entry) = self.oracle.get(&key) else {
+            return;
+        };
+        self.reads += 1;
+        let value: Option<SoakValue> = self
+            .cache
+            .read(&entry.response)
+            .unwrap_or_else(|e| self.violation(key, format!("read failed: {}", e)));
+        match value {
+            Some(value) => {
+                if value != SoakValue::new(key, entry.sequence, entry.size) {
+                    self.violation(
+                        key,
+                        format!(
+                            "read key {} sequence {} with {} bytes",
+                            value.key,
+                            value.sequence,
+                            value.payload.len()
+                        ),
+                    );
+                }
+            }
+            None if self.writes - entry.written_at < self.live_window => {
+                self.violation(key, "miss of a live entry".to_string());
+            }
+            None => {
+                self.oracle.remove(&key);
+            }
+        }
+    }
+
+    fn restart(&mut self, path: &Path) {
+        self.cache = open(path);
+        self.oracle.clear();
+        self.restarts += 1;
+    }
+}
+
+fn main() {
+    let args = parse_args();
+    // The payload, its length prefix, the key and sequence, and the checksum
+    let max_record = MAX_PAYLOAD + 8 * 3 + 4;
+    let mut soak = Soak {
+        cache: open(&args.path),
+        oracle: HashMap::new(),
+        writes: 0,
+        reads: 0,
+        restarts: 0,
+        live_window: ((PAGE_COUNT - 2) * (PAGE_SIZE / max_record)) as u64,
+    };
+    let spec = WorkloadSpec {
+        key_count: KEY_COUNT,
+        key_dist: KeyDistribution::Uniform,
+        value_size_dist: SizeDistribution::Uniform {
+            min: 0,
+            max: MAX_PAYLOAD,
+        },
+        write_ratio: 0.25,
+        seed: args.seed,
+    };
+    let mut ops = spec.ops();
+    let start = Instant::now();
+    let mut last_report = start;
+    let mut operations = 0u64;
+    println!(
+        "soak test on {} for {:?}",
+        args.path.display(),
+        args.duration
+    );
+
+    while start.elapsed() < args.duration {
+        for op in ops.by_ref().take(1000) {
+            match op {
+                Op::Set(key, size) => soak.write(key, size),
+                Op::Get(key) => soak.read(key),
+            }
+            operations += 1;
+            if operations.is_multiple_of(args.restart_every) {
+                soak.restart(&args.path);
+            }
+        }
+        if last_report.elapsed() >= REPORT_INTERVAL {
+            last_report = Instant::now();
+            let snapshot = soak.cache.snapshot();
+            println!(
+                "{:>8.0}s writes {} reads {} restarts {} hit ratio {:.3} tracked {} retired pages {:?} cursor {}:{}",
+                start.elapsed().as_secs_f64(),
+                soak.writes,
+                soak.reads,
+                soak.restarts,
+                snapshot.stats.object_hit_ratio(),
+                soak.oracle.len(),
+                snapshot.stats.retired_pages,
+                snapshot.cursor.page_id,
+                snapshot.cursor.page_offset
+            );
+        }
+    }
+    println!(
+        "soak test passed: writes {} reads {} restarts {}",
+        soak.writes, soak.reads, soak.restarts
+    );
+    let _ = std::fs::remove_file(&args.path);
+}
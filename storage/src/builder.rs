@@ -0,0 +1,613 @@
+use std::fs::{File, OpenOptions};
+use std::io::BufWriter;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::deadline::DeadlineReader;
+use crate::dedup::DedupIndex;
+use crate::directory::EntryDirectory;
+use crate::eviction::{EvictionCallback, EvictionCapture};
+use crate::frequency::AccessFrequencyTable;
+use crate::history::HistoryLog;
+use crate::prefetch::Prefetcher;
+use crate::readers::ReaderRegistry;
+use crate::recovery::RecoveredExtents;
+use crate::retire::PageHealth;
+use crate::seqlock::SeqLockPage;
+use crate::stats::CacheStats;
+use crate::sync::Syncer;
+use crate::task::TaskGroup;
+use crate::transform::TransformChain;
+use crate::version_table::VersionTableWriter;
+use crate::watch::EvictionWatchers;
+use crate::write_through::WriteThroughSink;
+use crate::{
+    predictor, throughput, BufferedRange, Checksum, EvictedEntry, FifoFileCache, Fnv1a,
+    HitRatePredictor, IoPriority, KeyHasher, PageID, PageOffset, PageVersion, RecordTransform,
+    SyncMode, WriteManger, WriteThrough, WriteThroughMode,
+};
+
+pub struct FifoFileCacheBuilder {
+    path: PathBuf,
+    page_size: usize,
+    capacity: usize,
+    io_priority: IoPriority,
+    checksum: Option<Box<dyn Checksum>>,
+    persist_versions: bool,
+    sync_mode: SyncMode,
+    throughput_window: Duration,
+    history_retention: Option<usize>,
+    hit_rate_alpha: f64,
+    key_hasher: Box<dyn KeyHasher>,
+    dedup: bool,
+    zero_fill_on_eviction: bool,
+    file_mode: Option<u32>,
+    ephemeral: bool,
+    seqlock_reads: bool,
+    version_check: bool,
+    write_buffer: usize,
+    // The callback, and whether it gets the bytes of the records
+    on_eviction: Option<(EvictionCallback, bool)>,
+    transforms: TransformChain,
+    // The page versions and the write cursor to start from, instead of a fresh file
+    initial_state: Option<(Vec<u64>, (PageID, PageOffset))>,
+    retire_after_errors: Option<u32>,
+    switch_slack: usize,
+    track_access_frequency: bool,
+    lenient_requests: bool,
+    write_through: Option<WriteThroughSink>,
+    // Where the pages start in a file shared with other caches, see `PartitionedCache`
+    file_region: Option<u64>,
+}
+
+impl FifoFileCacheBuilder {
+    pub fn new(path: PathBuf, page_size: usize, capacity: usize) -> Self {
+        Self {
+            path,
+            page_size,
+            capacity,
+            io_priority: IoPriority::default(),
+            checksum: None,
+            persist_versions: false,
+            sync_mode: SyncMode::default(),
+            throughput_window: throughput::DEFAULT_WINDOW,
+            history_retention: None,
+            hit_rate_alpha: predictor::DEFAULT_ALPHA,
+            key_hasher: Box::new(Fnv1a),
+            dedup: false,
+            zero_fill_on_eviction: false,
+            file_mode: None,
+            ephemeral: false,
+            seqlock_reads: false,
+            version_check: true,
+            write_buffer: 0,
+            on_eviction: None,
+            transforms: TransformChain::default(),
+            initial_state: None,
+            retire_after_errors: None,
+            switch_slack: 0,
+            track_access_frequency: false,
+            lenient_requests: false,
+            write_through: None,
+            file_region: None,
+        }
+    }
+
+    /// Set the io priority of the thread doing the write, see [`IoPriority`].
+    pub fn io_priority(mut self, io_priority: IoPriority) -> Self {
+        self.io_priority = io_priority;
+        self
+    }
+
+    /// Append a checksum to every record and verify it on read, see [`Checksum`].
+    /// Corrupted records are reported as misses.
+    pub fn checksum<C: Checksum + 'static>(mut self, checksum: C) -> Self {
+        self.checksum = Some(Box::new(checksum));
+        self
+    }
+
+    /// Keep the page versions in a `.meta` sidecar next to the cache file, updated at
+    /// each page switch. See [`VersionTable`](crate::VersionTable).
+    pub fn persist_versions(mut self, persist_versions: bool) -> Self {
+        self.persist_versions = persist_versions;
+        self
+    }
+
+    /// Make writes durable before they return, see [`SyncMode`].
+    pub fn sync_mode(mut self, sync_mode: SyncMode) -> Self {
+        self.sync_mode = sync_mode;
+        self
+    }
+
+    /// The window of [`write_throughput_bps`](FifoFileCache::write_throughput_bps) and
+    /// [`read_throughput_bps`](FifoFileCache::read_throughput_bps), 5 seconds by default.
+    pub fn throughput_window(mut self, throughput_window: Duration) -> Self {
+        self.throughput_window = throughput_window;
+        self
+    }
+
+    /// Keep the last `retention` values written with
+    /// [`write_keyed`](FifoFileCache::write_keyed) in a `.history` log next to the cache
+    /// file, see [`history`](FifoFileCache::history). The log is kept when the cache is
+    /// reopened, its values are still listed.
+    pub fn history(mut self, retention: usize) -> Self {
+        self.history_retention = Some(retention);
+        self
+    }
+
+    /// The hash of the keys tagged in the history log, [`Fnv1a`] by default. Its id is
+    /// recorded in the log, building the cache over a log left with another hasher panics
+    /// with [`StorageError::InvalidConfig`](crate::StorageError::InvalidConfig).
+    pub fn key_hasher<H: KeyHasher + 'static>(mut self, key_hasher: H) -> Self {
+        self.key_hasher = Box::new(key_hasher);
+        self
+    }
+
+    /// The weight of each read in the [`HitRatePredictor`](crate::HitRatePredictor)
+    /// moving average, 0.01 by default.
+    pub fn hit_rate_alpha(mut self, alpha: f64) -> Self {
+        self.hit_rate_alpha = alpha;
+        self
+    }
+
+    /// Answer the write of a value identical to a live record with the record's
+    /// response instead of storing a copy. The index costs about 64 bytes of memory per
+    /// live record, see [`stats`](FifoFileCache::stats) for the `dedup_hits`.
+    pub fn dedup(mut self, dedup: bool) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Overwrite a page with zeros when it's recycled, before the first value is written
+    /// to it, so the evicted values can't be recovered from the file. It costs a page
+    /// of writes at each switch.
+    pub fn zero_fill_on_eviction(mut self, zero_fill: bool) -> Self {
+        self.zero_fill_on_eviction = zero_fill;
+        self
+    }
+
+    /// The permissions of the cache file when it's created, e.g. `0o600` to keep it
+    /// private to its owner. The process umask still applies, and an existing file keeps
+    /// its mode. The sidecar files are created with the default mode.
+    pub fn file_mode(mut self, mode: u32) -> Self {
+        self.file_mode = Some(mode);
+        self
+    }
+
+    /// Unlink the cache file right after opening it, so it's never visible on disk and
+    /// its space is freed when the cache is dropped, even on a crash. This works on any
+    /// Unix filesystem. The path must not be shared with another cache, and the
+    /// sidecar files, if enabled, stay visible.
+    pub fn ephemeral(mut self, ephemeral: bool) -> Self {
+        self.ephemeral = ephemeral;
+        self
+    }
+
+    /// Validate reads with a sequence counter per page, padded to a cache line, instead
+    /// of the shared version array, to keep the cache lines of the versions from bouncing
+    /// between cores under very high read concurrency. It costs 64 bytes per page.
+    pub fn seqlock_reads(mut self, seqlock_reads: bool) -> Self {
+        self.seqlock_reads = seqlock_reads;
+        self
+    }
+
+    /// Check the version of the page of a record on read, on by default. Without it a
+    /// read of a recycled record returns whatever the page holds at its offset, only a
+    /// [checksum](Self::checksum) then catches the bytes that aren't a whole record, and
+    /// nothing catches a newer record of the same length at the same offset. Meant to
+    /// compare the validation strategies, see the validation bench.
+    pub fn version_check(mut self, version_check: bool) -> Self {
+        self.version_check = version_check;
+        self
+    }
+
+    /// Buffer up to `capacity` bytes of writes in memory, to issue fewer write syscalls for
+    /// small values, 0 (the default) writes each value as it comes. The buffer is flushed
+    /// on page switches, and by the reads of the records still in it, which then wait for
+    /// the write lock. It can't be combined with a [`SyncMode`] other than `None`.
+    pub fn write_buffer(mut self, capacity: usize) -> Self {
+        self.write_buffer = capacity;
+        self
+    }
+
+    /// Call `callback` with each live record of a page when the page is recycled, e.g.
+    /// to persist evicted values elsewhere. It's called by the writer that recycled the
+    /// page once the write lock is released, in write order. With `with_data`, the page
+    /// is read before it's overwritten to pass the bytes of each record, which costs a
+    /// page read per switch on top of listing the records.
+    pub fn on_eviction<F>(mut self, with_data: bool, callback: F) -> Self
+    where
+        F: Fn(EvictedEntry) + Send + Sync + 'static,
+    {
+        self.on_eviction = Some((Box::new(callback), with_data));
+        self
+    }
+
+    /// Add a transform of the serialized values, e.g. compression or encryption. The
+    /// transforms are applied in the order they're added on write, and undone in reverse
+    /// on read. Each record names the transforms it went through, in 1 + one byte per
+    /// transform, so a record is read back right as long as its transforms are in the
+    /// chain, whatever their order.
+    pub fn transform<T: RecordTransform + 'static>(mut self, transform: T) -> Self {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    /// Start from page versions and a write cursor kept outside the crate, e.g. in a
+    /// coordination service, instead of a fresh file where every page is at version 0.
+    /// There must be one version per page. The records written before are readable with
+    /// their responses as long as the versions match, but they aren't listed in the
+    /// directory, so scans and eviction callbacks don't see them. The file may be shorter
+    /// than the capacity, the records past its end, or past the cursor on its page, are
+    /// misses.
+    pub fn initial_state(mut self, versions: Vec<u64>, cursor: (PageID, PageOffset)) -> Self {
+        self.initial_state = Some((versions, cursor));
+        self
+    }
+
+    /// Take a page out of service once `errors` reads or writes to it failed, e.g. on a
+    /// disk failing at some offsets. The writer skips the retired pages, which shrinks the
+    /// capacity, and reads of their records fail with [`StorageError::Retired`]. Two pages
+    /// always stay in service. Off by default, the retired pages are forgotten on reopen.
+    pub fn retire_after_errors(mut self, errors: u32) -> Self {
+        assert!(errors > 0, "the error threshold should not be 0");
+        self.retire_after_errors = Some(errors);
+        self
+    }
+
+    /// Switch to the next page right after a write that leaves less than `slack` bytes in
+    /// its page, e.g. the size of the smaller records, instead of on the next write that
+    /// doesn't fit. The switch, and its work once the write lock is released, then falls
+    /// on the write that filled the page rather than on whichever write comes next. 0
+    /// (the default) only switches on demand. See the `eager_switches` and
+    /// `on_demand_switches` stats.
+    pub fn switch_slack(mut self, slack: usize) -> Self {
+        self.switch_slack = slack;
+        self
+    }
+
+    /// Count the read hits of each page, for policies deciding on access frequency, see
+    /// [`FifoFileCache::access_frequency`]. Off by default, it costs a shared counter
+    /// increment per hit.
+    pub fn track_access_frequency(mut self, track: bool) -> Self {
+        self.track_access_frequency = track;
+        self
+    }
+
+    /// Treat a read of a request out of the cache's bounds as a miss instead of an
+    /// [`InvalidRequest`](crate::StorageError::InvalidRequest) error, e.g. when the
+    /// responses come from an external index that may hold corrupted ones. A bogus
+    /// length almost always means the response is stale anyway. Off by default, see
+    /// [`FifoFileCache::verify_write_response`].
+    pub fn lenient_requests(mut self, lenient: bool) -> Self {
+        self.lenient_requests = lenient;
+        self
+    }
+
+    /// Also write the values of [`write`](crate::Storage::write),
+    /// [`write_many`](crate::Storage::write_many) and
+    /// [`write_bytes`](FifoFileCache::write_bytes) to `store`, after they're cached. See
+    /// [`WriteThroughMode`] for when the store is written and how its failures are
+    /// handled.
+    pub fn write_through<S>(mut self, store: S, mode: WriteThroughMode) -> Self
+    where
+        S: WriteThrough + 'static,
+    {
+        self.write_through = Some(WriteThroughSink::new(Arc::new(store), mode));
+        self
+    }
+
+    // Put the pages at `offset` in a file shared with other caches, which own the rest of
+    // it. The sidecar files are named after the path, so they can't be used then.
+    pub(crate) fn file_region(mut self, offset: u64) -> Self {
+        self.file_region = Some(offset);
+        self
+    }
+
+    pub fn build(self) -> FifoFileCache {
+        let page_size = self.page_size;
+        let capacity = self.capacity;
+        assert!(page_size > 0);
+        // The capacity should be a multiple of the page size
+        assert!(capacity.is_multiple_of(page_size));
+        assert!(capacity > page_size);
+        self.io_priority.validate();
+        self.sync_mode.validate();
+        assert!(
+            self.version_check || !self.seqlock_reads,
+            "seqlock reads are a version check"
+        );
+        assert!(
+            self.write_buffer == 0 || self.sync_mode == SyncMode::None,
+            "buffered writes can't be synced before they return"
+        );
+        let page_num = capacity / page_size;
+        assert!(
+            self.file_region.is_none()
+                || !(self.persist_versions || self.history_retention.is_some() || self.ephemeral),
+            "a cache sharing its file can't have sidecar files nor unlink it"
+        );
+        let file_offset = self.file_region.unwrap_or(0);
+
+        // Without an initial state all pages are initialized to 0
+        let recovering = self.initial_state.is_some();
+        let (versions, (write_page_id, write_offset)) = self
+            .initial_state
+            .unwrap_or_else(|| (vec![0; page_num], (0, 0)));
+        assert_eq!(versions.len(), page_num, "one version per page is needed");
+        assert!(
+            write_page_id < page_num as PageID,
+            "cursor page out of range"
+        );
+        assert!(
+            write_offset <= page_size as PageOffset,
+            "cursor offset out of page"
+        );
+        let pages: Arc<[PageVersion]> = versions.iter().map(|&v| AtomicU64::new(v)).collect();
+        let seqlock: Option<Arc<[SeqLockPage]>> = self.seqlock_reads.then(|| {
+            versions
+                .iter()
+                .map(|&version| {
+                    let page = SeqLockPage::default();
+                    page.end_recycle(version);
+                    page
+                })
+                .collect()
+        });
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(false);
+        if let Some(mode) = self.file_mode {
+            options.mode(mode);
+        }
+        let file = options.open(&self.path).expect("Failed to open file");
+        // The file may not cover all the pages the initial state has records in
+        let recovered = recovering.then(|| {
+            let file_len = file.metadata().expect("Failed to stat file").len();
+            let file_len = file_len.saturating_sub(file_offset);
+            RecoveredExtents::new(
+                &versions,
+                (write_page_id, write_offset),
+                page_size,
+                file_len,
+            )
+        });
+        let version_table = self.persist_versions.then(|| {
+            let table = VersionTableWriter::create(&self.path, page_size, page_num)
+                .expect("Failed to create version table");
+            for (page_id, &version) in versions.iter().enumerate().filter(|(_, &v)| v > 0) {
+                table
+                    .store(page_id as PageID, version)
+                    .expect("Failed to store version");
+            }
+            table
+        });
+        let history = self.history_retention.map(|retention| {
+            assert!(retention > 0, "history retention should not be 0");
+            let log = HistoryLog::create(&self.path, retention, self.key_hasher)
+                .expect("Failed to create history log");
+            Mutex::new(log)
+        });
+        let footer = self.checksum.as_ref().map_or(0, |checksum| checksum.size());
+        let (eviction_callback, eviction) = match self.on_eviction {
+            Some((callback, with_data)) => {
+                let file = with_data.then(|| File::open(&self.path).expect("Failed to open file"));
+                (
+                    Some(callback),
+                    Some(EvictionCapture::new(file, footer, file_offset)),
+                )
+            }
+            None => (None, None),
+        };
+        let sync_file = file.try_clone().expect("Failed to clone file");
+        let stats = Arc::new(CacheStats::new(
+            self.throughput_window,
+            HitRatePredictor::new(self.hit_rate_alpha, capacity as u64),
+            capacity as u64,
+        ));
+        let unflushed = Arc::new(BufferedRange::new());
+        let health = Arc::new(PageHealth::new(page_num, self.retire_after_errors));
+        let readers = Arc::new(ReaderRegistry::new(page_num));
+        let frequency = self
+            .track_access_frequency
+            .then(|| AccessFrequencyTable::new(page_num));
+        let mut manager = WriteManger {
+            pages: pages.clone(),
+            write_page_id,
+            write_offset,
+            page_size,
+            file: BufWriter::with_capacity(self.write_buffer, file),
+            unflushed: unflushed.clone(),
+            io_priority: self.io_priority,
+            stats: stats.clone(),
+            directory: EntryDirectory::new(page_num),
+            version_table,
+            recycled: Vec::new(),
+            dedup: self.dedup.then(|| DedupIndex::new(page_num)),
+            zero_fill: self.zero_fill_on_eviction,
+            seqlock: seqlock.clone(),
+            eviction,
+            watchers: EvictionWatchers::default(),
+            health: health.clone(),
+            frequency: frequency.clone(),
+            readers: readers.clone(),
+            switch_slack: self.switch_slack as u64,
+            file_offset,
+            region_end: self.file_region.map(|offset| offset + capacity as u64),
+            read_only: false,
+            #[cfg(debug_assertions)]
+            opened_versions: versions.clone().into_boxed_slice(),
+        };
+        manager
+            .seek_to_cursor()
+            .expect("Failed to seek to the cursor");
+        let manager = Mutex::new(manager);
+        let read_file = File::open(&self.path).expect("Failed to open file");
+        if self.ephemeral {
+            std::fs::remove_file(&self.path).expect("Failed to unlink file");
+        }
+        FifoFileCache {
+            path: self.path,
+            pages,
+            page_size,
+            manager,
+            read_file,
+            io_priority: self.io_priority,
+            stats,
+            checksum: self.checksum,
+            arc_cache: Mutex::default(),
+            syncer: Syncer::new(self.sync_mode, sync_file),
+            history,
+            prefetcher: Prefetcher::default(),
+            deadline_reader: DeadlineReader::default(),
+            seqlock,
+            eviction_callback,
+            transforms: self.transforms,
+            tasks: TaskGroup::default(),
+            version_check: self.version_check,
+            unflushed,
+            recovered,
+            health,
+            frequency,
+            lenient_requests: self.lenient_requests,
+            write_through: self.write_through,
+            readers,
+            file_offset,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage};
+
+    #[test]
+    fn test_file_mode() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_file_mode");
+        let _cache = FifoFileCache::builder(path.clone(), 8, 8 * 2)
+            .file_mode(0o600)
+            .build();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_ephemeral() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_ephemeral");
+        let cache = FifoFileCache::builder(path.clone(), 8, 8 * 2)
+            .ephemeral(true)
+            .build();
+        assert!(!path.exists());
+        let response = cache.write(TestValue::from(1)).unwrap();
+        let value: TestValue = cache.read(&response).unwrap().unwrap();
+        assert_eq!(value.value, 1);
+    }
+
+    #[test]
+    fn test_write_buffer() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_write_buffer");
+        let cache = FifoFileCache::builder(path.clone(), 32, 32 * 2)
+            .write_buffer(32)
+            .build();
+        let first = cache.write(TestValue::from(1)).unwrap();
+        let second = cache.write(TestValue::from(2)).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+
+        // Reading a buffered record flushes the buffer
+        let value: TestValue = cache.read(&second).unwrap().unwrap();
+        assert_eq!(value.value, 2);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 16);
+        let value: TestValue = cache.read(&first).unwrap().unwrap();
+        assert_eq!(value.value, 1);
+
+        // The page switch flushes the rest of the page
+        for i in 3..6 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 32);
+    }
+
+    #[test]
+    fn test_write_buffer_read_elsewhere() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_write_buffer_elsewhere");
+        let cache = FifoFileCache::builder(path.clone(), 16, 16 * 3)
+            .write_buffer(16)
+            .build();
+        let responses: Vec<_> = (1..=6)
+            .map(|i| cache.write(TestValue::from(i)).unwrap())
+            .collect();
+        // Wraps to the first page, the record stays in the buffer
+        let wrapped = cache.write(TestValue::from(7)).unwrap();
+        assert_eq!((wrapped.page_id, wrapped.page_offset), (0, 0));
+
+        // The records of the later pages are read without flushing
+        for (i, response) in responses.iter().enumerate().skip(2) {
+            let value: TestValue = cache.read(response).unwrap().unwrap();
+            assert_eq!(value.value, i as u64 + 1);
+        }
+        let file = std::fs::read(&path).unwrap();
+        assert_eq!(file[..8], 1u64.to_le_bytes());
+
+        let value: TestValue = cache.read(&wrapped).unwrap().unwrap();
+        assert_eq!(value.value, 7);
+        let file = std::fs::read(&path).unwrap();
+        assert_eq!(file[..8], 7u64.to_le_bytes());
+    }
+
+    #[test]
+    fn test_open_with_versions() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_open_with_versions");
+        // Two values per page, the first page is recycled once
+        let (responses, versions, checkpoint) = {
+            let cache = FifoFileCache::new(path.clone(), 16, 16 * 3);
+            let responses: Vec<_> = (0..7)
+                .map(|i| cache.write(TestValue::from(i)).unwrap())
+                .collect();
+            let versions: Vec<u64> = cache
+                .pages
+                .iter()
+                .map(|version| version.load(std::sync::atomic::Ordering::Relaxed))
+                .collect();
+            (responses, versions, cache.checkpoint())
+        };
+        let cursor = (checkpoint.page_id, checkpoint.page_offset);
+        let cache =
+            FifoFileCache::open_with_versions(path.clone(), 16, 16 * 3, versions.clone(), cursor);
+        let value: Option<TestValue> = cache.read(&responses[0]).unwrap();
+        assert!(value.is_none());
+        for (i, response) in responses.iter().enumerate().skip(2) {
+            let value: TestValue = cache.read(response).unwrap().unwrap();
+            assert_eq!(value.value, i as u64);
+        }
+        // Writes resume at the cursor
+        let response = cache.write(TestValue::from(7)).unwrap();
+        assert_eq!((response.page_id, response.page_offset), (0, 16 - 8));
+        drop(cache);
+
+        // Versions ahead of the records make them misses
+        let ahead = versions.iter().map(|version| version + 1).collect();
+        let cache = FifoFileCache::open_with_versions(path, 16, 16 * 3, ahead, cursor);
+        let value: Option<TestValue> = cache.read(&responses[6]).unwrap();
+        assert!(value.is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "one version per page is needed")]
+    fn test_open_with_versions_wrong_count() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_open_with_versions_wrong_count");
+        FifoFileCache::open_with_versions(path, 16, 16 * 3, vec![0, 0], (0, 0));
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{FifoFileCache, PageID, PageOffset};
+
+/// The position of the write cursor at some instant, see [`FifoFileCache::checkpoint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub page_id: PageID,
+    pub page_offset: PageOffset,
+    // The number of writes completed when the checkpoint was taken
+    pub sequence: u64,
+}
+
+impl FifoFileCache {
+    /// Capture the write cursor, no I/O is done.
+    ///
+    /// Every write that returned before this call is covered by the checkpoint.
+    pub fn checkpoint(&self) -> Checkpoint {
+        let manager = self.manager.lock().unwrap();
+        Checkpoint {
+            page_id: manager.write_page_id,
+            page_offset: manager.write_offset,
+            sequence: self.stats.writes_total(),
+        }
+    }
+
+    /// Block until all writes covered by `checkpoint` are visible to readers.
+    ///
+    /// Writes are sequenced by the write lock and a write is readable as soon as it
+    /// returns, so within one process this only waits for an in-flight write to finish.
+    /// Returns false if the checkpoint is ahead of this cache, i.e. it wasn't taken from it.
+    pub fn wait_for_checkpoint(&self, checkpoint: &Checkpoint) -> bool {
+        if self.stats.writes_total() >= checkpoint.sequence {
+            return true;
+        }
+        let _manager = self.manager.lock().unwrap();
+        self.stats.writes_total() >= checkpoint.sequence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{Checkpoint, FifoFileCache, Storage, WriteResponse};
+
+    #[test]
+    fn test_checkpoint() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_checkpoint"), 16, 16 * 4);
+
+        let checkpoint = cache.checkpoint();
+        assert_eq!((checkpoint.page_id, checkpoint.page_offset), (0, 0));
+        assert_eq!(checkpoint.sequence, 0);
+
+        for i in 0..3 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        let checkpoint = cache.checkpoint();
+        assert_eq!((checkpoint.page_id, checkpoint.page_offset), (1, 8));
+        assert_eq!(checkpoint.sequence, 3);
+        assert!(cache.wait_for_checkpoint(&checkpoint));
+
+        // A checkpoint from somewhere else can't be reached
+        let foreign = Checkpoint {
+            sequence: 100,
+            ..checkpoint
+        };
+        assert!(!cache.wait_for_checkpoint(&foreign));
+    }
+
+    #[test]
+    fn test_checkpoint_across_threads() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_checkpoint_across_threads");
+        // Large enough that nothing is recycled
+        let cache = Arc::new(FifoFileCache::new(path, 64, 64 * 64));
+        let (sender, receiver) = channel::<(Vec<WriteResponse>, Checkpoint)>();
+
+        let writer = {
+            let cache = cache.clone();
+            std::thread::spawn(move || {
+                for round in 0..10u64 {
+                    let responses = (0..20)
+                        .map(|i| cache.write(TestValue::from(round * 100 + i)).unwrap())
+                        .collect();
+                    sender.send((responses, cache.checkpoint())).unwrap();
+                }
+            })
+        };
+
+        let mut round = 0;
+        for (responses, checkpoint) in receiver {
+            assert!(cache.wait_for_checkpoint(&checkpoint));
+            assert!(cache.stats().writes_total >= checkpoint.sequence);
+            for (i, response) in responses.iter().enumerate() {
+                let value: TestValue = cache.read(response).unwrap().unwrap();
+                assert_eq!(value.value, round * 100 + i as u64);
+            }
+            round += 1;
+        }
+        writer.join().unwrap();
+        assert_eq!(round, 10);
+    }
+}
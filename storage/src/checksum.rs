@@ -0,0 +1,142 @@
+/// Checksum appended after each record to detect corrupted reads.
+///
+/// The output of `compute` must always be `size()` bytes, the footer takes room in the
+/// page so a value only fits if `serialized + size() <= page_size`.
+pub trait Checksum: Send + Sync {
+    fn size(&self) -> usize;
+    fn compute(&self, data: &[u8]) -> Vec<u8>;
+}
+
+/// CRC32 (IEEE), 4 bytes. Fast and the recommended default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Crc32;
+
+impl Checksum for Crc32 {
+    fn size(&self) -> usize {
+        4
+    }
+
+    fn compute(&self, data: &[u8]) -> Vec<u8> {
+        crc32fast::hash(data).to_le_bytes().to_vec()
+    }
+}
+
+/// XXH64, 8 bytes. Faster than CRC32 on large values with fewer collisions.
+#[cfg(feature = "xxhash")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct XxHash;
+
+#[cfg(feature = "xxhash")]
+impl Checksum for XxHash {
+    fn size(&self) -> usize {
+        8
+    }
+
+    fn compute(&self, data: &[u8]) -> Vec<u8> {
+        xxhash_rust::xxh64::xxh64(data, 0).to_le_bytes().to_vec()
+    }
+}
+
+/// BLAKE3, 32 bytes. Cryptographic strength, for when collisions must not happen.
+#[cfg(feature = "blake3")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Blake3;
+
+#[cfg(feature = "blake3")]
+impl Checksum for Blake3 {
+    fn size(&self) -> usize {
+        32
+    }
+
+    fn compute(&self, data: &[u8]) -> Vec<u8> {
+        blake3::hash(data).as_bytes().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::os::unix::fs::FileExt;
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage, StorageError, WriteResponse};
+
+    fn round_trip_and_corrupt<C: Checksum + Default + 'static>() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("checksum");
+        let page_size = 8 + C::default().size();
+        let cache = FifoFileCache::builder(path.clone(), page_size, page_size * 2)
+            .checksum(C::default())
+            .build();
+
+        let response = cache.write(TestValue::from(123)).unwrap();
+        assert_eq!(response.length, page_size);
+        let value: TestValue = cache.read(&response).unwrap().unwrap();
+        assert_eq!(value.value, 123);
+
+        // Flip one bit of the value on disk
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.write_at(&[123 ^ 1], 0).unwrap();
+        let value: Option<TestValue> = cache.read(&response).unwrap();
+        assert!(value.is_none());
+        assert_eq!(cache.stats().checksum_failures, 1);
+    }
+
+    #[test]
+    fn test_crc32() {
+        round_trip_and_corrupt::<Crc32>();
+    }
+
+    #[cfg(feature = "xxhash")]
+    #[test]
+    fn test_xxhash() {
+        round_trip_and_corrupt::<XxHash>();
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn test_blake3() {
+        round_trip_and_corrupt::<Blake3>();
+    }
+
+    #[test]
+    fn test_checksum_without_version_check() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("checksum"), 24, 24 * 2)
+            .checksum(Crc32)
+            .version_check(false)
+            .build();
+        // Two records per page, the fifth write recycles the first page
+        let first = cache.write(TestValue::from(1)).unwrap();
+        for i in 2..6 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        // The newer record at the same offset passes for the first one
+        let value: TestValue = cache.read(&first).unwrap().unwrap();
+        assert_eq!(value.value, 5);
+        // Bytes straddling two records fail the checksum
+        let shifted = WriteResponse {
+            page_offset: 4,
+            ..first
+        };
+        let value: Option<TestValue> = cache.read(&shifted).unwrap();
+        assert!(value.is_none());
+        assert_eq!(cache.stats().checksum_failures, 1);
+    }
+
+    #[test]
+    fn test_footer_counts_against_page() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("checksum"), 8, 8 * 2)
+            .checksum(Crc32)
+            .build();
+        let result = cache.write(TestValue::from(123));
+        assert!(matches!(
+            result,
+            Err(StorageError::ValueTooLarge { size: 12, limit: 8 })
+        ));
+    }
+}
@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{FifoFileCache, FifoFileCacheBuilder};
+
+/// The file and geometry a cache was created with. Builder options like the checksum
+/// or the io priority are not part of it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheConfig {
+    pub path: PathBuf,
+    pub page_size: usize,
+    pub capacity: usize,
+    pub page_count: usize,
+}
+
+impl CacheConfig {
+    pub fn to_builder(&self) -> FifoFileCacheBuilder {
+        assert_eq!(self.capacity, self.page_size * self.page_count);
+        FifoFileCacheBuilder::new(self.path.clone(), self.page_size, self.capacity)
+    }
+}
+
+impl From<&FifoFileCache> for CacheConfig {
+    fn from(cache: &FifoFileCache) -> Self {
+        Self {
+            path: cache.path.clone(),
+            page_size: cache.page_size,
+            capacity: cache.page_size * cache.pages.len(),
+            page_count: cache.pages.len(),
+        }
+    }
+}
+
+impl FifoFileCache {
+    pub fn new_from_config(config: &CacheConfig) -> Self {
+        config.to_builder().build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_config_round_trip() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("first"), 64, 64 * 8);
+        let config = CacheConfig::from(&cache);
+        assert_eq!(config.path, dir.path().join("first"));
+        assert_eq!(config.page_size, 64);
+        assert_eq!(config.capacity, 64 * 8);
+        assert_eq!(config.page_count, 8);
+
+        let second_config = CacheConfig {
+            path: dir.path().join("second"),
+            ..config.clone()
+        };
+        let second = FifoFileCache::new_from_config(&second_config);
+        assert_eq!(CacheConfig::from(&second), second_config);
+        assert_eq!(second.page_size, cache.page_size);
+        assert_eq!(second.pages.len(), cache.pages.len());
+    }
+}
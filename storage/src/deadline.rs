@@ -0,0 +1,199 @@
+use std::fs::File;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::task::{ShutdownToken, TaskGroup};
+use crate::{read_page_at, FifoFileCache, StorageError, Value, WriteResponse};
+
+// Threads doing the reads with a deadline, a stalled read only holds up one of them
+const READ_THREADS: usize = 4;
+
+type Job = Box<dyn FnOnce(&File) + Send>;
+
+#[derive(Default)]
+pub(crate) struct DeadlineReader {
+    // Spawned on the first read with a deadline. None if the threads couldn't be
+    // started, the reads then run on the caller's thread.
+    jobs: OnceLock<Option<Sender<Job>>>,
+}
+
+impl DeadlineReader {
+    // Run `job` with a file handle on one of the read threads, return it back if the
+    // threads aren't available
+    pub(crate) fn submit(&self, tasks: &TaskGroup, file: &File, job: Job) -> Result<(), Job> {
+        let sender = self.jobs.get_or_init(|| {
+            let (sender, receiver) = channel::<Job>();
+            let receiver = Arc::new(Mutex::new(receiver));
+            for i in 0..READ_THREADS {
+                let file = file.try_clone().ok()?;
+                let receiver = receiver.clone();
+                tasks.spawn(format!("cache-read-{}", i), move |token: &ShutdownToken| {
+                    run_jobs(&file, &receiver, token)
+                })?;
+            }
+            Some(sender)
+        });
+        match sender {
+            Some(sender) => sender.send(job).map_err(|e| e.0),
+            None => Err(job),
+        }
+    }
+
+    // Let each thread see the shutdown, the jobs queued behind are dropped
+    pub(crate) fn wake(&self) {
+        if let Some(Some(sender)) = self.jobs.get() {
+            for _ in 0..READ_THREADS {
+                let _ = sender.send(Box::new(|_: &File| {}));
+            }
+        }
+    }
+}
+
+fn run_jobs(file: &File, receiver: &Mutex<Receiver<Job>>, token: &ShutdownToken) {
+    loop {
+        let job = receiver.lock().unwrap().recv();
+        let Ok(job) = job else {
+            return;
+        };
+        token.fail_point("read-job");
+        if token.is_shutdown() {
+            return;
+        }
+        job(file);
+    }
+}
+
+impl FifoFileCache {
+    /// Read `request`, giving up with [`StorageError::TimedOut`] if the bytes aren't read
+    /// within `timeout`, to serve a miss rather than wait on a stalled device.
+    ///
+    /// The I/O runs on a small pool of threads. The timeout bounds how long the caller
+    /// waits, not the device work: a timed out read keeps a thread busy until the device
+    /// answers, then its result is dropped. Timeouts are counted in `read_timeouts` of
+    /// [`stats`](Self::stats), not as misses. For the [`CacheService`](crate::CacheService),
+    /// use a tower timeout layer instead.
+    pub fn read_with_deadline<V: Value>(
+        &self,
+        request: &WriteResponse,
+        timeout: Duration,
+    ) -> Result<Option<V>, StorageError> {
+        let record = self.read_record_with(request, || {
+            let (page_id, page_offset, length) =
+                (request.page_id, request.page_offset, request.length);
+            let offset = self.page_start(page_id) + page_offset;
+            // On the caller's thread, the read threads can't take the write lock
+            self.flush_before_read(offset, offset + length as u64)?;
+            let health = self.health.clone();
+            let (sender, receiver) = channel();
+            let job: Job = Box::new(move |file: &File| {
+                let mut buffer = vec![0; length];
+                let result = read_page_at(file, &health, &mut buffer, page_id, page_offset, offset)
+                    .map(|()| buffer);
+                // The caller may have given up
+                let _ = sender.send(result);
+            });
+            if let Err(job) = self
+                .deadline_reader
+                .submit(&self.tasks, &self.read_file, job)
+            {
+                job(&self.read_file);
+            }
+
+            match receiver.recv_timeout(timeout) {
+                Ok(buffer) => buffer,
+                Err(RecvTimeoutError::Timeout) => {
+                    self.stats.record_read_timeout();
+                    Err(StorageError::TimedOut(timeout))
+                }
+                Err(RecvTimeoutError::Disconnected) => Err(StorageError::Io(
+                    std::io::Error::other("read thread panicked"),
+                )),
+            }
+        })?;
+        match record {
+            Some(buffer) => crate::value::deserialize(&buffer).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    use tempfile::tempdir;
+
+    use super::READ_THREADS;
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage, StorageError};
+
+    #[test]
+    fn test_read_with_deadline() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_read_with_deadline"), 8, 8 * 2);
+        let response = cache.write(TestValue::from(1)).unwrap();
+        let value: TestValue = cache
+            .read_with_deadline(&response, Duration::from_secs(10))
+            .unwrap()
+            .unwrap();
+        assert_eq!(value.value, 1);
+
+        // Stall every read thread, as a stalled device would
+        let (release, stalled) = channel::<()>();
+        let stalled = std::sync::Arc::new(std::sync::Mutex::new(stalled));
+        for _ in 0..READ_THREADS {
+            let stalled = stalled.clone();
+            let job = Box::new(move |_: &std::fs::File| {
+                let _ = stalled.lock().unwrap().recv();
+            });
+            assert!(cache
+                .deadline_reader
+                .submit(&cache.tasks, &cache.read_file, job)
+                .is_ok());
+        }
+        let result: Result<Option<TestValue>, _> =
+            cache.read_with_deadline(&response, Duration::from_millis(20));
+        assert!(matches!(result, Err(StorageError::TimedOut(_))));
+        let stats = cache.stats();
+        assert_eq!((stats.read_timeouts, stats.read_hits), (1, 1));
+
+        // The abandoned read completes once the device recovers
+        drop(release);
+        let value: TestValue = cache
+            .read_with_deadline(&response, Duration::from_secs(10))
+            .unwrap()
+            .unwrap();
+        assert_eq!(value.value, 1);
+    }
+
+    #[test]
+    fn test_read_with_deadline_buffered() {
+        let dir = tempdir().unwrap();
+        for seqlock in [false, true] {
+            let path = dir
+                .path()
+                .join(format!("test_deadline_buffered_{}", seqlock));
+            let cache = FifoFileCache::builder(path, 16, 16 * 2)
+                .write_buffer(16)
+                .seqlock_reads(seqlock)
+                .build();
+            let response = cache.write(TestValue::from(1)).unwrap();
+            // Still in the write buffer
+            let value: TestValue = cache
+                .read_with_deadline(&response, Duration::from_secs(10))
+                .unwrap()
+                .unwrap();
+            assert_eq!(value.value, 1);
+
+            for i in 2..6 {
+                cache.write(TestValue::from(i)).unwrap();
+            }
+            let value: Option<TestValue> = cache
+                .read_with_deadline(&response, Duration::from_secs(10))
+                .unwrap();
+            assert!(value.is_none());
+        }
+    }
+}
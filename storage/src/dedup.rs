@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::{FifoFileCache, PageID, StorageError, WriteManger, WriteResponse};
+
+// The newest live record of each content hash, for the dedup mode. An entry costs
+// about 64 bytes: the hash and the response in the map, and the hash again in the
+// list of its page.
+pub(crate) struct DedupIndex {
+    records: HashMap<u64, WriteResponse>,
+    // The hashes indexed for the records of each page, dropped when it's recycled
+    pages: Vec<Vec<u64>>,
+}
+
+impl DedupIndex {
+    pub(crate) fn new(page_num: usize) -> Self {
+        Self {
+            records: HashMap::new(),
+            pages: vec![Vec::new(); page_num],
+        }
+    }
+
+    fn insert(&mut self, hash: u64, response: WriteResponse) {
+        self.pages[response.page_id as usize].push(hash);
+        self.records.insert(hash, response);
+    }
+
+    pub(crate) fn recycle(&mut self, page_id: PageID) {
+        for hash in std::mem::take(&mut self.pages[page_id as usize]) {
+            // The hash may point to a newer record on another page
+            if self
+                .records
+                .get(&hash)
+                .is_some_and(|response| response.page_id == page_id)
+            {
+                self.records.remove(&hash);
+            }
+        }
+    }
+}
+
+fn content_hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl FifoFileCache {
+    // Append an encoded record, or return the live record with the same bytes in dedup
+    // mode. Called under the write lock.
+    pub(crate) fn append_record(
+        &self,
+        manager: &mut WriteManger,
+        data: Vec<u8>,
+    ) -> Result<WriteResponse, StorageError> {
+        let Some(dedup) = &manager.dedup else {
+            return manager.append(data);
+        };
+        let hash = content_hash(&data);
+        if let Some(existing) = dedup.records.get(&hash).cloned() {
+            if self.is_duplicate(manager, &existing, &data)? {
+                self.stats.record_dedup_hit();
+                return Ok(existing);
+            }
+        }
+        let response = manager.append(data)?;
+        if let Some(dedup) = &mut manager.dedup {
+            dedup.insert(hash, response.clone());
+        }
+        Ok(response)
+    }
+
+    // The hashes match, compare the bytes to rule out a collision
+    fn is_duplicate(
+        &self,
+        manager: &mut WriteManger,
+        existing: &WriteResponse,
+        data: &[u8],
+    ) -> Result<bool, StorageError> {
+        if existing.length != data.len()
+            || manager.health.is_retired(existing.page_id)
+            || manager
+                .directory
+                .is_superseded(existing.page_id, existing.page_offset)
+        {
+            return Ok(false);
+        }
+        // The record may still be in the write buffer, the read can't take the lock
+        if !manager.file.buffer().is_empty() {
+            manager.flush_buffer()?;
+        }
+        let mut buffer = vec![0; existing.length];
+        self.read_exact_at(&mut buffer, existing.page_id, existing.page_offset)?;
+        Ok(buffer == data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage};
+
+    #[test]
+    fn test_dedup() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_dedup"), 16, 16 * 2)
+            .dedup(true)
+            .build();
+        let first = cache.write(TestValue::from(1)).unwrap();
+        let again = cache.write(TestValue::from(1)).unwrap();
+        assert_eq!(
+            (again.page_id, again.page_offset, again.version),
+            (first.page_id, first.page_offset, first.version)
+        );
+        let stats = cache.stats();
+        assert_eq!((stats.writes_total, stats.dedup_hits), (1, 1));
+
+        // Once its page is recycled, the value is stored again
+        for i in 2..6 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        let value: Option<TestValue> = cache.read(&first).unwrap();
+        assert!(value.is_none());
+        let rewritten = cache.write(TestValue::from(1)).unwrap();
+        assert_ne!(rewritten.version, first.version);
+        let value: TestValue = cache.read(&rewritten).unwrap().unwrap();
+        assert_eq!(value.value, 1);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::{PageID, PageOffset};
+
+// A record written to a page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DirectoryEntry {
+    pub(crate) page_offset: PageOffset,
+    pub(crate) length: usize,
+    // An update was written based on this record
+    pub(crate) superseded: bool,
+}
+
+// The records of each page in write order, it's only touched by the write manager.
+// A page's entries are dropped when the page is recycled.
+pub(crate) struct EntryDirectory {
+    pages: Vec<Vec<DirectoryEntry>>,
+    // The bytes left unused at the end of each page the writer moved past
+    padding: Vec<u64>,
+}
+
+impl EntryDirectory {
+    pub(crate) fn new(page_num: usize) -> Self {
+        Self {
+            pages: vec![Vec::new(); page_num],
+            padding: vec![0; page_num],
+        }
+    }
+
+    pub(crate) fn push(&mut self, page_id: PageID, entry: DirectoryEntry) {
+        self.pages[page_id as usize].push(entry);
+    }
+
+    fn find(&self, page_id: PageID, page_offset: PageOffset) -> Option<usize> {
+        // Entries are pushed in offset order
+        self.pages[page_id as usize]
+            .binary_search_by_key(&page_offset, |entry| entry.page_offset)
+            .ok()
+    }
+
+    // Does nothing if the record is gone, i.e. its page was recycled
+    pub(crate) fn supersede(&mut self, page_id: PageID, page_offset: PageOffset) {
+        if let Some(index) = self.find(page_id, page_offset) {
+            self.pages[page_id as usize][index].superseded = true;
+        }
+    }
+
+    // Show a record hidden with `supersede` again, for a reserved slot once committed
+    pub(crate) fn publish(&mut self, page_id: PageID, page_offset: PageOffset) {
+        if let Some(index) = self.find(page_id, page_offset) {
+            self.pages[page_id as usize][index].superseded = false;
+        }
+    }
+
+    // A record that is gone counts as superseded
+    pub(crate) fn is_superseded(&self, page_id: PageID, page_offset: PageOffset) -> bool {
+        match self.find(page_id, page_offset) {
+            Some(index) => self.pages[page_id as usize][index].superseded,
+            None => true,
+        }
+    }
+
+    pub(crate) fn entries(&self, page_id: PageID) -> &[DirectoryEntry] {
+        &self.pages[page_id as usize]
+    }
+
+    pub(crate) fn close_page(&mut self, page_id: PageID, padding: u64) {
+        self.padding[page_id as usize] = padding;
+    }
+
+    // Forget the page's entries and padding, return them so the caller can account for
+    // them
+    pub(crate) fn recycle(&mut self, page_id: PageID) -> (Vec<DirectoryEntry>, u64) {
+        let padding = std::mem::take(&mut self.padding[page_id as usize]);
+        (std::mem::take(&mut self.pages[page_id as usize]), padding)
+    }
+}
@@ -0,0 +1,77 @@
+use std::marker::PhantomData;
+
+use crate::scan::PageWalk;
+use crate::{FifoFileCache, StorageError, Value, WriteResponse};
+
+/// Iterator over the live values of a consumed cache, see [`FifoFileCache::drain`].
+pub struct Drain<V> {
+    cache: FifoFileCache,
+    walk: PageWalk,
+    _value: PhantomData<fn() -> V>,
+}
+
+impl FifoFileCache {
+    /// Consume the cache and yield each of its live values once, oldest to newest, e.g.
+    /// to persist them elsewhere on shutdown before deleting the file.
+    ///
+    /// The destructive companion of [`iter_live`](Self::iter_live): nothing can write
+    /// during the walk, so it sees every record live when it's called and skips the same
+    /// ones, the superseded records and the ones failing their checksum. The background
+    /// threads are stopped first. An error reading a page or deserializing a value is
+    /// yielded in place of the values concerned and the walk goes on.
+    pub fn drain<V: Value>(self) -> Drain<V> {
+        self.close();
+        Drain {
+            walk: PageWalk::new(&self),
+            cache: self,
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<V: Value> Iterator for Drain<V> {
+    type Item = Result<(WriteResponse, V), StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = match self.walk.next(&self.cache)? {
+            Ok(entry) => entry,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(crate::value::deserialize(&entry.data).map(|value| (entry.response, value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage};
+
+    #[test]
+    fn test_drain() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_drain");
+        let cache = FifoFileCache::new(path.clone(), 16, 16 * 3);
+        // Wraps once, the values of the first page are evicted
+        let mut live = HashMap::new();
+        for i in 0..8 {
+            let response = cache.write(TestValue::from(i)).unwrap();
+            live.insert(response.to_bytes(), i);
+        }
+        live.retain(|_, i| *i >= 2);
+
+        let mut drained = Vec::new();
+        for entry in cache.drain::<TestValue>() {
+            let (response, value) = entry.unwrap();
+            // Yielded once, under the response of its write
+            assert_eq!(live.remove(&response.to_bytes()), Some(value.value));
+            drained.push(value.value);
+        }
+        assert!(live.is_empty());
+        assert_eq!(drained, vec![2, 3, 4, 5, 6, 7]);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{TransformError, WriteResponse};
+
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    // Reading the bytes of a record from the file failed
+    #[error("failed to read {length} bytes at page {page_id} offset {page_offset}: {source}")]
+    Read {
+        page_id: u64,
+        page_offset: u64,
+        length: usize,
+        source: std::io::Error,
+    },
+    // The device ran out of space, the write was not recorded
+    #[error("disk is full: {0}")]
+    DiskFull(#[source] std::io::Error),
+    #[error("failed to serialize value: {0}")]
+    Serialize(#[source] bincode::Error),
+    // `length` is the size of the bytes that didn't deserialize
+    #[error("failed to deserialize value of {length} bytes: {source}")]
+    Deserialize {
+        length: usize,
+        source: bincode::Error,
+    },
+    // The record (checksum footer included) doesn't fit in a page
+    #[error("value of {size} bytes exceeds page size limit of {limit} bytes")]
+    ValueTooLarge { size: usize, limit: usize },
+    // The read didn't complete within the deadline, see `read_with_deadline`
+    #[error("read timed out after {0:?}")]
+    TimedOut(std::time::Duration),
+    // A live entry doesn't fit in the pages of a migration's destination
+    #[error(
+        "entry of {} bytes at page {} offset {} exceeds page size limit of {limit} bytes",
+        entry.length, entry.page_id, entry.page_offset
+    )]
+    EntryTooLarge { entry: WriteResponse, limit: usize },
+    // A record transform failed to undo itself
+    #[error("transform error: {0}")]
+    Transform(#[source] TransformError),
+    // A versioned record has a schema version the migrator can't upgrade
+    #[error("unknown schema version {version}")]
+    UnknownSchema { version: u16 },
+    // The page of the record was retired after repeated I/O errors
+    #[error("page {page_id} is retired after repeated io errors")]
+    Retired { page_id: u64 },
+    // A field of a `WriteResponse` is out of the cache's bounds, e.g. a corrupted handle
+    // received from elsewhere
+    #[error("invalid request: {field} is {value}, expected {constraint}")]
+    InvalidRequest {
+        field: &'static str,
+        value: u64,
+        constraint: &'static str,
+    },
+    // The value was cached but the write-through store failed to persist it
+    #[error("write-through failed: {0}")]
+    WriteThrough(#[source] crate::WriteThroughError),
+    // The options don't match the state persisted by an earlier run, e.g. its key hasher
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+    // The cache was migrated to another one, it only serves reads
+    #[error("the cache is read-only")]
+    ReadOnly,
+}
+
+impl StorageError {
+    pub(crate) fn from_write(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::StorageFull | std::io::ErrorKind::WriteZero => {
+                StorageError::DiskFull(e)
+            }
+            _ => StorageError::Io(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use super::StorageError;
+
+    #[test]
+    fn test_error_source() {
+        let error = StorageError::ValueTooLarge {
+            size: 500,
+            limit: 256,
+        };
+        assert_eq!(
+            error.to_string(),
+            "value of 500 bytes exceeds page size limit of 256 bytes"
+        );
+        assert!(error.source().is_none());
+
+        let io = std::io::Error::other("device gone");
+        let error = StorageError::from(io);
+        assert_eq!(error.to_string(), "io error: device gone");
+        let source = error.source().unwrap();
+        assert_eq!(source.to_string(), "device gone");
+        assert!(source.downcast_ref::<std::io::Error>().is_some());
+
+        let bincode_error = bincode::deserialize::<u64>(&[1]).unwrap_err();
+        let error = StorageError::Deserialize {
+            length: 1,
+            source: bincode_error,
+        };
+        assert!(error.source().unwrap().is::<bincode::Error>());
+    }
+}
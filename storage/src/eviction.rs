@@ -0,0 +1,144 @@
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+
+use crate::directory::DirectoryEntry;
+use crate::{PageID, WriteResponse};
+
+/// A record dropped by the recycling of its page, see
+/// [`FifoFileCacheBuilder::on_eviction`](crate::FifoFileCacheBuilder::on_eviction).
+#[derive(Debug, Clone)]
+pub struct EvictedEntry {
+    pub response: WriteResponse,
+    /// The bytes of the value, checksum footer stripped but not verified, if the callback
+    /// asked for them. Deserialize them with bincode to get the value, unless the cache
+    /// has [transforms](crate::RecordTransform): the bytes are the stored ones.
+    pub data: Option<Vec<u8>>,
+}
+
+pub(crate) type EvictionCallback = Box<dyn Fn(EvictedEntry) + Send + Sync>;
+
+// Collects the records of the pages recycled under the write lock, the callback is called
+// with them once the lock is released
+pub(crate) struct EvictionCapture {
+    // Set to read the records before the page is overwritten
+    file: Option<File>,
+    footer: usize,
+    // Where the cache's pages start in the file
+    file_offset: u64,
+    pub(crate) pending: Vec<EvictedEntry>,
+}
+
+impl EvictionCapture {
+    pub(crate) fn new(file: Option<File>, footer: usize, file_offset: u64) -> Self {
+        Self {
+            file,
+            footer,
+            file_offset,
+            pending: Vec::new(),
+        }
+    }
+
+    // Record the entries of a page about to be recycled, before anything is changed so a
+    // failed read leaves the page as it was
+    pub(crate) fn capture(
+        &mut self,
+        page_id: PageID,
+        version: u64,
+        page_size: usize,
+        entries: &[DirectoryEntry],
+    ) -> std::io::Result<()> {
+        let live = || entries.iter().filter(|entry| !entry.superseded);
+        let page = match &self.file {
+            Some(file) if live().next().is_some() => {
+                let mut page = vec![0; page_size];
+                file.read_exact_at(&mut page, self.file_offset + page_id * page_size as u64)?;
+                Some(page)
+            }
+            _ => None,
+        };
+        for entry in live() {
+            let data = page.as_ref().map(|page| {
+                let offset = entry.page_offset as usize;
+                let length = entry.length.saturating_sub(self.footer);
+                page[offset..offset + length].to_vec()
+            });
+            self.pending.push(EvictedEntry {
+                response: WriteResponse {
+                    page_id,
+                    page_offset: entry.page_offset,
+                    version,
+                    length: entry.length,
+                },
+                data,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{Crc32, FifoFileCache, Storage};
+
+    #[test]
+    fn test_eviction_callback() {
+        let dir = tempdir().unwrap();
+        let count = Arc::new(AtomicU64::new(0));
+        let cache = {
+            let count = count.clone();
+            FifoFileCache::builder(dir.path().join("test_eviction"), 16, 16 * 3)
+                .on_eviction(false, move |entry| {
+                    assert!(entry.data.is_none());
+                    count.fetch_add(1, Ordering::Relaxed);
+                })
+                .build()
+        };
+        // Two values per page, the 7th value recycles page 0, the 9th page 1, the 11th
+        // page 2, the 13th page 0 again
+        for i in 0..13 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        assert_eq!(count.load(Ordering::Relaxed), 8);
+    }
+
+    #[test]
+    fn test_eviction_callback_with_data() {
+        let dir = tempdir().unwrap();
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let cache = {
+            let evicted = evicted.clone();
+            FifoFileCache::builder(dir.path().join("test_eviction_data"), 24, 24 * 2)
+                .checksum(Crc32)
+                .zero_fill_on_eviction(true)
+                .on_eviction(true, move |entry| {
+                    let value: TestValue = bincode::deserialize(&entry.data.unwrap()).unwrap();
+                    evicted.lock().unwrap().push((entry.response, value.value));
+                })
+                .build()
+        };
+        let first = cache.write(TestValue::from(1)).unwrap();
+        let update = cache.write(TestValue::from(2)).unwrap();
+        cache
+            .update(&update, |_: TestValue| TestValue::from(3))
+            .unwrap();
+        cache.write(TestValue::from(4)).unwrap();
+        // Page 0 is recycled, the superseded record isn't reported
+        cache.write(TestValue::from(5)).unwrap();
+
+        let evicted = evicted.lock().unwrap();
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].1, 1);
+        assert_eq!(
+            (evicted[0].0.page_id, evicted[0].0.page_offset),
+            (first.page_id, first.page_offset)
+        );
+        let value: Option<TestValue> = cache.read(&evicted[0].0).unwrap();
+        assert!(value.is_none());
+    }
+}
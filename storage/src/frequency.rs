@@ -0,0 +1,136 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+
+use crate::{FifoFileCache, PageID};
+
+// Read hits per page, the access frequency data of frequency based policies like LFU.
+// The counts are halved each time the writer wraps around the file, so the pages read a
+// lot a long time ago don't outweigh the ones read now, and a page's count is cleared
+// when it's recycled. Shared by the readers counting the hits and the writer aging them.
+#[derive(Clone)]
+pub(crate) struct AccessFrequencyTable {
+    counts: Arc<[AtomicU64]>,
+}
+
+impl AccessFrequencyTable {
+    pub(crate) fn new(page_num: usize) -> Self {
+        Self {
+            counts: (0..page_num).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    pub(crate) fn record(&self, page_id: PageID) {
+        self.counts[page_id as usize].fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub(crate) fn get(&self, page_id: PageID) -> u64 {
+        self.counts[page_id as usize].load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    // Halve every count, the hits counted meanwhile are kept
+    pub(crate) fn age(&self) {
+        for count in self.counts.iter() {
+            let _ = count.fetch_update(
+                std::sync::atomic::Ordering::Relaxed,
+                std::sync::atomic::Ordering::Relaxed,
+                |count| Some(count / 2),
+            );
+        }
+    }
+
+    pub(crate) fn clear(&self, page_id: PageID) {
+        self.counts[page_id as usize].store(0, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub(crate) fn reset(&self) {
+        for count in self.counts.iter() {
+            count.store(0, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+}
+
+impl FifoFileCache {
+    /// The read hits on the page since it was last recycled, halved each time the writer
+    /// wraps around the file. Always 0 unless the cache was built with
+    /// [`track_access_frequency`](crate::FifoFileCacheBuilder::track_access_frequency).
+    pub fn access_frequency(&self, page_id: PageID) -> u64 {
+        self.frequency
+            .as_ref()
+            .map_or(0, |frequency| frequency.get(page_id))
+    }
+
+    /// Set the access frequency of every page back to 0.
+    pub fn reset_access_counts(&self) {
+        if let Some(frequency) = &self.frequency {
+            frequency.reset();
+        }
+    }
+
+    pub(crate) fn record_access(&self, page_id: PageID) {
+        if let Some(frequency) = &self.frequency {
+            frequency.record(page_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage, WriteResponse};
+
+    #[test]
+    fn test_access_frequency() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_access_frequency"), 16, 16 * 4)
+            .track_access_frequency(true)
+            .build();
+        // Two values on each of the first three pages
+        let responses: Vec<WriteResponse> = (0..6)
+            .map(|i| cache.write(TestValue::from(i)).unwrap())
+            .collect();
+        for _ in 0..1000 {
+            let value: Option<TestValue> = cache.read(&responses[0]).unwrap();
+            assert!(value.is_some());
+        }
+        for response in &responses[2..] {
+            for _ in 0..5 {
+                let value: Option<TestValue> = cache.read(response).unwrap();
+                assert!(value.is_some());
+            }
+        }
+        assert_eq!(cache.access_frequency(0), 1000);
+        assert_eq!(cache.access_frequency(1), 10);
+        assert!(cache.access_frequency(0) >= 10 * cache.access_frequency(1));
+        assert_eq!(cache.access_frequency(3), 0);
+
+        // Wrapping around to page 0 halves the counts, and clears the count of the
+        // recycled page
+        for i in 0..3 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        assert_eq!(cache.access_frequency(0), 0);
+        assert_eq!(cache.access_frequency(1), 5);
+        assert_eq!(cache.access_frequency(2), 5);
+
+        // The next recycled page starts over too
+        for i in 0..2 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        assert_eq!(cache.access_frequency(1), 0);
+        assert_eq!(cache.access_frequency(2), 5);
+
+        cache.reset_access_counts();
+        assert_eq!(cache.access_frequency(2), 0);
+    }
+
+    #[test]
+    fn test_access_frequency_off() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_access_frequency_off"), 16, 16 * 2);
+        let response = cache.write(TestValue::from(1)).unwrap();
+        let _: Option<TestValue> = cache.read(&response).unwrap();
+        assert_eq!(cache.access_frequency(0), 0);
+    }
+}
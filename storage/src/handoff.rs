@@ -0,0 +1,421 @@
+use std::path::PathBuf;
+
+use crate::{FifoFileCache, PageID, PageOffset, StorageError, WriteResponse};
+
+/// Where a handoff stopped, to resume it with [`FifoFileCache::handoff_step`].
+/// The default cursor starts from the oldest live entry.
+#[derive(Debug, Clone, Default)]
+pub struct HandoffCursor {
+    // The last entry handed off, (page_id, page_offset, version)
+    last: Option<(PageID, PageOffset, u64)>,
+}
+
+#[derive(Debug, Default)]
+pub struct HandoffReport {
+    /// (source, destination) of each entry copied, to migrate the external index.
+    pub mapping: Vec<(WriteResponse, WriteResponse)>,
+    /// Entries recycled in the source before they could be copied.
+    pub stale: usize,
+    /// Entries that don't fit in a page of the destination.
+    pub too_large: usize,
+    /// Live entries left after this step, 0 once the handoff caught up with the writes.
+    pub remaining: usize,
+    pub cursor: HandoffCursor,
+}
+
+impl FifoFileCache {
+    /// Copy all the live entries into `dest`, oldest first, while both caches keep
+    /// serving. Entries written to the source during the handoff are copied too if they
+    /// are reached, see [`handoff_step`](Self::handoff_step) to do it in steps.
+    pub fn handoff_to(&self, dest: &FifoFileCache) -> Result<HandoffReport, StorageError> {
+        self.handoff_step(dest, &HandoffCursor::default(), usize::MAX)
+    }
+
+    /// Copy up to `max_entries` live entries written after `cursor` into `dest`.
+    ///
+    /// The records are copied as stored, so the value type doesn't matter, and `dest` may
+    /// have another page size or checksum. Entries superseded by an
+    /// [`update`](Self::update) are not copied. If the page of `cursor` was recycled
+    /// since, everything copied before is gone from the source as well and the handoff
+    /// restarts from the oldest live entry.
+    pub fn handoff_step(
+        &self,
+        dest: &FifoFileCache,
+        cursor: &HandoffCursor,
+        max_entries: usize,
+    ) -> Result<HandoffReport, StorageError> {
+        let mut entries = self.live_entries_after(cursor);
+        let remaining = entries.len().saturating_sub(max_entries);
+        entries.truncate(max_entries);
+
+        let mut report = HandoffReport {
+            remaining,
+            cursor: cursor.clone(),
+            ..Default::default()
+        };
+        for source in entries {
+            match self.read_record(&source)? {
+                None => report.stale += 1,
+                Some(data) => match dest.write_record(data) {
+                    Ok(response) => report.mapping.push((source.clone(), response)),
+                    Err(StorageError::ValueTooLarge { .. }) => report.too_large += 1,
+                    Err(e) => return Err(e),
+                },
+            }
+            report.cursor.last = Some((source.page_id, source.page_offset, source.version));
+        }
+        Ok(report)
+    }
+
+    /// Repack the live entries into a new cache at `dest` with pages of `new_page_size`,
+    /// to retune a cache whose value sizes drifted. The new cache has about the same
+    /// capacity and the default builder options, without checksum nor transforms.
+    ///
+    /// Returns the new cache and the (source, destination) of each entry copied. If an
+    /// entry doesn't fit in the new pages once its transforms are undone, the new cache
+    /// isn't created and the error carries the entry. The entries are read in memory
+    /// before the copy for this. Entries recycled in the source while copying are left out, and so are the
+    /// oldest ones if the repacked entries overflow the new cache.
+    pub fn migrate_page_size(
+        &self,
+        new_page_size: usize,
+        dest: PathBuf,
+    ) -> Result<(FifoFileCache, Vec<(WriteResponse, WriteResponse)>), StorageError> {
+        let entries = self.live_entries_after(&HandoffCursor::default());
+        let capacity = self.page_size * self.pages.len();
+        // A spare page for the padding left at the end of the pages
+        let page_count = capacity.div_ceil(new_page_size).max(1) + 1;
+        self.migrate_into(entries, new_page_size, new_page_size * page_count, dest)
+    }
+
+    /// Move the live entries to a new cache at `dest` with larger pages of
+    /// `new_page_size` and the same capacity, e.g. once the values outgrew the pages.
+    /// The capacity must be a multiple of the new page size, of at least two pages, and
+    /// the new page size larger than the current one, or it fails with
+    /// [`StorageError::InvalidConfig`].
+    ///
+    /// The cache becomes read-only before the copy, so no write is lost on the way:
+    /// the writes after fail with [`StorageError::ReadOnly`], and the entries can still
+    /// be read from it until the caller switched to the new cache. The oldest entries
+    /// are left out if the repacked ones overflow the new cache. An entry that doesn't
+    /// fit in the new pages fails it as in [`migrate_page_size`](Self::migrate_page_size),
+    /// see it for the mapping of the entries.
+    pub fn migrate_to_larger_page_size(
+        &self,
+        new_page_size: usize,
+        new_path: PathBuf,
+    ) -> Result<FifoFileCache, StorageError> {
+        let capacity = self.page_size * self.pages.len();
+        if new_page_size <= self.page_size {
+            return Err(StorageError::InvalidConfig(format!(
+                "the new page size {} is not larger than {}",
+                new_page_size, self.page_size
+            )));
+        }
+        if !capacity.is_multiple_of(new_page_size) || capacity / new_page_size < 2 {
+            return Err(StorageError::InvalidConfig(format!(
+                "the capacity of {} bytes is not a multiple of at least two pages of {}",
+                capacity, new_page_size
+            )));
+        }
+        self.lock_manager().read_only = true;
+        let entries = self.live_entries_after(&HandoffCursor::default());
+        self.migrate_into(entries, new_page_size, capacity, new_path)
+            .map(|(cache, _)| cache)
+    }
+
+    // Copy `entries` into a new cache at `dest`, or fail with `EntryTooLarge` before
+    // creating it if one doesn't fit in its pages
+    fn migrate_into(
+        &self,
+        entries: Vec<WriteResponse>,
+        new_page_size: usize,
+        capacity: usize,
+        dest: PathBuf,
+    ) -> Result<(FifoFileCache, Vec<(WriteResponse, WriteResponse)>), StorageError> {
+        // The new cache stores the records without transforms nor checksum, their size
+        // there is only known once read and decoded
+        let mut records = Vec::with_capacity(entries.len());
+        for source in entries {
+            if let Some(data) = self.read_record(&source)? {
+                if data.len() > new_page_size {
+                    return Err(StorageError::EntryTooLarge {
+                        entry: source,
+                        limit: new_page_size,
+                    });
+                }
+                records.push((source, data));
+            }
+        }
+        let cache = FifoFileCache::new(dest, new_page_size, capacity);
+        let mut mapping = Vec::with_capacity(records.len());
+        for (source, data) in records {
+            mapping.push((source, cache.write_record(data)?));
+        }
+        mapping.retain(|(_, to)| {
+            cache.pages[to.page_id as usize].load(std::sync::atomic::Ordering::Relaxed)
+                == to.version
+        });
+        Ok((cache, mapping))
+    }
+
+    // The live entries in write order, starting after `cursor` if its page is still live
+    fn live_entries_after(&self, cursor: &HandoffCursor) -> Vec<WriteResponse> {
+        let manager = self.manager.lock().unwrap();
+        let page_num = self.pages.len() as u64;
+        let version_of = |page_id: PageID| {
+            self.pages[page_id as usize].load(std::sync::atomic::Ordering::Relaxed)
+        };
+        let resume = cursor
+            .last
+            .filter(|&(page_id, _, version)| version_of(page_id) == version);
+        let (mut page_id, mut after) = match resume {
+            Some((page_id, page_offset, _)) => (page_id, Some(page_offset)),
+            None => ((manager.write_page_id + 1) % page_num, None),
+        };
+
+        let mut live = Vec::new();
+        loop {
+            let version = version_of(page_id);
+            live.extend(
+                manager
+                    .directory
+                    .entries(page_id)
+                    .iter()
+                    .filter(|entry| !entry.superseded)
+                    .filter(|entry| after.is_none_or(|after| entry.page_offset > after))
+                    .map(|entry| WriteResponse {
+                        page_id,
+                        page_offset: entry.page_offset,
+                        version,
+                        length: entry.length,
+                    }),
+            );
+            if page_id == manager.write_page_id {
+                break;
+            }
+            page_id = (page_id + 1) % page_num;
+            // Only the first page is cut at the cursor
+            after = None;
+        }
+        live
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::HandoffCursor;
+    use crate::tests::TestValue;
+    use crate::workload::{self, WorkloadRng};
+    use crate::{Crc32, FifoFileCache, RecordTransform, Storage, StorageError, TransformError};
+
+    #[test]
+    fn test_handoff_between_page_sizes() {
+        let dir = tempdir().unwrap();
+        let source = FifoFileCache::new(dir.path().join("test_handoff_source"), 16, 16 * 4);
+        let dest = FifoFileCache::new(dir.path().join("test_handoff_dest"), 64, 64 * 2);
+        // The first two values are recycled, 8 entries stay live
+        for i in 0..10 {
+            source.write(TestValue::from(i)).unwrap();
+        }
+
+        let report = source.handoff_to(&dest).unwrap();
+        assert_eq!(report.mapping.len(), 8);
+        assert_eq!(
+            (report.stale, report.too_large, report.remaining),
+            (0, 0, 0)
+        );
+        for (i, (from, to)) in report.mapping.iter().enumerate() {
+            let old: TestValue = source.read(from).unwrap().unwrap();
+            let new: TestValue = dest.read(to).unwrap().unwrap();
+            assert_eq!(old.value, i as u64 + 2);
+            assert_eq!(new.value, old.value);
+        }
+    }
+
+    #[test]
+    fn test_handoff_resume() {
+        let dir = tempdir().unwrap();
+        let source = FifoFileCache::new(dir.path().join("test_handoff_source"), 16, 16 * 4);
+        let dest = FifoFileCache::new(dir.path().join("test_handoff_dest"), 16, 16 * 8);
+        for i in 0..3 {
+            source.write(TestValue::from(i)).unwrap();
+        }
+
+        let report = source
+            .handoff_step(&dest, &HandoffCursor::default(), 2)
+            .unwrap();
+        assert_eq!((report.mapping.len(), report.remaining), (2, 1));
+        // Writes made in between are picked up by the next step
+        source.write(TestValue::from(3)).unwrap();
+        let report = source.handoff_step(&dest, &report.cursor, 10).unwrap();
+        assert_eq!((report.mapping.len(), report.remaining), (2, 0));
+        let values: Vec<u64> = report
+            .mapping
+            .iter()
+            .map(|(_, to)| {
+                Storage::<TestValue>::read(&dest, to)
+                    .unwrap()
+                    .unwrap()
+                    .value
+            })
+            .collect();
+        assert_eq!(values, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_migrate_page_size() {
+        let dir = tempdir().unwrap();
+        let source = FifoFileCache::builder(dir.path().join("test_migrate_source"), 24, 24 * 4)
+            .checksum(Crc32)
+            .build();
+        // Records of 12 bytes with the checksum, two per page
+        for i in 0..4 {
+            source.write(TestValue::from(i)).unwrap();
+        }
+
+        // Up: the four live entries are packed in one page
+        let (larger, mapping) = source
+            .migrate_page_size(64, dir.path().join("test_migrate_up"))
+            .unwrap();
+        assert_eq!(mapping.len(), 4);
+        assert!(mapping.iter().all(|(_, to)| to.page_id == 0));
+        for (from, to) in &mapping {
+            let old: TestValue = source.read(from).unwrap().unwrap();
+            let new: TestValue = larger.read(to).unwrap().unwrap();
+            assert_eq!(new.value, old.value);
+        }
+
+        // Down: without the checksum the values fit in pages of 8 bytes
+        let (smaller, mapping) = larger
+            .migrate_page_size(8, dir.path().join("test_migrate_down"))
+            .unwrap();
+        assert_eq!(mapping.len(), 4);
+        let values: Vec<u64> = mapping
+            .iter()
+            .map(|(_, to)| {
+                Storage::<TestValue>::read(&smaller, to)
+                    .unwrap()
+                    .unwrap()
+                    .value
+            })
+            .collect();
+        assert_eq!(values, vec![0, 1, 2, 3]);
+
+        match smaller.migrate_page_size(4, dir.path().join("test_migrate_too_small")) {
+            Err(StorageError::EntryTooLarge { entry, limit }) => {
+                assert_eq!((entry.length, limit), (8, 4));
+            }
+            _ => panic!("expected EntryTooLarge"),
+        }
+        assert!(!dir.path().join("test_migrate_too_small").exists());
+    }
+
+    // Drops the trailing zeros of a `TestValue`, the stored records are smaller than
+    // the values
+    struct TrimZeros;
+
+    impl RecordTransform for TrimZeros {
+        fn id(&self) -> u8 {
+            1
+        }
+
+        fn on_write(&self, mut bytes: Vec<u8>) -> Vec<u8> {
+            while bytes.last() == Some(&0) {
+                bytes.pop();
+            }
+            bytes
+        }
+
+        fn on_read(&self, mut bytes: Vec<u8>) -> Result<Vec<u8>, TransformError> {
+            bytes.resize(8, 0);
+            Ok(bytes)
+        }
+    }
+
+    #[test]
+    fn test_migrate_decoded_too_large() {
+        let dir = tempdir().unwrap();
+        let source = FifoFileCache::builder(dir.path().join("test_migrate_source"), 8, 8 * 4)
+            .transform(TrimZeros)
+            .build();
+        // Records of 3 bytes with the frame, values of 8
+        let responses: Vec<_> = (1..3)
+            .map(|i| source.write(TestValue::from(i)).unwrap())
+            .collect();
+        assert!(responses.iter().all(|response| response.length == 3));
+
+        let dest = dir.path().join("test_migrate_dest");
+        match source.migrate_page_size(4, dest.clone()) {
+            Err(StorageError::EntryTooLarge { entry, limit }) => {
+                assert_eq!((entry.page_offset, limit), (0, 4));
+            }
+            _ => panic!("expected EntryTooLarge"),
+        }
+        assert!(!dest.exists());
+        let (migrated, mapping) = source.migrate_page_size(8, dest).unwrap();
+        for (i, (_, to)) in mapping.iter().enumerate() {
+            let value: TestValue = migrated.read(to).unwrap().unwrap();
+            assert_eq!(value.value, i as u64 + 1);
+        }
+    }
+
+    #[test]
+    fn test_migrate_full_pages() {
+        let dir = tempdir().unwrap();
+        let source = FifoFileCache::new(dir.path().join("test_migrate_source"), 256, 256 * 8);
+        // Values of 200 bytes serialized, one per page with a tail too small for another
+        let mut rng = WorkloadRng::new(0);
+        let mut written = Vec::new();
+        let mut responses = Vec::new();
+        for _ in 0..8 {
+            let value = workload::TestValue::generate(188, &mut rng);
+            let response = source.write(value.clone()).unwrap();
+            assert_eq!(response.length, 200);
+            written.push(value);
+            responses.push(response);
+        }
+
+        let (larger, mapping) = source
+            .migrate_page_size(512, dir.path().join("test_migrate_up"))
+            .unwrap();
+        assert_eq!(larger.stats().capacity_bytes, 512 * 5);
+        assert_eq!(mapping.len(), 8);
+        // Two per page now
+        assert_eq!(mapping.last().unwrap().1.page_id, 3);
+        for ((_, to), value) in mapping.iter().zip(&written) {
+            let read: workload::TestValue = larger.read(to).unwrap().unwrap();
+            read.validate();
+            assert_eq!(&read, value);
+        }
+
+        // The same capacity, the source is sealed
+        let larger = source
+            .migrate_to_larger_page_size(512, dir.path().join("test_migrate_larger"))
+            .unwrap();
+        assert_eq!(larger.stats().capacity_bytes, 256 * 8);
+        let values: Vec<workload::TestValue> = larger
+            .iter_live()
+            .map(|entry| larger.read(&entry.unwrap().response).unwrap().unwrap())
+            .collect();
+        assert_eq!(values, written);
+        let result = source.write(TestValue::from(1));
+        assert!(matches!(result, Err(StorageError::ReadOnly)));
+        let value: Option<workload::TestValue> = source.read(&responses[0]).unwrap();
+        assert_eq!(value.as_ref(), written.first());
+    }
+
+    #[test]
+    fn test_migrate_to_larger_page_size_config() {
+        let dir = tempdir().unwrap();
+        let source = FifoFileCache::new(dir.path().join("test_migrate_config"), 256, 256 * 3);
+        for (page_size, name) in [(256, "same"), (128, "smaller"), (512, "not_multiple")] {
+            let result = source.migrate_to_larger_page_size(page_size, dir.path().join(name));
+            assert!(matches!(result, Err(StorageError::InvalidConfig(_))));
+        }
+        // Still writable
+        source.write(TestValue::from(1)).unwrap();
+    }
+}
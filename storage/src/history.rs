@@ -0,0 +1,402 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+use crate::{FifoFileCache, KeyHasher, Storage, StorageError, Value, WriteResponse};
+
+// The `.history` log starts with | magic "CRHL" | format u32 | key hasher id u32 | zero u32 |,
+// followed by records | key_hash u64 | key_len u32 | value_len u32 | key | value |, all
+// little-endian, the value being the serialized value without any checksum footer.
+const MAGIC: &[u8; 4] = b"CRHL";
+const FORMAT: u32 = 1;
+const FILE_HEADER_SIZE: u64 = 16;
+const HEADER_SIZE: u64 = 16;
+
+struct HistoryEntry {
+    key_hash: u64,
+    key: Vec<u8>,
+    // Offset of the value in the log
+    offset: u64,
+    length: usize,
+}
+
+// Append-only log of every keyed write, for auditing. Only the last `retention` records
+// are kept, the log is rewritten once the evicted ones take more room than the kept ones.
+// A log left by an earlier run is replayed, so the history survives reopening the cache.
+pub(crate) struct HistoryLog {
+    path: PathBuf,
+    file: File,
+    hasher: Box<dyn KeyHasher>,
+    entries: VecDeque<HistoryEntry>,
+    retention: usize,
+    end: u64,
+    evicted_bytes: u64,
+}
+
+impl HistoryLog {
+    pub(crate) fn create(
+        path: &Path,
+        retention: usize,
+        hasher: Box<dyn KeyHasher>,
+    ) -> Result<Self, StorageError> {
+        let path = Self::log_path(path);
+        let existing = match fs::read(&path) {
+            Ok(existing) => existing,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        // Shorter than a header, the earlier run didn't get to write any record
+        let fresh = existing.len() < FILE_HEADER_SIZE as usize;
+        let file = match fresh {
+            true => Self::open(&path, hasher.as_ref())?,
+            false => {
+                Self::check_header(&existing, hasher.as_ref())?;
+                OpenOptions::new().read(true).write(true).open(&path)?
+            }
+        };
+        let mut log = Self {
+            path,
+            file,
+            hasher,
+            entries: VecDeque::new(),
+            retention,
+            end: FILE_HEADER_SIZE,
+            evicted_bytes: 0,
+        };
+        if !fresh {
+            log.replay(&existing)?;
+        }
+        Ok(log)
+    }
+
+    fn log_path(path: &Path) -> PathBuf {
+        let mut log = path.as_os_str().to_owned();
+        log.push(".history");
+        log.into()
+    }
+
+    // A log left by an earlier run must have been written with the same hasher, or the
+    // key tags of the two runs wouldn't match
+    fn check_header(log: &[u8], hasher: &dyn KeyHasher) -> Result<(), StorageError> {
+        if &log[0..4] != MAGIC {
+            return Err(StorageError::InvalidConfig(
+                "the .history file is not a history log".to_string(),
+            ));
+        }
+        let format = u32::from_le_bytes(log[4..8].try_into().unwrap());
+        if format != FORMAT {
+            return Err(StorageError::InvalidConfig(format!(
+                "unsupported history log format {}",
+                format
+            )));
+        }
+        let id = u32::from_le_bytes(log[8..12].try_into().unwrap());
+        if id != hasher.id() {
+            return Err(StorageError::InvalidConfig(format!(
+                "the history log was written with key hasher {}, not {}",
+                id,
+                hasher.id()
+            )));
+        }
+        Ok(())
+    }
+
+    // Load the records of an existing log. A record cut short or whose tag doesn't match
+    // its key, e.g. from a crash in the middle of an append, ends the log there.
+    fn replay(&mut self, log: &[u8]) -> io::Result<()> {
+        let mut offset = FILE_HEADER_SIZE as usize;
+        while let Some(header) = log.get(offset..offset + HEADER_SIZE as usize) {
+            let key_hash = u64::from_le_bytes(header[0..8].try_into().unwrap());
+            let key_len = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+            let length = u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize;
+            let key_start = offset + HEADER_SIZE as usize;
+            let value_start = key_start + key_len;
+            let Some(key) = log.get(key_start..value_start) else {
+                break;
+            };
+            if value_start + length > log.len() || self.hasher.hash(key) != key_hash {
+                break;
+            }
+            self.entries.push_back(HistoryEntry {
+                key_hash,
+                key: key.to_vec(),
+                offset: value_start as u64,
+                length,
+            });
+            offset = value_start + length;
+        }
+        self.end = offset as u64;
+        // The next append starts right after the last whole record
+        self.file.set_len(self.end)?;
+        self.evict()
+    }
+
+    // Create an empty log with its header
+    fn open(path: &Path, hasher: &dyn KeyHasher) -> io::Result<File> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let mut header = [0; FILE_HEADER_SIZE as usize];
+        header[0..4].copy_from_slice(MAGIC);
+        header[4..8].copy_from_slice(&FORMAT.to_le_bytes());
+        header[8..12].copy_from_slice(&hasher.id().to_le_bytes());
+        file.write_all_at(&header, 0)?;
+        Ok(file)
+    }
+
+    fn record_size(entry: &HistoryEntry) -> u64 {
+        HEADER_SIZE + (entry.key.len() + entry.length) as u64
+    }
+
+    pub(crate) fn append(&mut self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        let key_hash = self.hasher.hash(key);
+        let mut record = Vec::with_capacity(HEADER_SIZE as usize + key.len() + value.len());
+        record.extend_from_slice(&key_hash.to_le_bytes());
+        record.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        record.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        record.extend_from_slice(key);
+        record.extend_from_slice(value);
+        self.file.write_all_at(&record, self.end)?;
+        self.entries.push_back(HistoryEntry {
+            key_hash,
+            key: key.to_vec(),
+            offset: self.end + HEADER_SIZE + key.len() as u64,
+            length: value.len(),
+        });
+        self.end += record.len() as u64;
+        self.evict()
+    }
+
+    // Drop the records past the retention, compact once they take most of the log
+    fn evict(&mut self) -> io::Result<()> {
+        while self.entries.len() > self.retention {
+            let entry = self.entries.pop_front().unwrap();
+            self.evicted_bytes += Self::record_size(&entry);
+        }
+        if self.evicted_bytes > self.end - FILE_HEADER_SIZE - self.evicted_bytes {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    // Rewrite the kept records at the start of a new log
+    fn compact(&mut self) -> io::Result<()> {
+        let mut tmp_path = self.path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp = Self::open(Path::new(&tmp_path), self.hasher.as_ref())?;
+        let mut end = FILE_HEADER_SIZE;
+        for entry in &mut self.entries {
+            let size = Self::record_size(entry);
+            let mut record = vec![0; size as usize];
+            let start = entry.offset - HEADER_SIZE - entry.key.len() as u64;
+            self.file.read_exact_at(&mut record, start)?;
+            tmp.write_all_at(&record, end)?;
+            entry.offset = end + HEADER_SIZE + entry.key.len() as u64;
+            end += size;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        self.file = tmp;
+        self.end = end;
+        self.evicted_bytes = 0;
+        Ok(())
+    }
+
+    // The serialized values written for `key`, oldest first
+    pub(crate) fn values(&self, key: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+        let key_hash = self.hasher.hash(key);
+        self.entries
+            .iter()
+            .filter(|entry| entry.key_hash == key_hash && entry.key == key)
+            .map(|entry| {
+                let mut value = vec![0; entry.length];
+                self.file.read_exact_at(&mut value, entry.offset)?;
+                Ok(value)
+            })
+            .collect()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> u64 {
+        self.end
+    }
+}
+
+impl FifoFileCache {
+    /// Write a value like [`Storage::write`], and record it under `key` in the history
+    /// log when it's enabled with
+    /// [`FifoFileCacheBuilder::history`](crate::FifoFileCacheBuilder::history).
+    pub fn write_keyed<V: Value>(
+        &self,
+        key: &[u8],
+        value: V,
+    ) -> Result<WriteResponse, StorageError> {
+        let Some(history) = &self.history else {
+            return Storage::write(self, value);
+        };
+        let serialized = bincode::serialize(&value).map_err(StorageError::Serialize)?;
+        let response = self.write_record(serialized.clone())?;
+        history.lock().unwrap().append(key, &serialized)?;
+        Ok(response)
+    }
+
+    /// The values written for `key` with [`write_keyed`](Self::write_keyed) still in
+    /// the history log, oldest first, whether or not they're still in the cache. Empty
+    /// if the history is disabled.
+    pub fn history<V: Value>(&self, key: &[u8]) -> Result<Vec<V>, StorageError> {
+        let Some(history) = &self.history else {
+            return Ok(Vec::new());
+        };
+        let values = history.lock().unwrap().values(key)?;
+        values
+            .iter()
+            .map(|value| crate::value::deserialize(value))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::{HistoryLog, MAGIC};
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Fnv1a, KeyHasher, Storage, StorageError};
+
+    fn values(cache: &FifoFileCache, key: &[u8]) -> Vec<u64> {
+        let history: Vec<TestValue> = cache.history(key).unwrap();
+        history.into_iter().map(|value| value.value).collect()
+    }
+
+    #[test]
+    fn test_history() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_history"), 8, 8 * 2)
+            .history(3)
+            .build();
+        let first = cache.write_keyed(b"a", TestValue::from(1)).unwrap();
+        cache.write_keyed(b"a", TestValue::from(2)).unwrap();
+        cache.write_keyed(b"b", TestValue::from(10)).unwrap();
+        // The first value is gone from the cache, not from the history
+        assert!(Storage::<TestValue>::read(&cache, &first)
+            .unwrap()
+            .is_none());
+        assert_eq!(values(&cache, b"a"), vec![1, 2]);
+        assert_eq!(values(&cache, b"b"), vec![10]);
+
+        // The fourth record evicts the oldest one
+        cache.write_keyed(b"a", TestValue::from(3)).unwrap();
+        assert_eq!(values(&cache, b"a"), vec![2, 3]);
+        assert!(values(&cache, b"c").is_empty());
+    }
+
+    #[test]
+    fn test_history_retention_bounds_log() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_history_bounds"), 8, 8 * 2)
+            .history(4)
+            .build();
+        // Each record takes 16 + 1 + 8 bytes
+        for i in 0..100 {
+            cache.write_keyed(b"k", TestValue::from(i)).unwrap();
+            let log_len = cache.history.as_ref().unwrap().lock().unwrap().len();
+            assert!(log_len <= 16 + 2 * 4 * 25, "log is {} bytes", log_len);
+        }
+        assert_eq!(values(&cache, b"k"), vec![96, 97, 98, 99]);
+    }
+
+    #[test]
+    fn test_history_header_records_hasher() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_history_header");
+        let cache = FifoFileCache::builder(path.clone(), 8, 8 * 2)
+            .history(2)
+            .build();
+        cache.write_keyed(b"k", TestValue::from(1)).unwrap();
+
+        let log = std::fs::read(HistoryLog::log_path(&path)).unwrap();
+        assert_eq!(&log[0..4], MAGIC);
+        assert_eq!(log[8..12], Fnv1a.id().to_le_bytes());
+        // The record is tagged with the stable hash of its key
+        assert_eq!(log[16..24], Fnv1a.hash(b"k").to_le_bytes());
+    }
+
+    // FNV-1a under another id, as a hasher with another seed would be
+    struct Reseeded;
+
+    impl KeyHasher for Reseeded {
+        fn id(&self) -> u32 {
+            7
+        }
+
+        fn hash(&self, key: &[u8]) -> u64 {
+            Fnv1a.hash(key) ^ 1
+        }
+    }
+
+    #[test]
+    fn test_history_reopen_checks_hasher() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_history_reopen");
+        let cache = FifoFileCache::builder(path.clone(), 8, 8 * 2)
+            .history(2)
+            .build();
+        cache.write_keyed(b"k", TestValue::from(1)).unwrap();
+        drop(cache);
+
+        // Reopened with the same hasher
+        let cache = FifoFileCache::builder(path.clone(), 8, 8 * 2)
+            .history(2)
+            .build();
+        cache.write_keyed(b"k", TestValue::from(2)).unwrap();
+        drop(cache);
+
+        let result = HistoryLog::create(&path, 2, Box::new(Reseeded));
+        assert!(matches!(result, Err(StorageError::InvalidConfig(_))));
+        let log = std::fs::read(HistoryLog::log_path(&path)).unwrap();
+        assert_eq!(log[8..12], Fnv1a.id().to_le_bytes());
+    }
+
+    #[test]
+    fn test_history_across_reopen() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_history_across_reopen");
+        let open = || {
+            FifoFileCache::builder(path.clone(), 8, 8 * 2)
+                .history(3)
+                .build()
+        };
+        let cache = open();
+        cache.write_keyed(b"a", TestValue::from(1)).unwrap();
+        cache.write_keyed(b"b", TestValue::from(10)).unwrap();
+        drop(cache);
+
+        let cache = open();
+        assert_eq!(values(&cache, b"a"), vec![1]);
+        assert_eq!(values(&cache, b"b"), vec![10]);
+        // The retention counts the records of both runs
+        cache.write_keyed(b"a", TestValue::from(2)).unwrap();
+        cache.write_keyed(b"a", TestValue::from(3)).unwrap();
+        assert_eq!(values(&cache, b"a"), vec![2, 3]);
+        assert_eq!(values(&cache, b"b"), vec![10]);
+        drop(cache);
+
+        // A record torn by a crash is dropped, the ones before it are kept
+        let log_path = HistoryLog::log_path(&path);
+        let mut log = std::fs::read(&log_path).unwrap();
+        let len = log.len() as u64;
+        log.extend_from_slice(&Fnv1a.hash(b"a").to_le_bytes());
+        log.extend_from_slice(&[1, 0, 0, 0, 8, 0, 0, 0, b'a', 4]);
+        std::fs::write(&log_path, log).unwrap();
+        let cache = open();
+        assert_eq!(values(&cache, b"a"), vec![2, 3]);
+        assert_eq!(std::fs::metadata(&log_path).unwrap().len(), len);
+        cache.write_keyed(b"a", TestValue::from(4)).unwrap();
+        assert_eq!(values(&cache, b"a"), vec![2, 3, 4]);
+        assert!(values(&cache, b"b").is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::{Json, Router};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::{FifoFileCache, StorageError, WriteResponse};
+
+/// Decides whether a request may use the cache, from its headers. The server has no
+/// authentication of its own, this is the hook to plug one in.
+pub trait AuthMiddleware: Send + Sync {
+    fn authorize(&self, headers: &HeaderMap) -> bool;
+}
+
+/// Lets every request through.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AllowAll;
+
+impl AuthMiddleware for AllowAll {
+    fn authorize(&self, _headers: &HeaderMap) -> bool {
+        true
+    }
+}
+
+/// An HTTP front of a cache for services that can't link the crate, built on axum.
+///
+/// - `POST /write` with `{"value": <base64 bytes>}` stores the bytes as is and answers
+///   with the location `{"page_id", "page_offset", "version", "length"}`.
+/// - `POST /read` with a location answers `{"found": bool, "value": <base64 bytes>}`,
+///   `value` is null on a miss.
+///
+/// The bytes are opaque to the server, clients serialize the values themselves. The
+/// file I/O runs on the tokio blocking pool.
+pub struct CacheHttpServer {
+    cache: Arc<FifoFileCache>,
+    auth: Arc<dyn AuthMiddleware>,
+}
+
+#[derive(Clone)]
+struct ServerState {
+    cache: Arc<FifoFileCache>,
+    auth: Arc<dyn AuthMiddleware>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WriteBody {
+    value: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Location {
+    page_id: u64,
+    page_offset: u64,
+    version: u64,
+    length: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReadBody {
+    found: bool,
+    value: Option<String>,
+}
+
+type HttpError = (StatusCode, String);
+
+impl CacheHttpServer {
+    pub fn new(cache: Arc<FifoFileCache>) -> Self {
+        Self {
+            cache,
+            auth: Arc::new(AllowAll),
+        }
+    }
+
+    /// Check every request with `auth`, the ones it rejects get a 401.
+    pub fn auth<A: AuthMiddleware + 'static>(mut self, auth: A) -> Self {
+        self.auth = Arc::new(auth);
+        self
+    }
+
+    /// The routes, to serve with `axum::serve` or nest in a larger router.
+    pub fn router(self) -> Router {
+        let state = ServerState {
+            cache: self.cache,
+            auth: self.auth,
+        };
+        Router::new()
+            .route("/write", post(write))
+            .route("/read", post(read))
+            .with_state(state)
+    }
+}
+
+fn authorize(state: &ServerState, headers: &HeaderMap) -> Result<(), HttpError> {
+    match state.auth.authorize(headers) {
+        true => Ok(()),
+        false => Err((StatusCode::UNAUTHORIZED, "unauthorized".to_string())),
+    }
+}
+
+fn storage_error(e: StorageError) -> HttpError {
+    match e {
+        StorageError::ValueTooLarge { .. } => (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()),
+        // The location comes from the client
+        StorageError::InvalidRequest { .. } => (StatusCode::BAD_REQUEST, e.to_string()),
+        // Migrated to another cache, the writes go there
+        StorageError::ReadOnly => (StatusCode::SERVICE_UNAVAILABLE, e.to_string()),
+        e => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, StorageError> + Send + 'static,
+) -> Result<T, HttpError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(storage_error)
+}
+
+async fn write(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Json(body): Json<WriteBody>,
+) -> Result<Json<Location>, HttpError> {
+    authorize(&state, &headers)?;
+    let bytes = STANDARD
+        .decode(body.value)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let cache = state.cache.clone();
+    let response = blocking(move || cache.write_bytes(bytes)).await?;
+    Ok(Json(Location {
+        page_id: response.page_id,
+        page_offset: response.page_offset,
+        version: response.version,
+        length: response.length,
+    }))
+}
+
+async fn read(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Json(location): Json<Location>,
+) -> Result<Json<ReadBody>, HttpError> {
+    authorize(&state, &headers)?;
+    let request = WriteResponse {
+        page_id: location.page_id,
+        page_offset: location.page_offset,
+        version: location.version,
+        length: location.length,
+    };
+    let cache = state.cache.clone();
+    let value = blocking(move || cache.read_record(&request)).await?;
+    Ok(Json(ReadBody {
+        found: value.is_some(),
+        value: value.map(|value| STANDARD.encode(value)),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::body::Body;
+    use axum::http::{HeaderMap, Request, StatusCode};
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde_json::{json, Value};
+    use tempfile::tempdir;
+    use tower::ServiceExt;
+
+    use super::{AuthMiddleware, CacheHttpServer};
+    use crate::FifoFileCache;
+
+    async fn post(router: &axum::Router, uri: &str, body: Value) -> (StatusCode, Value) {
+        let request = Request::post(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    #[test]
+    fn test_http_write_read() {
+        let dir = tempdir().unwrap();
+        let cache = Arc::new(FifoFileCache::new(dir.path().join("test_http"), 16, 16 * 2));
+        let router = CacheHttpServer::new(cache).router();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let value = STANDARD.encode(b"hello");
+            let (status, location) = post(&router, "/write", json!({ "value": value })).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(
+                location,
+                json!({ "page_id": 0, "page_offset": 0, "version": 0, "length": 5 })
+            );
+
+            let (status, body) = post(&router, "/read", location.clone()).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body, json!({ "found": true, "value": value }));
+
+            // Fill both pages so the first one is recycled
+            for _ in 0..3 {
+                let value = STANDARD.encode([0; 10]);
+                post(&router, "/write", json!({ "value": value })).await;
+            }
+            let (_, body) = post(&router, "/read", location).await;
+            assert_eq!(body, json!({ "found": false, "value": null }));
+
+            let out_of_range =
+                json!({ "page_id": 0, "page_offset": 12, "version": 0, "length": 5 });
+            let (status, _) = post(&router, "/read", out_of_range).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        });
+    }
+
+    struct Token;
+
+    impl AuthMiddleware for Token {
+        fn authorize(&self, headers: &HeaderMap) -> bool {
+            headers
+                .get("authorization")
+                .is_some_and(|value| value == "secret")
+        }
+    }
+
+    #[test]
+    fn test_http_auth() {
+        let dir = tempdir().unwrap();
+        let cache = Arc::new(FifoFileCache::new(
+            dir.path().join("test_http_auth"),
+            16,
+            16 * 2,
+        ));
+        let router = CacheHttpServer::new(cache).auth(Token).router();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (status, _) = post(&router, "/write", json!({ "value": "" })).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        });
+    }
+}
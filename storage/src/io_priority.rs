@@ -0,0 +1,161 @@
+/// The I/O scheduling class applied to the thread that writes into the cache file.
+///
+/// On Linux the priority is set with `ioprio_set` on the calling thread before its
+/// first `write_all`, and again only when the priority to write with changes, so the
+/// thread keeps it after the write returns. A priority set on the thread outside the
+/// cache is left as is until then. It is meant for a dedicated writer thread, like the
+/// one in the storage bench. On other platforms all variants are no-ops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IoPriority {
+    /// Leave the writer thread's priority untouched.
+    #[default]
+    None,
+    /// Only get disk time when no other process needs it.
+    Idle,
+    /// The default class of the kernel, `level` is 0 (highest) to 7 (lowest).
+    BestEffort { level: u8 },
+    /// Always served first, `level` is 0 (highest) to 7 (lowest).
+    /// Setting it requires root (`CAP_SYS_ADMIN`), otherwise it's silently ignored.
+    Realtime { level: u8 },
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    // `IOPRIO_WHO_PROCESS` with `who == 0` targets the calling thread, the kernel
+    // keeps the io priority per thread.
+    pub const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    pub const IOPRIO_CLASS_SHIFT: u32 = 13;
+    pub const IOPRIO_CLASS_RT: u32 = 1;
+    pub const IOPRIO_CLASS_BE: u32 = 2;
+    pub const IOPRIO_CLASS_IDLE: u32 = 3;
+
+    thread_local! {
+        // The ioprio the calling thread was last set to by `apply`
+        pub static APPLIED: std::cell::Cell<Option<u32>> = const { std::cell::Cell::new(None) };
+    }
+}
+
+impl IoPriority {
+    pub(crate) fn validate(&self) {
+        match self {
+            IoPriority::BestEffort { level } | IoPriority::Realtime { level } => {
+                assert!(*level < 8, "io priority level should be in 0..8");
+            }
+            IoPriority::None | IoPriority::Idle => {}
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn ioprio(&self) -> Option<u32> {
+        use linux::*;
+        let (class, data) = match *self {
+            IoPriority::None => return None,
+            IoPriority::Idle => (IOPRIO_CLASS_IDLE, 0),
+            IoPriority::BestEffort { level } => (IOPRIO_CLASS_BE, level as u32),
+            IoPriority::Realtime { level } => (IOPRIO_CLASS_RT, level as u32),
+        };
+        Some(class << IOPRIO_CLASS_SHIFT | data)
+    }
+
+    // Apply the priority to the calling thread unless it already has it, errors (e.g. no
+    // permission for the realtime class) are ignored, the write itself should not fail
+    // because of it
+    #[cfg(target_os = "linux")]
+    pub(crate) fn apply(&self) {
+        let Some(ioprio) = self.ioprio() else {
+            return;
+        };
+        if linux::APPLIED.replace(Some(ioprio)) == Some(ioprio) {
+            return;
+        }
+        unsafe {
+            libc::syscall(
+                libc::SYS_ioprio_set,
+                linux::IOPRIO_WHO_PROCESS,
+                0,
+                ioprio as libc::c_int,
+            );
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(crate) fn apply(&self) {}
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage, WriteOptions};
+
+    fn thread_ioprio() -> u32 {
+        let ret = unsafe { libc::syscall(libc::SYS_ioprio_get, linux::IOPRIO_WHO_PROCESS, 0) };
+        assert!(ret >= 0);
+        ret as u32
+    }
+
+    #[test]
+    fn test_writer_thread_priority() {
+        let dir = tempdir().unwrap();
+        for priority in [IoPriority::Idle, IoPriority::BestEffort { level: 6 }] {
+            let path = dir.path().join("test_writer_thread_priority");
+            let cache = FifoFileCache::builder(path, 64, 64 * 2)
+                .io_priority(priority)
+                .build();
+            assert_eq!(cache.current_io_priority(), priority);
+
+            // /proc/self/task/{tid}/io only has byte counters, ask the kernel directly
+            let ioprio = std::thread::scope(|s| {
+                s.spawn(|| {
+                    cache.write(TestValue::from(1)).unwrap();
+                    thread_ioprio()
+                })
+                .join()
+                .unwrap()
+            });
+            assert_eq!(Some(ioprio), priority.ioprio());
+        }
+    }
+
+    #[test]
+    fn test_priority_set_once() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_priority_set_once"), 64, 64 * 2)
+            .io_priority(IoPriority::Idle)
+            .build();
+        let best_effort = IoPriority::BestEffort { level: 5 };
+        let priorities = std::thread::scope(|s| {
+            s.spawn(|| {
+                cache.write(TestValue::from(1)).unwrap();
+                // As if set outside the cache, the next writes at the same priority keep it
+                best_effort.apply();
+                linux::APPLIED.set(IoPriority::Idle.ioprio());
+                cache.write(TestValue::from(2)).unwrap();
+                let kept = thread_ioprio();
+                // Another priority is applied
+                let options = WriteOptions {
+                    io_priority: Some(IoPriority::BestEffort { level: 6 }),
+                    ..Default::default()
+                };
+                cache
+                    .write_with_options(TestValue::from(3), options)
+                    .unwrap();
+                let overridden = thread_ioprio();
+                cache.write(TestValue::from(4)).unwrap();
+                (kept, overridden, thread_ioprio())
+            })
+            .join()
+            .unwrap()
+        });
+        assert_eq!(
+            priorities,
+            (
+                best_effort.ioprio().unwrap(),
+                IoPriority::BestEffort { level: 6 }.ioprio().unwrap(),
+                IoPriority::Idle.ioprio().unwrap()
+            )
+        );
+    }
+}
@@ -0,0 +1,57 @@
+/// Hash of the keys given to [`write_keyed`](crate::FifoFileCache::write_keyed), for
+/// the key tags persisted in files.
+///
+/// The hash must be stable across processes and Rust versions, so `std`'s randomly
+/// keyed `DefaultHasher` can't be used. `id` is recorded in the file headers, it must
+/// be unique to the hash function and its seed.
+pub trait KeyHasher: Send + Sync {
+    fn id(&self) -> u32;
+    fn hash(&self, key: &[u8]) -> u64;
+}
+
+/// 64-bit FNV-1a, the default. Always available and stable by definition.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Fnv1a;
+
+impl KeyHasher for Fnv1a {
+    fn id(&self) -> u32 {
+        1
+    }
+
+    fn hash(&self, key: &[u8]) -> u64 {
+        key.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+}
+
+/// XXH64 with a seed of 0, faster than FNV-1a on long keys.
+#[cfg(feature = "xxhash")]
+impl KeyHasher for crate::XxHash {
+    fn id(&self) -> u32 {
+        2
+    }
+
+    fn hash(&self, key: &[u8]) -> u64 {
+        xxhash_rust::xxh64::xxh64(key, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fnv1a, KeyHasher};
+
+    #[test]
+    fn test_fnv1a_is_stable() {
+        // Reference values of the FNV-1a 64 specification
+        assert_eq!(Fnv1a.hash(b""), 0xcbf29ce484222325);
+        assert_eq!(Fnv1a.hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(Fnv1a.hash(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[cfg(feature = "xxhash")]
+    #[test]
+    fn test_xxhash_is_stable() {
+        assert_eq!(crate::XxHash.hash(b""), 0xef46db3751d8e999);
+    }
+}
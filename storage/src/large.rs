@@ -0,0 +1,200 @@
+use crate::{FifoFileCache, PageID, StorageError, Value, WriteResponse};
+
+/// The consecutive pages holding a value written with
+/// [`write_large`](FifoFileCache::write_large), they wrap around the end of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageGroup {
+    pub start: PageID,
+    pub count: u8,
+}
+
+impl PageGroup {
+    /// The group of the chunks returned by `write_large`, None without chunks.
+    pub fn of(responses: &[WriteResponse]) -> Option<Self> {
+        // One chunk per page
+        let first = responses.first()?;
+        Some(Self {
+            start: first.page_id,
+            count: responses.len() as u8,
+        })
+    }
+}
+
+impl FifoFileCache {
+    /// Write a value larger than a page, split in chunks over consecutive pages starting
+    /// at the write cursor. Returns one response per chunk, in order, to pass to
+    /// [`read_large`](Self::read_large).
+    ///
+    /// The chunks are written under a single lock acquisition, so no other write lands
+    /// between them. The value must fit in all the pages but one, and in 255 pages, or the
+    /// write fails with [`StorageError::ValueTooLarge`]. The room left for the transform
+    /// frame assumes the transforms keep the size of the bytes, ones that grow them may
+    /// make a chunk overflow its page.
+    pub fn write_large<V: Value>(&self, value: V) -> Result<Vec<WriteResponse>, StorageError> {
+        let bytes = bincode::serialize(&value).map_err(StorageError::Serialize)?;
+        // The checksum footer and the transform frame of each chunk
+        let overhead = self.checksum.as_ref().map_or(0, |checksum| checksum.size())
+            + self.transforms.frame_size();
+        assert!(
+            self.page_size > overhead,
+            "the checksum and the transform frame fill the pages"
+        );
+        let chunk_size = self.page_size - overhead;
+
+        let mut manager = self.lock_manager();
+        // The first chunk fills the rest of the current page
+        let space = (self.page_size as u64 - manager.write_offset) as usize;
+        let first = space.saturating_sub(overhead).min(bytes.len());
+        let pages = (first > 0) as usize + (bytes.len() - first).div_ceil(chunk_size);
+        let max_pages = (self.pages.len() - 1).min(u8::MAX as usize);
+        if pages > max_pages {
+            return Err(StorageError::ValueTooLarge {
+                size: bytes.len(),
+                limit: max_pages * chunk_size,
+            });
+        }
+
+        let mut chunks = Vec::with_capacity(pages);
+        if first > 0 {
+            chunks.push(&bytes[..first]);
+        }
+        chunks.extend(bytes[first..].chunks(chunk_size));
+        let responses = chunks
+            .into_iter()
+            .map(|chunk| {
+                let data = self.encode_record(chunk.to_vec())?;
+                // Not deduplicated, the chunks must stay on consecutive pages
+                manager.append(data)
+            })
+            .collect();
+        self.finish_write(manager, responses)
+    }
+
+    /// Read a value written with [`write_large`](Self::write_large). It's a miss if any
+    /// of the chunks was recycled, the value is never assembled from a partial group.
+    pub fn read_large<V: Value>(
+        &self,
+        responses: &[WriteResponse],
+    ) -> Result<Option<V>, StorageError> {
+        for response in responses {
+            if !self.check_request(response)? {
+                return Ok(None);
+            }
+        }
+        // The oldest page goes first, check every version before reading anything
+        let current = |response: &WriteResponse| {
+            self.pages[response.page_id as usize].load(std::sync::atomic::Ordering::Relaxed)
+                == response.version
+        };
+        if responses.is_empty() || !responses.iter().all(current) {
+            let length = responses.iter().map(|response| response.length).sum();
+            self.stats.record_miss(length);
+            return Ok(None);
+        }
+        let mut bytes = Vec::new();
+        for response in responses {
+            // Each chunk is checked after its read, so all of them were still current
+            // when read
+            let Some(chunk) = self.read_record(response)? else {
+                return Ok(None);
+            };
+            bytes.extend_from_slice(&chunk);
+        }
+        crate::value::deserialize(&bytes).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use tempfile::tempdir;
+
+    use super::PageGroup;
+    use crate::tests::TestValue;
+    use crate::{
+        Crc32, FifoFileCache, RecordTransform, Storage, StorageError, TransformError, Value,
+    };
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Blob(Vec<u8>);
+
+    impl Value for Blob {}
+
+    // Flips every bit, keeping the size of the bytes
+    struct Invert;
+
+    impl RecordTransform for Invert {
+        fn id(&self) -> u8 {
+            1
+        }
+
+        fn on_write(&self, bytes: Vec<u8>) -> Vec<u8> {
+            bytes.into_iter().map(|byte| !byte).collect()
+        }
+
+        fn on_read(&self, bytes: Vec<u8>) -> Result<Vec<u8>, TransformError> {
+            Ok(self.on_write(bytes))
+        }
+    }
+
+    #[test]
+    fn test_write_large() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_write_large"), 64, 64 * 4)
+            .checksum(Crc32)
+            .build();
+        // 160 bytes with the length, 2.5 pages of 60 bytes of payload
+        let value = Blob((0..152).collect());
+        let responses = cache.write_large(Blob((0..152).collect())).unwrap();
+        assert_eq!(responses.len(), 3);
+        assert_eq!(
+            PageGroup::of(&responses),
+            Some(PageGroup { start: 0, count: 3 })
+        );
+        let read: Blob = cache.read_large(&responses).unwrap().unwrap();
+        assert_eq!(read, value);
+
+        // A write after it on the last page leaves it readable, recycling the first
+        // page drops the whole value
+        cache.write(TestValue::from(1)).unwrap();
+        let read: Option<Blob> = cache.read_large(&responses).unwrap();
+        assert_eq!(read, Some(value));
+        for i in 0..8 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        let read: Option<Blob> = cache.read_large(&responses).unwrap();
+        assert!(read.is_none());
+
+        let result = cache.write_large(Blob(vec![0; 200]));
+        assert!(matches!(
+            result,
+            Err(StorageError::ValueTooLarge { limit: 180, .. })
+        ));
+    }
+
+    #[test]
+    fn test_write_large_with_transform() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_large_transform"), 64, 64 * 4)
+            .checksum(Crc32)
+            .transform(Invert)
+            .build();
+        // 58 bytes of payload per page, 2 for the frame and 4 for the checksum
+        let value = Blob((0..150).collect());
+        let responses = cache.write_large(Blob((0..150).collect())).unwrap();
+        assert_eq!(responses.len(), 3);
+        assert!(responses
+            .iter()
+            .all(|response| response.length == 64 || response.page_id == 2));
+        let read: Blob = cache.read_large(&responses).unwrap().unwrap();
+        assert_eq!(read, value);
+
+        let result = cache.write_large(Blob(vec![0; 200]));
+        assert!(matches!(
+            result,
+            Err(StorageError::ValueTooLarge { limit: 174, .. })
+        ));
+        // Nothing was appended for the value too large
+        assert_eq!(cache.len(), 3);
+    }
+}
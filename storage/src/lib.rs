@@ -1,19 +1,174 @@
-use std::fs::{File, OpenOptions};
-use std::io::{Seek, SeekFrom, Write};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
 use std::os::unix::fs::FileExt;
 use std::path::PathBuf;
 use std::sync::atomic::AtomicU64;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
 
+pub use builder::FifoFileCacheBuilder;
+pub use checkpoint::Checkpoint;
+#[cfg(feature = "blake3")]
+pub use checksum::Blake3;
+#[cfg(feature = "xxhash")]
+pub use checksum::XxHash;
+pub use checksum::{Checksum, Crc32};
+pub use config::CacheConfig;
+pub use drain::Drain;
+pub use error::StorageError;
+pub use eviction::EvictedEntry;
+pub use handoff::{HandoffCursor, HandoffReport};
+#[cfg(feature = "http-server")]
+pub use http_server::{AllowAll, AuthMiddleware, CacheHttpServer};
+pub use io_priority::IoPriority;
+pub use key_hasher::{Fnv1a, KeyHasher};
+pub use large::PageGroup;
+pub use meta::Meta;
+pub use partition::PartitionedCache;
+pub use peek::PeekResult;
+pub use planner::CapacityPlanner;
+pub use predictor::HitRatePredictor;
+pub use raw_io::RawIoBenchResult;
+pub use readers::ReadHandle;
+pub use reserve::{Reservation, ReservationWriter};
+pub use scan::{LiveIter, ScanEntry};
+pub use schema::{Migrator, VersionedRead, VersionedValue};
+pub use segmented::SegmentedLogStorage;
+#[cfg(feature = "tower")]
+pub use service::{CacheRequest, CacheResponse, CacheService};
+pub use simulate::{SimOp, SimResult};
+pub use snapshot::Snapshot;
+pub use stats::StatsSnapshot;
+pub use sync::SyncMode;
+#[cfg(feature = "base64")]
+pub use text_handle::DecodeError;
+pub use transform::{RecordTransform, TransformError};
 pub use value::Value;
+pub use version_table::VersionTable;
+pub use watch::EvictionEvent;
+pub use write_if_absent::WriteIfAbsentResult;
+pub use write_options::WriteOptions;
+pub use write_through::{WriteThrough, WriteThroughError, WriteThroughMode};
 
+use crate::arc_cache::ArcCache;
+use crate::deadline::DeadlineReader;
+use crate::dedup::DedupIndex;
+use crate::directory::{DirectoryEntry, EntryDirectory};
+use crate::eviction::{EvictionCallback, EvictionCapture};
+use crate::frequency::AccessFrequencyTable;
+use crate::history::HistoryLog;
+use crate::prefetch::Prefetcher;
+use crate::readers::ReaderRegistry;
+use crate::recovery::RecoveredExtents;
+use crate::retire::PageHealth;
+use crate::seqlock::SeqLockPage;
+use crate::stats::CacheStats;
+use crate::sync::Syncer;
+use crate::task::TaskGroup;
+use crate::transform::TransformChain;
+use crate::version_table::VersionTableWriter;
+use crate::watch::EvictionWatchers;
+use crate::write_through::WriteThroughSink;
+
+mod arc_cache;
+mod batch;
+mod builder;
+mod checkpoint;
+mod checksum;
+mod config;
+mod deadline;
+mod dedup;
+mod directory;
+mod drain;
+mod error;
+mod eviction;
+mod frequency;
+mod handoff;
+mod history;
+#[cfg(feature = "http-server")]
+mod http_server;
+mod io_priority;
+mod key_hasher;
+mod large;
+mod meta;
+mod partition;
+mod peek;
+mod planner;
+mod predictor;
+mod prefetch;
+mod range;
+mod raw_io;
+mod readers;
+mod recovery;
+mod reserve;
+mod retire;
+mod scan;
+mod schema;
+mod scrub;
+mod segmented;
+#[cfg(debug_assertions)]
+mod self_check;
+mod seqlock;
+#[cfg(feature = "tower")]
+mod service;
+mod simulate;
+mod snapshot;
+mod stats;
+mod sync;
+mod task;
+#[cfg(feature = "base64")]
+mod text_handle;
+mod throughput;
+mod transform;
+mod update;
 mod value;
+mod version_table;
+mod watch;
+pub mod workload;
+mod write_if_absent;
+mod write_options;
+mod write_through;
+
+// The offsets in the file of the bytes in the write buffer, [start, end). The start is
+// u64::MAX if the buffer is empty.
+struct BufferedRange {
+    start: AtomicU64,
+    end: AtomicU64,
+}
+
+impl BufferedRange {
+    fn new() -> Self {
+        Self {
+            start: AtomicU64::new(u64::MAX),
+            end: AtomicU64::new(0),
+        }
+    }
+
+    // The end is stored first: a reader seeing a start sees its end or a later one, and a
+    // later end is only a spurious flush or comes after the bytes it reads were flushed
+    fn publish(&self, start: u64, end: u64) {
+        self.end.store(end, std::sync::atomic::Ordering::Release);
+        self.start
+            .store(start, std::sync::atomic::Ordering::Release);
+    }
+
+    fn clear(&self) {
+        self.start
+            .store(u64::MAX, std::sync::atomic::Ordering::Release);
+    }
+
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        let buffered_start = self.start.load(std::sync::atomic::Ordering::Acquire);
+        buffered_start < end && start < self.end.load(std::sync::atomic::Ordering::Acquire)
+    }
+}
 
 type PageVersion = AtomicU64;
 type PageID = u64;
 type PageOffset = u64;
 
 pub struct FifoFileCache {
+    // The backing file
+    path: PathBuf,
     // The version of each page, which is incremented by 1 after each write
     // After reading a page, the version of the page should be checked
     pages: Arc<[PageVersion]>,
@@ -22,6 +177,43 @@ pub struct FifoFileCache {
     manager: Mutex<WriteManger>,
     // The file for reading
     read_file: File,
+    io_priority: IoPriority,
+    stats: Arc<CacheStats>,
+    // Appended after each record when integrity checking is enabled
+    checksum: Option<Box<dyn Checksum>>,
+    arc_cache: Mutex<ArcCache>,
+    syncer: Syncer,
+    // Every keyed write when the history is enabled
+    history: Option<Mutex<HistoryLog>>,
+    prefetcher: Prefetcher,
+    // The threads of `read_with_deadline`, started on its first call
+    deadline_reader: DeadlineReader,
+    // The sequence counters of the pages when seqlock reads are enabled
+    seqlock: Option<Arc<[SeqLockPage]>>,
+    // Called with each live record of the recycled pages when set
+    eviction_callback: Option<EvictionCallback>,
+    // Applied to the serialized values, empty if no transform is set
+    transforms: TransformChain,
+    // The background threads, stopped by `close`
+    tasks: TaskGroup,
+    // Reads check the version of the page, only turned off to compare validations
+    version_check: bool,
+    // Shared with the write manager, see `WriteManger::unflushed`
+    unflushed: Arc<BufferedRange>,
+    // The records written before, when opened with an initial state
+    recovered: Option<RecoveredExtents>,
+    // The I/O errors and the retired pages, shared with the write manager
+    health: Arc<PageHealth>,
+    // The read hits per page when tracked, aged by the write manager
+    frequency: Option<AccessFrequencyTable>,
+    // Reads of invalid requests are misses instead of errors
+    lenient_requests: bool,
+    // The store the written values also go to when set
+    write_through: Option<WriteThroughSink>,
+    // The read handles held on each page, shared with the write manager
+    readers: Arc<ReaderRegistry>,
+    // Where the pages start in the file, 0 unless it's shared with other caches
+    file_offset: u64,
 }
 
 struct WriteManger {
@@ -29,28 +221,197 @@ struct WriteManger {
     write_page_id: u64,
     write_offset: u64,
     page_size: usize,
-    file: File,
+    // Unbuffered unless a write buffer is set, the buffer is flushed on page switches
+    file: BufWriter<File>,
+    // The bytes of the file still in the write buffer, reads overlapping them flush the
+    // buffer first
+    unflushed: Arc<BufferedRange>,
+    io_priority: IoPriority,
+    stats: Arc<CacheStats>,
+    directory: EntryDirectory,
+    // Persists the page versions for external observers when enabled
+    version_table: Option<VersionTableWriter>,
+    // Entries of the recycled pages, accounted for once the lock is released
+    recycled: Vec<DirectoryEntry>,
+    // The live records by content when dedup is enabled
+    dedup: Option<DedupIndex>,
+    // Overwrite pages with zeros when they are recycled
+    zero_fill: bool,
+    seqlock: Option<Arc<[SeqLockPage]>>,
+    // Collects the recycled records when there is an eviction callback
+    eviction: Option<EvictionCapture>,
+    // The subscribers of `watch_all_evictions`
+    watchers: EvictionWatchers,
+    health: Arc<PageHealth>,
+    // Halved when the cursor wraps around to the first page
+    frequency: Option<AccessFrequencyTable>,
+    // A page isn't recycled while read handles are held on it
+    readers: Arc<ReaderRegistry>,
+    // Switch right after a write leaving less than this in the page, 0 never does
+    switch_slack: u64,
+    file_offset: u64,
+    // The end of the cache's part of the file when it's shared with other caches
+    region_end: Option<u64>,
+    // Set once the cache was migrated elsewhere, appends fail from then on
+    read_only: bool,
+    // The page versions the cache was opened with, any vector for an initial state
+    #[cfg(debug_assertions)]
+    opened_versions: Box<[u64]>,
 }
 
 impl WriteManger {
-    fn write_move(&mut self, value_size: u64) {
-        if self.write_offset + value_size > self.page_size as u64 {
-            // Increment the next page version
-            let next_page_id = (self.write_page_id + 1) % (self.pages.len() as u64);
-            self.pages[next_page_id as usize].fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-            // Switch to the next page
-            self.write_page_id = next_page_id;
-            self.write_offset = 0;
-            self.file
-                .seek(SeekFrom::Start(self.write_page_id * self.page_size as u64))
-                .expect("Failed to seek file");
-            self.file.flush().expect("Failed to flush file");
-        }
-    }
-
-    fn write_data(&mut self, data: Vec<u8>) -> WriteResponse {
+    // Append an encoded record at the cursor
+    fn append(&mut self, data: Vec<u8>) -> Result<WriteResponse, StorageError> {
+        if self.read_only {
+            return Err(StorageError::ReadOnly);
+        }
+        self.write_move(data.len() as u64)
+            .map_err(StorageError::from_write)?;
+        let response = self.write_data(data).map_err(|e| {
+            self.health.record_error(self.write_page_id);
+            StorageError::from_write(e)
+        })?;
+        if (self.page_size as u64 - self.write_offset) < self.switch_slack {
+            self.stats.record_switch(true);
+            // The record is in, a failed switch is left to the next write_move
+            let _ = self.switch_pages();
+        }
+        #[cfg(debug_assertions)]
+        self.check_invariants();
+        Ok(response)
+    }
+
+    // Switch to the next page if the value doesn't fit in the current one.
+    // The seek happens first, so a failure leaves the cursor untouched. Once switched,
+    // a failed write_data doesn't undo the switch: the write may have clobbered part of
+    // the recycled page, and the next write_move is a no-op as the page is empty.
+    // Only the bookkeeping happens here, the rest of the switch is left to
+    // `FifoFileCache::finish_write`.
+    fn write_move(&mut self, value_size: u64) -> std::io::Result<()> {
+        if self.write_offset + value_size > self.page_size as u64
+            || self.health.is_retired(self.write_page_id)
+        {
+            self.stats.record_switch_delay();
+            self.stats.record_switch(false);
+            self.switch_pages()?;
+        }
+        Ok(())
+    }
+
+    fn switch_pages(&mut self) -> std::io::Result<()> {
+        self.stats.set_switching(true);
+        let mut result = self.switch_page();
+        // The retired pages are recycled like the others but left empty
+        while result.is_ok() && self.health.is_retired(self.write_page_id) {
+            result = self.switch_page();
+        }
+        self.stats.set_switching(false);
+        result
+    }
+
+    fn switch_page(&mut self) -> std::io::Result<()> {
+        let next_page_id = (self.write_page_id + 1) % (self.pages.len() as u64);
+        // Flushed before waiting for the readers, a holder of a handle may read a buffered
+        // record, which would take the lock to flush it
+        self.flush_buffer()?;
+        self.readers.wait_released(next_page_id);
+        self.file
+            .seek(SeekFrom::Start(self.page_start(next_page_id)))?;
+        // Persist the new version before publishing it, a failure leaves nothing changed
+        let next_version =
+            self.pages[next_page_id as usize].load(std::sync::atomic::Ordering::Relaxed) + 1;
+        if let Some(eviction) = &mut self.eviction {
+            let entries = self.directory.entries(next_page_id);
+            eviction.capture(next_page_id, next_version - 1, self.page_size, entries)?;
+        }
+        if let Some(version_table) = &self.version_table {
+            version_table.store(next_page_id, next_version)?;
+        }
+        if let Some(seqlock) = &self.seqlock {
+            seqlock[next_page_id as usize].begin_recycle();
+        }
+        // Increment the next page version
+        self.pages[next_page_id as usize].store(next_version, std::sync::atomic::Ordering::Relaxed);
+        self.watchers
+            .notify(next_page_id, next_version - 1, next_version);
+        let (recycled, recycled_padding) = self.directory.recycle(next_page_id);
+        if let Some(dedup) = &mut self.dedup {
+            dedup.recycle(next_page_id);
+        }
+        if self.recycled.is_empty() {
+            self.recycled = recycled;
+        } else {
+            self.recycled.extend(recycled);
+        }
+        // The rest of a retired page is out of the capacity already
+        let padding = match self.health.is_retired(self.write_page_id) {
+            true => 0,
+            false => self.page_size as u64 - self.write_offset,
+        };
+        self.directory.close_page(self.write_page_id, padding);
+        self.stats.record_page_switch(padding, recycled_padding);
+        if let Some(frequency) = &self.frequency {
+            if next_page_id == 0 {
+                frequency.age();
+            }
+            // The hits were on the records the page held
+            frequency.clear(next_page_id);
+        }
+        // Switch to the next page
+        self.write_page_id = next_page_id;
+        self.write_offset = 0;
+        let result = match self.zero_fill && !self.health.is_retired(next_page_id) {
+            true => self.zero_page(next_page_id),
+            false => Ok(()),
+        };
+        if let Some(seqlock) = &self.seqlock {
+            seqlock[next_page_id as usize].end_recycle(next_version);
+        }
+        result
+    }
+
+    fn page_start(&self, page_id: PageID) -> u64 {
+        self.file_offset + page_id * self.page_size as u64
+    }
+
+    fn seek_to_cursor(&mut self) -> std::io::Result<()> {
+        let cursor = self.page_start(self.write_page_id) + self.write_offset;
+        // Flushes the buffer first
+        self.file.seek(SeekFrom::Start(cursor))?;
+        self.unflushed.clear();
+        Ok(())
+    }
+
+    fn flush_buffer(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        self.unflushed.clear();
+        Ok(())
+    }
+
+    // On failure nothing is recorded and the file position is moved back to the cursor,
+    // so the next write starts at the same offset
+    fn write_data(&mut self, data: Vec<u8>) -> std::io::Result<WriteResponse> {
         let data_len = data.len();
-        self.file.write_all(&data).expect("Failed to write file");
+        self.io_priority.apply();
+        if let Err(e) = self.file.write_all(&data) {
+            let _ = self.seek_to_cursor();
+            return Err(e);
+        }
+        // Published before the response, a reader holding it sees the record is buffered
+        let end = self.page_start(self.write_page_id) + self.write_offset + data_len as u64;
+        match self.file.buffer().len() {
+            0 => self.unflushed.clear(),
+            buffered => self.unflushed.publish(end - buffered as u64, end),
+        }
+        self.directory.push(
+            self.write_page_id,
+            DirectoryEntry {
+                page_offset: self.write_offset,
+                length: data_len,
+                superseded: false,
+            },
+        );
+        self.stats.record_written(data_len);
         let response = WriteResponse {
             page_id: self.write_page_id,
             page_offset: self.write_offset,
@@ -59,7 +420,7 @@ impl WriteManger {
             length: data_len,
         };
         self.write_offset += data_len as u64;
-        response
+        Ok(response)
     }
 }
 
@@ -71,100 +432,550 @@ pub struct WriteResponse {
     pub length: usize,
 }
 
-pub trait MockRequest<V>
+impl WriteResponse {
+    /// Size of the [`to_bytes`](Self::to_bytes) encoding.
+    pub const ENCODED_SIZE: usize = 24;
+
+    /// A fixed encoding for external indices, independent of any serde config.
+    ///
+    /// The layout, all integers little-endian:
+    ///
+    /// | bytes  | field       | type |
+    /// |--------|-------------|------|
+    /// | 0..8   | page_id     | u64  |
+    /// | 8..12  | page_offset | u32  |
+    /// | 12..20 | version     | u64  |
+    /// | 20..24 | length      | u32  |
+    ///
+    /// Panics if `page_offset` or `length` doesn't fit in a u32, which can't happen for a
+    /// response returned by a cache with pages smaller than 4 GiB.
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_SIZE] {
+        let page_offset = u32::try_from(self.page_offset).expect("page offset exceeds u32");
+        let length = u32::try_from(self.length).expect("length exceeds u32");
+        let mut bytes = [0; Self::ENCODED_SIZE];
+        bytes[0..8].copy_from_slice(&self.page_id.to_le_bytes());
+        bytes[8..12].copy_from_slice(&page_offset.to_le_bytes());
+        bytes[12..20].copy_from_slice(&self.version.to_le_bytes());
+        bytes[20..24].copy_from_slice(&length.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; Self::ENCODED_SIZE]) -> Self {
+        Self {
+            page_id: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            page_offset: u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as PageOffset,
+            version: u64::from_le_bytes(bytes[12..20].try_into().unwrap()),
+            length: u32::from_le_bytes(bytes[20..24].try_into().unwrap()) as usize,
+        }
+    }
+}
+
+pub trait Storage<V>
 where
     V: Value,
 {
+    type Error;
+
     // Read a value from the storage
     // Return none if the page_version is not the same as the version of the page
     // Otherwise return the value deserialized from the page directly
-    fn read(&self, request: &WriteResponse) -> Option<V>;
+    fn read(&self, request: &WriteResponse) -> Result<Option<V>, Self::Error>;
     // Write a value to the storage
     // Return the page_id, page_offset, version, and length of the written value
     // The page_version should be incremented by 1
-    fn write(&self, value: V) -> WriteResponse;
+    fn write(&self, value: V) -> Result<WriteResponse, Self::Error>;
+
+    /// Write the values in order, storages can override it with a batched write.
+    fn write_many(&self, values: Vec<V>) -> Result<Vec<WriteResponse>, Self::Error> {
+        values.into_iter().map(|value| self.write(value)).collect()
+    }
+
+    /// Read the requests, the results are in the order of `requests`. Storages can
+    /// override it with a batched read.
+    fn read_many(&self, requests: &[WriteResponse]) -> Result<Vec<Option<V>>, Self::Error> {
+        requests.iter().map(|request| self.read(request)).collect()
+    }
 }
 
-impl FifoFileCache {
-    pub fn new(path: PathBuf, page_size: usize, capacity: usize) -> Self {
-        assert!(page_size > 0);
-        // The capacity should be a multiple of the page size
-        assert!(capacity % page_size == 0);
-        assert!(capacity > page_size);
-        let page_num = capacity / page_size;
-
-        // All pages are initialized to 0
-        let mut pages = Vec::with_capacity(page_num);
-        for _ in 0..page_num {
-            pages.push(AtomicU64::new(0));
-        }
-        let pages: Arc<[PageVersion]> = pages.into();
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(&path)
-            .expect("Failed to open file");
-        let manager = Mutex::new(WriteManger {
-            pages: pages.clone(),
-            write_page_id: 0,
-            write_offset: 0,
-            page_size,
-            file,
-        });
-        let read_file = File::open(&path).expect("Failed to open file");
-        Self {
-            pages,
-            page_size,
-            manager,
-            read_file,
-        }
+#[deprecated(note = "use `Storage` instead, it reports errors instead of panicking")]
+pub trait MockRequest<V>
+where
+    V: Value,
+{
+    fn read(&self, request: &WriteResponse) -> Option<V>;
+    fn write(&self, value: V) -> WriteResponse;
+
+    fn write_many(&self, values: Vec<V>) -> Vec<WriteResponse> {
+        values.into_iter().map(|value| self.write(value)).collect()
+    }
+
+    fn read_many(&self, requests: &[WriteResponse]) -> Vec<Option<V>> {
+        requests.iter().map(|request| self.read(request)).collect()
     }
 }
 
-impl<V> MockRequest<V> for FifoFileCache
+#[allow(deprecated)]
+impl<V, S> MockRequest<V> for S
 where
     V: Value,
+    S: Storage<V> + ?Sized,
+    S::Error: std::fmt::Debug,
 {
     fn read(&self, request: &WriteResponse) -> Option<V> {
-        assert!(request.length <= self.page_size);
-        assert!(request.page_id < self.pages.len() as u64);
-        assert!(request.page_offset + request.length as u64 <= self.page_size as u64);
-        let offset = request.page_id * self.page_size as u64 + request.page_offset;
-        let mut buffer = vec![0; request.length];
-        let mut bytes_read_total = 0;
-        loop {
-            let bytes_read = self
-                .read_file
-                .read_at(
-                    &mut buffer[bytes_read_total..],
-                    offset + bytes_read_total as u64,
-                )
-                .unwrap();
-            bytes_read_total += bytes_read;
-            if bytes_read_total == request.length || bytes_read == 0 {
-                break;
+        Storage::read(self, request).expect("Failed to read value")
+    }
+
+    fn write(&self, value: V) -> WriteResponse {
+        Storage::write(self, value).expect("Failed to write value")
+    }
+
+    fn write_many(&self, values: Vec<V>) -> Vec<WriteResponse> {
+        Storage::write_many(self, values).expect("Failed to write values")
+    }
+
+    fn read_many(&self, requests: &[WriteResponse]) -> Vec<Option<V>> {
+        Storage::read_many(self, requests).expect("Failed to read values")
+    }
+}
+
+impl FifoFileCache {
+    pub fn new(path: PathBuf, page_size: usize, capacity: usize) -> Self {
+        Self::builder(path, page_size, capacity).build()
+    }
+
+    pub fn builder(path: PathBuf, page_size: usize, capacity: usize) -> FifoFileCacheBuilder {
+        FifoFileCacheBuilder::new(path, page_size, capacity)
+    }
+
+    /// Open the cache file at `path` with the page versions and write cursor managed by
+    /// the caller, see [`FifoFileCacheBuilder::initial_state`].
+    pub fn open_with_versions(
+        path: PathBuf,
+        page_size: usize,
+        capacity: usize,
+        versions: Vec<u64>,
+        cursor: (PageID, PageOffset),
+    ) -> Self {
+        Self::builder(path, page_size, capacity)
+            .initial_state(versions, cursor)
+            .build()
+    }
+
+    pub fn current_io_priority(&self) -> IoPriority {
+        self.io_priority
+    }
+
+    pub fn stats(&self) -> StatsSnapshot {
+        let mut snapshot = self.stats.snapshot();
+        snapshot.retired_pages = self.health.retired_pages();
+        snapshot.capacity_bytes -= snapshot.retired_pages.len() as u64 * self.page_size as u64;
+        snapshot
+    }
+
+    /// The minimum version across all pages.
+    ///
+    /// Pages are recycled strictly in FIFO order (nothing can be pinned), so the pages
+    /// ahead of the write cursor hold this version and the ones behind it hold one more.
+    /// Any `WriteResponse` with a lower version has been evicted for sure.
+    pub fn oldest_live_version(&self) -> u64 {
+        self.pages
+            .iter()
+            .map(|version| version.load(std::sync::atomic::Ordering::Relaxed))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Bytes wasted at page ends because the next value didn't fit, see
+    /// [`StatsSnapshot::fragmentation_bytes`].
+    pub fn fragmentation_bytes(&self) -> u64 {
+        self.stats().fragmentation_bytes()
+    }
+
+    pub fn fragmentation_fraction(&self) -> f64 {
+        self.stats().fragmentation_fraction()
+    }
+
+    /// Average size of the records written so far, 0 if nothing was written.
+    pub fn average_value_size(&self) -> f64 {
+        self.stats().average_value_size()
+    }
+
+    /// The number of records that are still readable.
+    ///
+    /// Records are counted until their page is recycled, a value that was written again
+    /// (e.g. the same key updated) is counted once per write.
+    pub fn len(&self) -> usize {
+        self.stats.live_entries() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The bytes occupied by the records counted in [`len`](Self::len), including
+    /// checksum footers.
+    pub fn live_bytes(&self) -> u64 {
+        self.stats.live_bytes()
+    }
+
+    /// [`live_bytes`](Self::live_bytes) relative to the capacity. The bytes left unused
+    /// at the end of the pages don't count, see `tail_gap_bytes` in
+    /// [`stats`](Self::stats).
+    pub fn fill_fraction(&self) -> f32 {
+        self.stats().fill_fraction()
+    }
+}
+
+impl FifoFileCache {
+    // Read the raw bytes of a record, return None if the page was recycled or the
+    // checksum doesn't match. The checksum footer is stripped from the returned bytes.
+    fn read_record(&self, request: &WriteResponse) -> Result<Option<Vec<u8>>, StorageError> {
+        self.read_record_with(request, || {
+            let mut buffer = vec![0; request.length];
+            self.read_exact_at(&mut buffer, request.page_id, request.page_offset)?;
+            Ok(buffer)
+        })
+    }
+
+    // The checks around `read`, which reads the bytes of the record, for the reads doing
+    // the I/O elsewhere
+    fn read_record_with<F>(
+        &self,
+        request: &WriteResponse,
+        read: F,
+    ) -> Result<Option<Vec<u8>>, StorageError>
+    where
+        F: FnOnce() -> Result<Vec<u8>, StorageError>,
+    {
+        if !self.check_request(request)? {
+            return Ok(None);
+        }
+        if let Some(seqlock) = &self.seqlock {
+            return self.read_record_seqlock(&seqlock[request.page_id as usize], request, read);
+        }
+        let buffer = read()?;
+        self.verify_record(request, buffer)
+    }
+
+    // The page's sequence counter replaces the version check, see `seqlock`
+    fn read_record_seqlock<F>(
+        &self,
+        page: &SeqLockPage,
+        request: &WriteResponse,
+        read: F,
+    ) -> Result<Option<Vec<u8>>, StorageError>
+    where
+        F: FnOnce() -> Result<Vec<u8>, StorageError>,
+    {
+        if !page.read_begin(request.version) {
+            self.stats.record_miss(request.length);
+            return Ok(None);
+        }
+        let buffer = read()?;
+        if !page.read_validate(request.version) {
+            self.stats.record_miss(request.length);
+            return Ok(None);
+        }
+        self.accept_record(request, buffer)
+    }
+
+    // An invalid request is an error, or a miss when the cache is lenient. A request past
+    // the bytes written before open is a miss too. Returns whether the record can be read.
+    fn check_request(&self, request: &WriteResponse) -> Result<bool, StorageError> {
+        match self.verify_write_response(request) {
+            Ok(()) => {}
+            Err(_) if self.lenient_requests => {
+                // The length is likely bogus too
+                self.stats.record_miss(request.length.min(self.page_size));
+                return Ok(false);
             }
+            Err(e) => return Err(e),
         }
-        assert_eq!(bytes_read_total, request.length);
+        if self
+            .recovered
+            .as_ref()
+            .is_some_and(|recovered| recovered.is_unwritten(request))
+        {
+            self.stats.record_miss(request.length);
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Check that `request` addresses a record within the cache's pages, e.g. for a
+    /// response deserialized from disk or received over the network, without reading
+    /// it. Unlike a read, it doesn't check the version.
+    pub fn verify_write_response(&self, request: &WriteResponse) -> Result<(), StorageError> {
+        let invalid = |field, value, constraint| {
+            Err(StorageError::InvalidRequest {
+                field,
+                value,
+                constraint,
+            })
+        };
+        let page_size = self.page_size as u64;
+        if request.page_id >= self.pages.len() as u64 {
+            return invalid("page_id", request.page_id, "less than the page count");
+        }
+        if request.page_offset >= page_size {
+            return invalid(
+                "page_offset",
+                request.page_offset,
+                "less than the page size",
+            );
+        }
+        if request.length == 0 {
+            return invalid("length", 0, "greater than 0");
+        }
+        if request.length > self.page_size {
+            return invalid("length", request.length as u64, "at most the page size");
+        }
+        if request.page_offset + request.length as u64 > page_size {
+            return invalid(
+                "page_offset + length",
+                request.page_offset + request.length as u64,
+                "at most the page size",
+            );
+        }
+        Ok(())
+    }
+
+    // Read the bytes at `page_offset` in the page, a short read is an error as the
+    // bytes of a record are always in the file
+    fn read_exact_at(
+        &self,
+        buffer: &mut [u8],
+        page_id: PageID,
+        page_offset: PageOffset,
+    ) -> Result<(), StorageError> {
+        let offset = self.page_start(page_id) + page_offset;
+        self.flush_before_read(offset, offset + buffer.len() as u64)?;
+        read_page_at(
+            &self.read_file,
+            &self.health,
+            buffer,
+            page_id,
+            page_offset,
+            offset,
+        )
+    }
+
+    fn page_start(&self, page_id: PageID) -> u64 {
+        self.file_offset + page_id * self.page_size as u64
+    }
+
+    // Flush the write buffer if it holds bytes of [start, end), to read them from the
+    // file. Must not be called under the write lock.
+    fn flush_before_read(&self, start: u64, end: u64) -> Result<(), StorageError> {
+        if !self.unflushed.overlaps(start, end) {
+            return Ok(());
+        }
+        self.lock_manager().flush_buffer()?;
+        Ok(())
+    }
+
+    // Check the bytes read for `request` are still its record, strip the checksum footer
+    fn verify_record(
+        &self,
+        request: &WriteResponse,
+        buffer: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
         // Each page's version is incremented by 1 after each write
         // Check the version after read, if it's not the same as the request version, return None
         let page_version =
             self.pages[request.page_id as usize].load(std::sync::atomic::Ordering::Relaxed);
-        if page_version != request.version {
-            return None;
+        if self.version_check && page_version != request.version {
+            self.stats.record_miss(request.length);
+            return Ok(None);
         }
-        let value = bincode::deserialize(&buffer).expect("Failed to deserialize value");
-        Some(value)
+        self.accept_record(request, buffer)
     }
 
-    fn write(&self, value: V) -> WriteResponse {
-        let serialized = bincode::serialize(&value).expect("Failed to serialize value");
-        let length = serialized.len();
-        assert!(length <= self.page_size);
-        let mut manager = self.manager.lock().unwrap();
-        manager.write_move(length as u64);
-        manager.write_data(serialized)
+    // Check the checksum of the bytes of a current record, count the hit and undo the
+    // transforms
+    fn accept_record(
+        &self,
+        request: &WriteResponse,
+        mut buffer: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        if let Some(checksum) = &self.checksum {
+            let Some(payload_len) = buffer.len().checked_sub(checksum.size()) else {
+                self.stats.record_checksum_failure(request.length);
+                return Ok(None);
+            };
+            if checksum.compute(&buffer[..payload_len]) != buffer[payload_len..] {
+                self.stats.record_checksum_failure(request.length);
+                return Ok(None);
+            }
+            buffer.truncate(payload_len);
+        }
+        self.stats.record_hit(request.length);
+        self.record_access(request.page_id);
+        if self.prefetcher.note_hit(request) {
+            self.stats.record_prefetch_useful();
+        }
+        self.decode_record(buffer).map(Some)
+    }
+
+    // Apply the transforms, add the checksum footer (if any) and check the record fits in
+    // a page
+    fn encode_record(&self, data: Vec<u8>) -> Result<Vec<u8>, StorageError> {
+        self.encode_record_with(data, true)
+    }
+
+    // Without `transforms` the record is framed as one that went through none of them
+    fn encode_record_with(
+        &self,
+        mut data: Vec<u8>,
+        transforms: bool,
+    ) -> Result<Vec<u8>, StorageError> {
+        if !self.transforms.is_empty() {
+            data = match transforms {
+                true => self.transforms.encode(data),
+                false => TransformChain::encode_untransformed(data),
+            };
+        }
+        if let Some(checksum) = &self.checksum {
+            let footer = checksum.compute(&data);
+            data.extend_from_slice(&footer);
+        }
+        if data.len() > self.page_size {
+            return Err(StorageError::ValueTooLarge {
+                size: data.len(),
+                limit: self.page_size,
+            });
+        }
+        Ok(data)
+    }
+
+    fn write_record(&self, data: Vec<u8>) -> Result<WriteResponse, StorageError> {
+        let value = self.write_through.as_ref().map(|_| data.clone());
+        let data = self.encode_record(data)?;
+        let mut manager = self.lock_manager();
+        let response = self.append_record(&mut manager, data);
+        let response = self.finish_write(manager, response)?;
+        if let Some(value) = value {
+            self.write_through(&response, value)?;
+        }
+        Ok(response)
+    }
+
+    // Take the write lock, counting the writes that wait for another writer's page switch
+    fn lock_manager(&self) -> MutexGuard<'_, WriteManger> {
+        match self.manager.try_lock() {
+            Ok(manager) => manager,
+            Err(TryLockError::WouldBlock) => {
+                let switching = self.stats.switching();
+                let manager = self.manager.lock().unwrap();
+                if switching {
+                    self.stats.record_switch_delay();
+                }
+                manager
+            }
+            Err(e @ TryLockError::Poisoned(_)) => panic!("{}", e),
+        }
+    }
+
+    // Release the write lock, then finish the page switches that happened under it and
+    // make the write durable according to the sync mode. A guard dropped without this
+    // leaves the switch work to the next write.
+    fn finish_write<T>(
+        &self,
+        manager: MutexGuard<'_, WriteManger>,
+        response: Result<T, StorageError>,
+    ) -> Result<T, StorageError> {
+        self.finish_write_with(manager, response, None)
+    }
+
+    // `sync` overrides the sync mode, true syncs the write and false doesn't wait for it
+    fn finish_write_with<T>(
+        &self,
+        mut manager: MutexGuard<'_, WriteManger>,
+        response: Result<T, StorageError>,
+        sync: Option<bool>,
+    ) -> Result<T, StorageError> {
+        let sequence = self.stats.writes_total();
+        let recycled = std::mem::take(&mut manager.recycled);
+        let evicted = manager
+            .eviction
+            .as_mut()
+            .map(|eviction| std::mem::take(&mut eviction.pending));
+        drop(manager);
+        if !recycled.is_empty() {
+            self.stats.record_recycled(&recycled);
+        }
+        if let (Some(callback), Some(evicted)) = (&self.eviction_callback, evicted) {
+            evicted.into_iter().for_each(callback);
+        }
+        let response = response?;
+        match sync {
+            None => self.syncer.sync(sequence, &self.stats)?,
+            Some(true) => self.syncer.sync_now(&self.stats)?,
+            Some(false) => {}
+        }
+        Ok(response)
+    }
+}
+
+impl<V> Storage<V> for FifoFileCache
+where
+    V: Value,
+{
+    type Error = StorageError;
+
+    fn read(&self, request: &WriteResponse) -> Result<Option<V>, StorageError> {
+        let Some(buffer) = self.read_record(request)? else {
+            return Ok(None);
+        };
+        let value = value::deserialize(&buffer)?;
+        Ok(Some(value))
+    }
+
+    fn write(&self, value: V) -> Result<WriteResponse, StorageError> {
+        let serialized = bincode::serialize(&value).map_err(StorageError::Serialize)?;
+        self.write_record(serialized)
     }
+
+    // Takes the write lock once for the whole batch
+    fn write_many(&self, values: Vec<V>) -> Result<Vec<WriteResponse>, StorageError> {
+        let records = values
+            .iter()
+            .map(|value| bincode::serialize(value).map_err(StorageError::Serialize))
+            .collect::<Result<_, _>>()?;
+        self.write_records(records)
+    }
+
+    // Reads records next to each other in the file with a single read
+    fn read_many(&self, requests: &[WriteResponse]) -> Result<Vec<Option<V>>, StorageError> {
+        self.read_records(requests)?
+            .into_iter()
+            .map(|buffer| buffer.map(|buffer| value::deserialize(&buffer)).transpose())
+            .collect()
+    }
+}
+
+// Read the bytes at `offset` in the file, the `page_offset` of the page, with the read
+// threads' own handle of the file. The bytes must have been flushed.
+fn read_page_at(
+    file: &File,
+    health: &PageHealth,
+    buffer: &mut [u8],
+    page_id: PageID,
+    page_offset: PageOffset,
+    offset: u64,
+) -> Result<(), StorageError> {
+    if health.is_retired(page_id) {
+        return Err(StorageError::Retired { page_id });
+    }
+    file.read_exact_at(buffer, offset).map_err(|source| {
+        health.record_error(page_id);
+        StorageError::Read {
+            page_id,
+            page_offset,
+            length: buffer.len(),
+            source,
+        }
+    })
 }
 
 #[cfg(test)]
@@ -173,11 +984,11 @@ mod tests {
     use serde::{Deserialize, Serialize};
     use tempfile::tempdir;
 
-    use super::*;
+    use super::{FifoFileCache, Storage, StorageError, Value, WriteResponse};
 
     #[derive(Debug, Serialize, Deserialize)]
-    struct TestValue {
-        value: u64,
+    pub(crate) struct TestValue {
+        pub(crate) value: u64,
     }
 
     impl From<u64> for TestValue {
@@ -197,7 +1008,7 @@ mod tests {
         let cache = FifoFileCache::new(path.clone(), page_size, capacity);
 
         let value = TestValue::from(123);
-        let response = cache.write(value);
+        let response = cache.write(value).unwrap();
         assert!(response.page_id == 0);
         assert!(response.page_offset == 0);
         assert!(response.version == 0);
@@ -208,19 +1019,348 @@ mod tests {
             version: response.version,
             length: response.length,
         };
-        let read_value: TestValue = cache.read(&read_request).unwrap();
+        let read_value: TestValue = cache.read(&read_request).unwrap().unwrap();
         assert_eq!(read_value.value, 123);
 
-        cache.write(TestValue::from(456));
+        cache.write(TestValue::from(456)).unwrap();
         // The cache only has 2 pages, so the third write should move to the next page
-        let reponse = cache.write(TestValue::from(789));
+        let reponse = cache.write(TestValue::from(789)).unwrap();
 
         assert!(reponse.page_id == 0);
         assert!(reponse.page_offset == 0);
         assert!(reponse.version == 1);
 
         // Try read the old value, should return None
-        let read_value: Option<TestValue> = cache.read(&read_request);
+        let read_value: Option<TestValue> = cache.read(&read_request).unwrap();
         assert!(read_value.is_none());
     }
+
+    #[test]
+    fn test_storage_trait_object() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_storage_trait_object");
+        let storage: Box<dyn Storage<TestValue, Error = StorageError>> =
+            Box::new(FifoFileCache::new(path, 8, 8 * 2));
+        let response = storage.write(TestValue::from(1)).unwrap();
+        assert_eq!(storage.read(&response).unwrap().unwrap().value, 1);
+
+        #[allow(deprecated)]
+        {
+            use super::MockRequest;
+            let response = MockRequest::write(storage.as_ref(), TestValue::from(2));
+            let value: Option<TestValue> = MockRequest::read(storage.as_ref(), &response);
+            assert_eq!(value.unwrap().value, 2);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_disk_full() {
+        // Every write to /dev/full fails with ENOSPC
+        let cache = FifoFileCache::new("/dev/full".into(), 8, 8 * 2);
+        for i in 0..3 {
+            let result = cache.write(TestValue::from(i));
+            assert!(matches!(result, Err(StorageError::DiskFull(_))));
+        }
+        let checkpoint = cache.checkpoint();
+        assert_eq!((checkpoint.page_id, checkpoint.page_offset), (0, 0));
+        assert_eq!(checkpoint.sequence, 0);
+        assert_eq!(cache.pages[1].load(std::sync::atomic::Ordering::Relaxed), 0);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_error_context() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_error_context");
+        let cache = FifoFileCache::new(path.clone(), 16, 16 * 2);
+        cache.write(TestValue::from(1)).unwrap();
+        let response = cache.write(TestValue::from(2)).unwrap();
+
+        // Two u64 don't fit in the 8 bytes of a TestValue
+        #[derive(Debug, Serialize, Deserialize)]
+        struct Wide(u64, u64);
+        impl Value for Wide {}
+        let error = Storage::<Wide>::read(&cache, &response).unwrap_err();
+        assert!(matches!(error, StorageError::Deserialize { length: 8, .. }));
+
+        // The record is gone from the file
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(4)
+            .unwrap();
+        let error = Storage::<TestValue>::read(&cache, &response).unwrap_err();
+        let StorageError::Read {
+            page_id,
+            page_offset,
+            length,
+            ref source,
+        } = error
+        else {
+            panic!("unexpected {:?}", error);
+        };
+        assert_eq!((page_id, page_offset, length), (0, 8, 8));
+        assert_eq!(source.kind(), std::io::ErrorKind::UnexpectedEof);
+        assert_eq!(
+            error.to_string(),
+            "failed to read 8 bytes at page 0 offset 8: failed to fill whole buffer"
+        );
+    }
+
+    #[test]
+    fn test_oldest_live_version() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_oldest_live_version");
+        // 4 pages, each holds one value
+        let cache = FifoFileCache::new(path, 8, 8 * 4);
+        assert_eq!(cache.oldest_live_version(), 0);
+
+        // Switching into a page bumps its version, so page 0 is at 2 after two wraps,
+        // pages 1 and 2 are at 3 and page 3 (ahead of the cursor) is still at 2
+        let responses: Vec<WriteResponse> = (0..11)
+            .map(|i| cache.write(TestValue::from(i)).unwrap())
+            .collect();
+        assert_eq!(responses[10].page_id, 2);
+        assert_eq!(responses[10].version, 3);
+        assert_eq!(cache.oldest_live_version(), 2);
+
+        // Everything older than the floor is gone, the floor itself may still be live
+        for response in &responses {
+            let value: Option<TestValue> = cache.read(response).unwrap();
+            if response.version < cache.oldest_live_version() {
+                assert!(value.is_none());
+            }
+        }
+        let value: Option<TestValue> = cache.read(&responses[7]).unwrap();
+        assert_eq!(value.unwrap().value, 7);
+    }
+
+    #[test]
+    fn test_fragmentation() {
+        let dir = tempdir().unwrap();
+        // Values fill their page exactly, nothing is wasted
+        let cache = FifoFileCache::new(dir.path().join("exact"), 8, 8 * 2);
+        for i in 0..5 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        assert_eq!(cache.fragmentation_bytes(), 0);
+        assert_eq!(cache.fragmentation_fraction(), 0.0);
+        assert_eq!(cache.average_value_size(), 8.0);
+
+        // Two values per 20 byte page leave 4 bytes at each page end
+        let cache = FifoFileCache::new(dir.path().join("padded"), 20, 20 * 2);
+        for i in 0..5 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        assert_eq!(cache.fragmentation_bytes(), 8);
+        assert!((cache.fragmentation_fraction() - 8.0 / 48.0).abs() < 1e-9);
+        assert_eq!(cache.average_value_size(), 8.0);
+    }
+
+    #[test]
+    fn test_live_entries() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_live_entries");
+        // 3 pages of 16 bytes, each holds two values
+        let cache = FifoFileCache::new(path, 16, 16 * 3);
+        assert!(cache.is_empty());
+
+        for i in 0..6 {
+            cache.write(TestValue::from(i)).unwrap();
+            assert_eq!(cache.len(), i as usize + 1);
+            assert_eq!(cache.live_bytes(), (i + 1) * 8);
+        }
+
+        // Switching into the second page drops its two entries and adds the new one
+        cache.write(TestValue::from(6)).unwrap();
+        assert_eq!(cache.len(), 5);
+        assert_eq!(cache.live_bytes(), 5 * 8);
+        cache.write(TestValue::from(7)).unwrap();
+        assert_eq!(cache.len(), 6);
+
+        // A full wrap later the counters are back at a full cache
+        for i in 8..14 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        assert_eq!(cache.len(), 6);
+        assert_eq!(cache.live_bytes(), 6 * 8);
+    }
+
+    #[test]
+    fn test_switch_delayed_writes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_switch_delayed_writes");
+        let cache = FifoFileCache::new(path, 16, 16 * 3);
+        // Every other write switches pages, the first one doesn't need to
+        for i in 0..7 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        assert_eq!(cache.stats().switch_delayed_writes, 3);
+        // The recycled entries are accounted for by the time the write returns
+        assert_eq!(cache.len(), 5);
+    }
+
+    #[test]
+    fn test_switch_slack() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_switch_slack");
+        let cache = FifoFileCache::builder(path, 16, 16 * 3)
+            .switch_slack(4)
+            .build();
+        cache.write_bytes(vec![1; 10]).unwrap();
+        // Doesn't fit in the 6 bytes left
+        let on_demand = cache.write_bytes(vec![2; 8]).unwrap();
+        assert_eq!((on_demand.page_id, on_demand.page_offset), (1, 0));
+        // Leaves 2 bytes, less than the slack
+        cache.write_bytes(vec![3; 6]).unwrap();
+        let stats = cache.stats();
+        assert_eq!((stats.eager_switches, stats.on_demand_switches), (1, 1));
+        assert_eq!(stats.switch_delayed_writes, 1);
+
+        // The next write starts on the page switched to
+        let response = cache.write_bytes(vec![4; 2]).unwrap();
+        assert_eq!((response.page_id, response.page_offset), (2, 0));
+        let stats = cache.stats();
+        assert_eq!((stats.eager_switches, stats.on_demand_switches), (1, 1));
+        assert_eq!(stats.padding_bytes, 6 + 2);
+        let bytes = cache.read_range(&on_demand, 0, 8).unwrap().unwrap();
+        assert_eq!(bytes, vec![2; 8]);
+    }
+
+    #[test]
+    fn test_verify_write_response() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_verify"), 16, 16 * 3);
+        let response = cache.write(TestValue::from(1)).unwrap();
+        cache.verify_write_response(&response).unwrap();
+
+        let invalid = |page_id, page_offset, length| WriteResponse {
+            page_id,
+            page_offset,
+            version: response.version,
+            length,
+        };
+        let field_of = |request: WriteResponse| match cache.verify_write_response(&request) {
+            Err(StorageError::InvalidRequest { field, .. }) => field,
+            result => panic!("unexpected {:?}", result),
+        };
+        assert_eq!(field_of(invalid(3, 0, 8)), "page_id");
+        assert_eq!(field_of(invalid(0, 16, 8)), "page_offset");
+        assert_eq!(field_of(invalid(0, 0, 0)), "length");
+        assert_eq!(field_of(invalid(0, 0, 17)), "length");
+        assert_eq!(field_of(invalid(0, 12, 8)), "page_offset + length");
+
+        // Reads report it instead of panicking
+        let result: Result<Option<TestValue>, _> = cache.read(&invalid(7, 0, 8));
+        let error = result.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid request: page_id is 7, expected less than the page count"
+        );
+        let result = cache.read_range(&invalid(0, 12, 8), 0, 4);
+        assert!(matches!(result, Err(StorageError::InvalidRequest { .. })));
+    }
+
+    #[test]
+    fn test_lenient_requests() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_lenient"), 16, 16 * 3)
+            .lenient_requests(true)
+            .build();
+        let response = cache.write(TestValue::from(1)).unwrap();
+        let corrupted = [
+            (0, 0, usize::MAX),
+            (0, 0, 17),
+            (0, u64::MAX, 8),
+            (0, 12, 8),
+            (u64::MAX, 0, 8),
+        ];
+        for (page_id, page_offset, length) in corrupted {
+            let request = WriteResponse {
+                page_id,
+                page_offset,
+                version: response.version,
+                length,
+            };
+            let value: Option<TestValue> = cache.read(&request).unwrap();
+            assert!(value.is_none());
+            assert!(cache.read_range(&request, 0, 4).unwrap().is_none());
+            let values: Vec<Option<TestValue>> = cache
+                .read_many(&[request.clone(), response.clone()])
+                .unwrap();
+            assert!(values[0].is_none());
+            assert_eq!(values[1].as_ref().unwrap().value, 1);
+            let value: Option<TestValue> = cache.read_large(&[request]).unwrap();
+            assert!(value.is_none());
+        }
+        // Counted as misses, with the length capped at a page
+        let stats = cache.stats();
+        assert_eq!(stats.read_misses, 5 * 4);
+        assert!(stats.read_miss_bytes <= 5 * 4 * 16);
+
+        // Without it they're errors
+        let strict = FifoFileCache::new(dir.path().join("test_strict"), 16, 16 * 3);
+        let request = WriteResponse {
+            page_id: 0,
+            page_offset: u64::MAX,
+            version: 0,
+            length: usize::MAX,
+        };
+        let result: Result<Option<TestValue>, _> = strict.read(&request);
+        assert!(matches!(result, Err(StorageError::InvalidRequest { .. })));
+        let result: Result<Vec<Option<TestValue>>, _> = strict.read_many(&[request]);
+        assert!(matches!(result, Err(StorageError::InvalidRequest { .. })));
+    }
+
+    #[test]
+    fn test_write_response_bytes() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_write_response_bytes"), 8, 8 * 2);
+        let response = cache.write(TestValue::from(3)).unwrap();
+        let decoded = WriteResponse::from_bytes(&response.to_bytes());
+        let value: TestValue = cache.read(&decoded).unwrap().unwrap();
+        assert_eq!(value.value, 3);
+
+        let max = WriteResponse {
+            page_id: u64::MAX,
+            page_offset: u32::MAX as u64,
+            version: u64::MAX,
+            length: u32::MAX as usize,
+        };
+        let bytes = max.to_bytes();
+        assert_eq!(bytes, [0xff; WriteResponse::ENCODED_SIZE]);
+        let decoded = WriteResponse::from_bytes(&bytes);
+        assert_eq!(decoded.page_id, u64::MAX);
+        assert_eq!(decoded.page_offset, u32::MAX as u64);
+        assert_eq!(decoded.version, u64::MAX);
+        assert_eq!(decoded.length, u32::MAX as usize);
+
+        // The layout is fixed, not just self-consistent
+        let response = WriteResponse {
+            page_id: 1,
+            page_offset: 2,
+            version: 3,
+            length: 4,
+        };
+        let mut expected = [0; WriteResponse::ENCODED_SIZE];
+        expected[0] = 1;
+        expected[8] = 2;
+        expected[12] = 3;
+        expected[20] = 4;
+        assert_eq!(response.to_bytes(), expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "page offset exceeds u32")]
+    fn test_write_response_offset_overflow() {
+        let response = WriteResponse {
+            page_id: 0,
+            page_offset: u32::MAX as u64 + 1,
+            version: 0,
+            length: 0,
+        };
+        response.to_bytes();
+    }
 }
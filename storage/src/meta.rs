@@ -0,0 +1,119 @@
+use crate::{FifoFileCache, StorageError, Value, WriteResponse};
+
+/// Small fixed-size metadata stored in front of a value, see
+/// [`FifoFileCache::write_with_meta`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Meta {
+    // Milliseconds since the unix epoch, set by the caller
+    pub inserted_at_ms: u64,
+    pub source: u32,
+    pub flags: u32,
+}
+
+impl Meta {
+    /// Bytes taken by the header, it counts against the page size.
+    pub const SIZE: usize = 16;
+
+    fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.inserted_at_ms.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.source.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.flags.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+        Self {
+            inserted_at_ms: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            source: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+            flags: u32::from_le_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+}
+
+impl FifoFileCache {
+    /// Write a value with a metadata header, it has to be read back with
+    /// [`read_with_meta`](Self::read_with_meta).
+    pub fn write_with_meta<V: Value>(
+        &self,
+        value: V,
+        meta: Meta,
+    ) -> Result<WriteResponse, StorageError> {
+        let mut data = meta.to_bytes().to_vec();
+        bincode::serialize_into(&mut data, &value).map_err(StorageError::Serialize)?;
+        self.write_record(data)
+    }
+
+    pub fn read_with_meta<V: Value>(
+        &self,
+        request: &WriteResponse,
+    ) -> Result<Option<(V, Meta)>, StorageError> {
+        let Some(buffer) = self.read_record(request)? else {
+            return Ok(None);
+        };
+        let Some((header, payload)) = buffer.split_first_chunk::<{ Meta::SIZE }>() else {
+            return Err(StorageError::Deserialize {
+                length: buffer.len(),
+                source: Box::new(bincode::ErrorKind::Custom(
+                    "record is shorter than the meta header".into(),
+                )),
+            });
+        };
+        let value = crate::value::deserialize(payload)?;
+        Ok(Some((value, Meta::from_bytes(header))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::tests::TestValue;
+
+    #[test]
+    fn test_meta_round_trip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_meta_round_trip");
+        // The header and the value fill a page exactly
+        let page_size = Meta::SIZE + 8;
+        let cache = FifoFileCache::new(path, page_size, page_size * 2);
+
+        let meta = Meta {
+            inserted_at_ms: 1_700_000_000_000,
+            source: 7,
+            flags: 0b101,
+        };
+        let response = cache.write_with_meta(TestValue::from(42), meta).unwrap();
+        assert_eq!(response.length, page_size);
+        let (value, read_meta) = cache
+            .read_with_meta::<TestValue>(&response)
+            .unwrap()
+            .unwrap();
+        assert_eq!(value.value, 42);
+        assert_eq!(read_meta, meta);
+
+        // Recycling the page invalidates the entry like a plain write
+        cache.write_with_meta(TestValue::from(1), meta).unwrap();
+        cache.write_with_meta(TestValue::from(2), meta).unwrap();
+        assert!(cache
+            .read_with_meta::<TestValue>(&response)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_meta_counts_against_page() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_meta_counts_against_page");
+        let cache = FifoFileCache::new(path, 16, 16 * 2);
+        let result = cache.write_with_meta(TestValue::from(42), Meta::default());
+        assert!(matches!(
+            result,
+            Err(StorageError::ValueTooLarge {
+                size: 24,
+                limit: 16
+            })
+        ));
+    }
+}
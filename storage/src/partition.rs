@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+
+use crate::FifoFileCache;
+
+/// Several caches sharing one backing file, e.g. for values of different types or
+/// lifetimes, without a file per cache.
+///
+/// Each partition is a [`FifoFileCache`] over its own range of pages in the file, with
+/// its own page versions and write cursor. The partitions are isolated: the writes of
+/// one only ever recycle its own pages, so its churn can't evict the records of
+/// another. A [`WriteResponse`](crate::WriteResponse) is scoped to the partition that
+/// returned it and must only be read from that one. Each partition opens its own
+/// handles to the file, and the options keeping sidecar files next to it (persisted
+/// versions, history) aren't available.
+pub struct PartitionedCache {
+    partitions: Vec<(String, FifoFileCache)>,
+}
+
+impl PartitionedCache {
+    /// Lay out `partitions`, given as names and capacities, one after the other in the
+    /// file at `path`. Each capacity must be a multiple of `page_size` larger than a page.
+    pub fn new(path: PathBuf, page_size: usize, partitions: &[(&str, usize)]) -> Self {
+        let mut offset = 0;
+        let mut caches: Vec<(String, FifoFileCache)> = Vec::with_capacity(partitions.len());
+        for &(name, capacity) in partitions {
+            assert!(
+                caches.iter().all(|(existing, _)| existing != name),
+                "partition {} is declared twice",
+                name
+            );
+            let cache = FifoFileCache::builder(path.clone(), page_size, capacity)
+                .file_region(offset)
+                .build();
+            caches.push((name.to_string(), cache));
+            offset += capacity as u64;
+        }
+        Self { partitions: caches }
+    }
+
+    /// The partition named `name`, None if there's none.
+    pub fn partition(&self, name: &str) -> Option<&FifoFileCache> {
+        self.partitions
+            .iter()
+            .find(|(partition, _)| partition == name)
+            .map(|(_, cache)| cache)
+    }
+
+    /// The names of the partitions, in file order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.partitions.iter().map(|(name, _)| name.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::PartitionedCache;
+    use crate::tests::TestValue;
+    use crate::Storage;
+
+    #[test]
+    fn test_partitions_are_isolated() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_partitions");
+        let cache = PartitionedCache::new(path.clone(), 16, &[("hot", 16 * 2), ("cold", 16 * 3)]);
+        assert_eq!(cache.names().collect::<Vec<_>>(), vec!["hot", "cold"]);
+        assert!(cache.partition("warm").is_none());
+        let hot = cache.partition("hot").unwrap();
+        let cold = cache.partition("cold").unwrap();
+
+        let kept = cold.write(TestValue::from(1)).unwrap();
+        let first = hot.write(TestValue::from(2)).unwrap();
+        // Both start at their own first page
+        assert_eq!((kept.page_id, kept.page_offset), (0, 0));
+        assert_eq!((first.page_id, first.page_offset), (0, 0));
+
+        // The hot partition goes around its pages many times
+        for i in 0..100 {
+            hot.write(TestValue::from(i)).unwrap();
+        }
+        let value: Option<TestValue> = hot.read(&first).unwrap();
+        assert!(value.is_none());
+        let value: TestValue = cold.read(&kept).unwrap().unwrap();
+        assert_eq!(value.value, 1);
+        // The hot pages never spilled over the cold ones
+        let file = std::fs::read(&path).unwrap();
+        assert_eq!(file[32..40], 1u64.to_le_bytes());
+
+        // Scrubbing a partition leaves the others alone
+        hot.scrub_entire_file().unwrap();
+        let file = std::fs::read(&path).unwrap();
+        assert_eq!(file[..32], [0; 32]);
+        let value: TestValue = cold.read(&kept).unwrap().unwrap();
+        assert_eq!(value.value, 1);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{FifoFileCache, WriteResponse};
+
+/// The state of a [`WriteResponse`] according to [`FifoFileCache::peek`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeekResult {
+    // `length` is the size of the stored value, without the checksum footer
+    Valid { length: usize },
+    // A read misses: the page was recycled since the write, or the record was lost with
+    // the end of the file before the cache was reopened
+    Stale,
+    // The response doesn't point inside the cache, it can't come from this cache
+    OutOfBounds,
+    // The page was retired after repeated I/O errors, a read fails
+    Retired,
+}
+
+impl FifoFileCache {
+    /// Check whether `request` can still be read, without touching the disk.
+    ///
+    /// `Valid` is a point-in-time answer: the page can be recycled right after, and a
+    /// read following it can still miss. The checksum isn't verified either, as that
+    /// needs the record's bytes. Peeks are not counted in the stats.
+    pub fn peek(&self, request: &WriteResponse) -> PeekResult {
+        // The checks of a read, in the same order
+        if self.verify_write_response(request).is_err() {
+            return PeekResult::OutOfBounds;
+        }
+        if self
+            .recovered
+            .as_ref()
+            .is_some_and(|recovered| recovered.is_unwritten(request))
+        {
+            return PeekResult::Stale;
+        }
+        if self.health.is_retired(request.page_id) {
+            return PeekResult::Retired;
+        }
+        let version =
+            self.pages[request.page_id as usize].load(std::sync::atomic::Ordering::Relaxed);
+        if version != request.version {
+            return PeekResult::Stale;
+        }
+        let footer = self.checksum.as_ref().map_or(0, |checksum| checksum.size());
+        PeekResult::Valid {
+            length: request.length.saturating_sub(footer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::PeekResult;
+    use crate::tests::TestValue;
+    use crate::{Crc32, FifoFileCache, Storage, WriteResponse};
+
+    #[test]
+    fn test_peek() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_peek"), 12, 12 * 2)
+            .checksum(Crc32)
+            .build();
+        let response = cache.write(TestValue::from(1)).unwrap();
+        assert_eq!(cache.peek(&response), PeekResult::Valid { length: 8 });
+
+        let out_of_bounds = WriteResponse {
+            page_id: 2,
+            ..response.clone()
+        };
+        assert_eq!(cache.peek(&out_of_bounds), PeekResult::OutOfBounds);
+        let past_page_end = WriteResponse {
+            page_offset: 8,
+            ..response.clone()
+        };
+        assert_eq!(cache.peek(&past_page_end), PeekResult::OutOfBounds);
+        let overflowing = WriteResponse {
+            page_offset: u64::MAX - 2,
+            ..response.clone()
+        };
+        assert_eq!(cache.peek(&overflowing), PeekResult::OutOfBounds);
+
+        cache.write(TestValue::from(2)).unwrap();
+        cache.write(TestValue::from(3)).unwrap();
+        assert_eq!(cache.peek(&response), PeekResult::Stale);
+        // Peeking doesn't count as a read
+        assert_eq!(cache.stats().read_hits + cache.stats().read_misses, 0);
+    }
+
+    #[test]
+    fn test_peek_like_read() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_peek_like_read");
+        let checkpoint = {
+            let cache = FifoFileCache::new(path.clone(), 16, 16 * 4);
+            cache.write(TestValue::from(1)).unwrap();
+            cache.checkpoint()
+        };
+        let cursor = (checkpoint.page_id, checkpoint.page_offset);
+        let cache = FifoFileCache::open_with_versions(path, 16, 16 * 4, vec![0; 4], cursor);
+        // Never written before the reopen, a read misses
+        let unwritten = WriteResponse {
+            page_id: 2,
+            page_offset: 0,
+            version: 0,
+            length: 8,
+        };
+        assert_eq!(cache.peek(&unwritten), PeekResult::Stale);
+        let value: Option<TestValue> = cache.read(&unwritten).unwrap();
+        assert!(value.is_none());
+
+        let path = dir.path().join("test_peek_retired");
+        let cache = FifoFileCache::builder(path, 16, 16 * 4)
+            .retire_after_errors(1)
+            .build();
+        let response = cache.write(TestValue::from(2)).unwrap();
+        cache.health.record_error(response.page_id);
+        assert_eq!(cache.peek(&response), PeekResult::Retired);
+        let result: Result<Option<TestValue>, _> = cache.read(&response);
+        assert!(result.is_err());
+    }
+}
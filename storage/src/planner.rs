@@ -0,0 +1,129 @@
+/// Estimates the cache size needed for a target hit rate under Zipf traffic.
+///
+/// It uses Che's characteristic time approximation in its FIFO form: with requests
+/// independent of each other, an item of popularity `p` is cached with probability
+/// `p * T / (1 + p * T)` for some time `T`, and the cache holds the sum of these
+/// probabilities. Whole pages are recycled at once, so the cache behaves like a FIFO of
+/// slightly less than its capacity, see [`pages_needed`](Self::pages_needed).
+pub struct CapacityPlanner {
+    // The request probability of each item, most popular first
+    popularity: Vec<f64>,
+    avg_value_size_bytes: usize,
+}
+
+impl CapacityPlanner {
+    pub fn new(zipf_exponent: f64, population_size: usize, avg_value_size_bytes: usize) -> Self {
+        assert!(zipf_exponent >= 0.0, "zipf exponent should not be negative");
+        assert!(population_size > 0, "population size should not be 0");
+        let weights: Vec<f64> = (1..=population_size)
+            .map(|rank| (rank as f64).powf(-zipf_exponent))
+            .collect();
+        let total: f64 = weights.iter().sum();
+        Self {
+            popularity: weights.into_iter().map(|weight| weight / total).collect(),
+            avg_value_size_bytes,
+        }
+    }
+
+    // The hit rate and the number of cached items for a characteristic time
+    fn evaluate(&self, characteristic_time: f64) -> (f64, f64) {
+        self.popularity
+            .iter()
+            .fold((0.0, 0.0), |(hit_rate, items), &p| {
+                let cached = p * characteristic_time / (1.0 + p * characteristic_time);
+                (hit_rate + p * cached, items + cached)
+            })
+    }
+
+    /// The bytes of values to cache to get `target_hit_rate`, at most the whole
+    /// population.
+    pub fn capacity_for_hit_rate(&self, target_hit_rate: f64) -> usize {
+        assert!(
+            (0.0..=1.0).contains(&target_hit_rate),
+            "target hit rate should be in [0, 1]"
+        );
+        let population = self.popularity.len();
+        // The hit rate grows with the characteristic time, find it by bisection
+        let mut high = 1.0;
+        while self.evaluate(high).0 < target_hit_rate {
+            high *= 2.0;
+            if high > 1e18 {
+                return population * self.avg_value_size_bytes;
+            }
+        }
+        let mut low = 0.0;
+        for _ in 0..100 {
+            let middle = (low + high) / 2.0;
+            if self.evaluate(middle).0 < target_hit_rate {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        let items = self.evaluate(high).1.ceil() as usize;
+        items.min(population) * self.avg_value_size_bytes
+    }
+
+    /// The pages of `page_size` bytes to get `target_hit_rate`. The page being recycled
+    /// holds nothing, so there is one more than the pages of values.
+    pub fn pages_needed(&self, page_size: usize, target_hit_rate: f64) -> usize {
+        assert!(page_size > 0);
+        self.capacity_for_hit_rate(target_hit_rate)
+            .div_ceil(page_size)
+            + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use rand::{Rng, SeedableRng};
+
+    use super::CapacityPlanner;
+
+    // The hit rate of a FIFO of `capacity` items under Zipf(1.0) requests
+    fn simulate_fifo(population: usize, capacity: usize, requests: usize) -> f64 {
+        let mut cdf = Vec::with_capacity(population);
+        let mut total = 0.0;
+        for rank in 1..=population {
+            total += 1.0 / rank as f64;
+            cdf.push(total);
+        }
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let mut cached = vec![false; population];
+        let mut queue = VecDeque::with_capacity(capacity);
+        let mut hits = 0;
+        for _ in 0..requests {
+            let sample = rng.gen::<f64>() * total;
+            let item = cdf.partition_point(|&c| c < sample).min(population - 1);
+            if cached[item] {
+                hits += 1;
+                continue;
+            }
+            if queue.len() == capacity {
+                let evicted: usize = queue.pop_front().unwrap();
+                cached[evicted] = false;
+            }
+            queue.push_back(item);
+            cached[item] = true;
+        }
+        hits as f64 / requests as f64
+    }
+
+    #[test]
+    fn test_capacity_for_zipf() {
+        let planner = CapacityPlanner::new(1.0, 10_000, 1024);
+        let capacity = planner.capacity_for_hit_rate(0.9);
+        let items = capacity / 1024;
+        // The capacity reaching 90% in a simulated FIFO is within 10% of the estimate
+        assert!(simulate_fifo(10_000, items * 9 / 10, 1_000_000) < 0.9);
+        assert!(simulate_fifo(10_000, items * 11 / 10, 1_000_000) > 0.9);
+
+        assert_eq!(planner.capacity_for_hit_rate(1.0), 10_000 * 1024);
+        assert_eq!(
+            planner.pages_needed(1024 * 16, 0.9),
+            capacity.div_ceil(1024 * 16) + 1
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::FifoFileCache;
+
+pub(crate) const DEFAULT_ALPHA: f64 = 0.01;
+
+/// An exponential moving average of the read hit rate, with a rough forecast of when a
+/// filling cache reaches a target hit rate.
+///
+/// The forecast assumes the hit rate grows in proportion to the filled fraction of the
+/// cache until it's full, which holds for a uniform working set larger than the cache.
+pub struct HitRatePredictor {
+    alpha: f64,
+    // The f64 bits of the average
+    current_ema: AtomicU64,
+    capacity_bytes: u64,
+    inserted_items: AtomicU64,
+    inserted_bytes: AtomicU64,
+}
+
+impl HitRatePredictor {
+    /// `alpha` in (0, 1] is the weight of each read, the higher the faster it follows.
+    pub fn new(alpha: f64, capacity_bytes: u64) -> Self {
+        assert!(alpha > 0.0 && alpha <= 1.0, "alpha should be in (0, 1]");
+        Self {
+            alpha,
+            current_ema: AtomicU64::new(0f64.to_bits()),
+            capacity_bytes,
+            inserted_items: AtomicU64::new(0),
+            inserted_bytes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_read(&self, hit: bool) {
+        let sample = if hit { 1.0 } else { 0.0 };
+        let _ = self
+            .current_ema
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                let ema = f64::from_bits(bits);
+                Some((self.alpha * sample + (1.0 - self.alpha) * ema).to_bits())
+            });
+    }
+
+    pub fn record_insert(&self, bytes: u64) {
+        self.inserted_items.fetch_add(1, Ordering::Relaxed);
+        self.inserted_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn predicted_hit_rate(&self) -> f64 {
+        f64::from_bits(self.current_ema.load(Ordering::Relaxed))
+    }
+
+    /// When the hit rate should reach `target` if items keep being inserted at
+    /// `current_fill_rate_items_per_sec`. `Some(Duration::ZERO)` if it's already there,
+    /// `None` if it shouldn't get there before the cache is full, or if the cache isn't
+    /// filling.
+    pub fn time_to_reach_target(
+        &self,
+        target_hit_rate: f64,
+        current_fill_rate_items_per_sec: f64,
+    ) -> Option<Duration> {
+        let ema = self.predicted_hit_rate();
+        if ema >= target_hit_rate {
+            return Some(Duration::ZERO);
+        }
+        let items = self.inserted_items.load(Ordering::Relaxed);
+        let filled = self
+            .inserted_bytes
+            .load(Ordering::Relaxed)
+            .min(self.capacity_bytes) as f64;
+        if items == 0 || ema <= 0.0 || current_fill_rate_items_per_sec <= 0.0 {
+            return None;
+        }
+        let needed = filled * target_hit_rate / ema;
+        if needed > self.capacity_bytes as f64 {
+            return None;
+        }
+        let item_size = self.inserted_bytes.load(Ordering::Relaxed) as f64 / items as f64;
+        let seconds = (needed - filled) / item_size / current_fill_rate_items_per_sec;
+        Some(Duration::from_secs_f64(seconds))
+    }
+}
+
+// Only the moving average is meaningful without a capacity
+impl Default for HitRatePredictor {
+    fn default() -> Self {
+        Self::new(DEFAULT_ALPHA, 0)
+    }
+}
+
+impl FifoFileCache {
+    /// The hit rate predictor fed by this cache's reads and writes, its alpha is set with
+    /// [`FifoFileCacheBuilder::hit_rate_alpha`](crate::FifoFileCacheBuilder::hit_rate_alpha).
+    pub fn hit_rate_predictor(&self) -> &HitRatePredictor {
+        &self.stats.hit_rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::HitRatePredictor;
+
+    #[test]
+    fn test_ema_converges() {
+        let predictor = HitRatePredictor::new(0.05, 1024);
+        let mut previous = 0.0;
+        // 10 phases of 100 reads, the true hit rate goes from 0 to 0.9
+        for phase in 0..10 {
+            for i in 0..100 {
+                predictor.record_read(i % 10 < phase);
+            }
+            let ema = predictor.predicted_hit_rate();
+            assert!(ema >= previous, "{} after {}", ema, previous);
+            previous = ema;
+        }
+        let ema = predictor.predicted_hit_rate();
+        assert!((ema - 0.9).abs() < 0.05, "{}", ema);
+    }
+
+    #[test]
+    fn test_time_to_reach_target() {
+        let predictor = HitRatePredictor::new(0.4, 1000);
+        assert_eq!(predictor.time_to_reach_target(0.5, 10.0), None);
+
+        // Half full with a 40% hit rate, it should reach 80% once full
+        for _ in 0..50 {
+            predictor.record_insert(10);
+        }
+        predictor.record_read(true);
+        assert_eq!(
+            predictor.time_to_reach_target(0.3, 10.0),
+            Some(Duration::ZERO)
+        );
+        // 60% needs 750 bytes, 25 more items at 10 items per second
+        let time = predictor.time_to_reach_target(0.6, 10.0).unwrap();
+        assert!((time.as_secs_f64() - 2.5).abs() < 1e-9, "{:?}", time);
+        assert_eq!(predictor.time_to_reach_target(0.9, 10.0), None);
+        assert_eq!(predictor.time_to_reach_target(0.6, 0.0), None);
+    }
+}
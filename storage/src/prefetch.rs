@@ -0,0 +1,175 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Mutex, OnceLock};
+
+use crate::task::ShutdownToken;
+use crate::{FifoFileCache, PageID, PageOffset, WriteResponse};
+
+type RecordKey = (PageID, PageOffset, u64);
+
+// Prefetched records are forgotten once this many are waiting for a read, the ones left
+// on recycled pages are dropped first
+const MAX_PENDING: usize = 4096;
+
+#[derive(Default)]
+pub(crate) struct Prefetcher {
+    // Pages to warm, sent to a background thread spawned on the first prefetch. None if
+    // the thread couldn't be started, prefetching is then a no-op.
+    pages: OnceLock<Option<Sender<Vec<u64>>>>,
+    // Records prefetched but not read yet, to count the useful prefetches
+    pending: Mutex<HashSet<RecordKey>>,
+    pending_count: AtomicUsize,
+}
+
+// `pages` are the offsets of the pages in the file
+fn warm_pages(file: &File, page_size: u64, pages: Vec<u64>) {
+    for page_start in pages {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::io::AsRawFd;
+            // Only starts the readahead, errors just mean no prefetch
+            unsafe {
+                libc::posix_fadvise(
+                    file.as_raw_fd(),
+                    page_start as libc::off_t,
+                    page_size as libc::off_t,
+                    libc::POSIX_FADV_WILLNEED,
+                );
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            use std::os::unix::fs::FileExt;
+            let mut buffer = vec![0; page_size as usize];
+            let _ = file.read_at(&mut buffer, page_start);
+        }
+    }
+}
+
+impl Prefetcher {
+    // Let the thread see the shutdown
+    pub(crate) fn wake(&self) {
+        if let Some(Some(sender)) = self.pages.get() {
+            let _ = sender.send(Vec::new());
+        }
+    }
+
+    // Count a read hit, return whether the record was prefetched
+    pub(crate) fn note_hit(&self, request: &WriteResponse) -> bool {
+        if self.pending_count.load(Ordering::Relaxed) == 0 {
+            return false;
+        }
+        let mut pending = self.pending.lock().unwrap();
+        let found = pending.remove(&(request.page_id, request.page_offset, request.version));
+        self.pending_count.store(pending.len(), Ordering::Relaxed);
+        found
+    }
+}
+
+impl FifoFileCache {
+    /// Warm the pages of `requests` into the page cache in the background, so the reads
+    /// that follow hit memory. Stale requests are skipped. It never blocks on I/O, see
+    /// `prefetch_issued` and `prefetch_useful` in [`stats`](Self::stats) to tell whether
+    /// it pays off.
+    pub fn prefetch(&self, requests: &[WriteResponse]) {
+        let live: Vec<&WriteResponse> = requests
+            .iter()
+            .filter(|request| {
+                self.pages
+                    .get(request.page_id as usize)
+                    .is_some_and(|version| {
+                        version.load(std::sync::atomic::Ordering::Relaxed) == request.version
+                    })
+            })
+            .collect();
+        let sender = self.prefetcher.pages.get_or_init(|| {
+            let file = self.read_file.try_clone().ok()?;
+            let page_size = self.page_size as u64;
+            let (sender, receiver) = channel::<Vec<u64>>();
+            self.tasks.spawn(
+                "cache-prefetch".to_string(),
+                move |token: &ShutdownToken| {
+                    for pages in receiver {
+                        token.fail_point("prefetch");
+                        if token.is_shutdown() {
+                            return;
+                        }
+                        warm_pages(&file, page_size, pages);
+                    }
+                },
+            )?;
+            Some(sender)
+        });
+        let Some(sender) = sender else {
+            return;
+        };
+        if live.is_empty() {
+            return;
+        }
+
+        let mut pages: Vec<PageID> = live.iter().map(|request| request.page_id).collect();
+        pages.sort_unstable();
+        pages.dedup();
+        let pages = pages
+            .into_iter()
+            .map(|page_id| self.page_start(page_id))
+            .collect();
+        if sender.send(pages).is_err() {
+            return;
+        }
+        self.stats.record_prefetch(live.len() as u64);
+        let mut pending = self.prefetcher.pending.lock().unwrap();
+        if pending.len() + live.len() > MAX_PENDING {
+            pending.retain(|(page_id, _, version)| {
+                self.pages[*page_id as usize].load(std::sync::atomic::Ordering::Relaxed) == *version
+            });
+        }
+        if pending.len() + live.len() > MAX_PENDING {
+            pending.clear();
+        }
+        pending.extend(
+            live.iter()
+                .map(|request| (request.page_id, request.page_offset, request.version)),
+        );
+        self.prefetcher
+            .pending_count
+            .store(pending.len(), Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, PeekResult, Storage, WriteResponse};
+
+    #[test]
+    fn test_prefetch_counters() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_prefetch"), 16, 16 * 3);
+        let stale = cache.write(TestValue::from(0)).unwrap();
+        let responses: Vec<WriteResponse> = (1..7)
+            .map(|i| cache.write(TestValue::from(i)).unwrap())
+            .collect();
+        // The first page was recycled by the seventh write
+        assert_eq!(cache.peek(&stale), PeekResult::Stale);
+
+        cache.prefetch(&[stale, responses[3].clone(), responses[4].clone()]);
+        let stats = cache.stats();
+        assert_eq!(stats.prefetch_issued, 2);
+        assert_eq!(stats.prefetch_useful, 0);
+
+        for response in &responses[2..] {
+            let value: TestValue = cache.read(response).unwrap().unwrap();
+            assert!(value.value >= 3);
+        }
+        // Only the first read of a prefetched record counts
+        let _: Option<TestValue> = cache.read(&responses[3]).unwrap();
+        let stats = cache.stats();
+        assert_eq!(stats.prefetch_issued, 2);
+        assert_eq!(stats.prefetch_useful, 2);
+    }
+}
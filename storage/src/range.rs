@@ -0,0 +1,98 @@
+use crate::{FifoFileCache, StorageError, WriteResponse};
+
+impl FifoFileCache {
+    /// Write `bytes` as is, without serializing them, so [`read_range`](Self::read_range)
+    /// can address them by offset.
+    pub fn write_bytes(&self, bytes: Vec<u8>) -> Result<WriteResponse, StorageError> {
+        self.write_record(bytes)
+    }
+
+    /// Read `[offset, offset + len)` of a record written with
+    /// [`write_bytes`](Self::write_bytes), without reading the rest of it. Like an HTTP
+    /// range, the range is cut at the end of the record, and is empty if `offset` is past
+    /// it. Returns None if the page was recycled.
+    ///
+    /// Offsets are into the stored bytes, so this only makes sense for records stored
+    /// verbatim: a value written with [`write`](crate::Storage::write) is serialized
+    /// first, and so are the bytes of a cache with [transforms](crate::RecordTransform),
+    /// the range addresses the transformed bytes. The checksum footer is never part of
+    /// the range, and it isn't verified as that needs the whole record.
+    pub fn read_range(
+        &self,
+        request: &WriteResponse,
+        offset: usize,
+        len: usize,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        if !self.check_request(request)? {
+            return Ok(None);
+        }
+        let footer = self.checksum.as_ref().map_or(0, |checksum| checksum.size());
+        let stored = request.length.saturating_sub(footer);
+        let start = offset.min(stored);
+        let end = offset.saturating_add(len).min(stored);
+        let mut buffer = vec![0; end - start];
+        let page_offset = request.page_offset + start as u64;
+        self.read_exact_at(&mut buffer, request.page_id, page_offset)?;
+        // Check the version after the read, as in `verify_record`
+        let page_version =
+            self.pages[request.page_id as usize].load(std::sync::atomic::Ordering::Relaxed);
+        if page_version != request.version {
+            self.stats.record_miss(buffer.len());
+            return Ok(None);
+        }
+        self.stats.record_hit(buffer.len());
+        self.record_access(request.page_id);
+        Ok(Some(buffer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::{Crc32, FifoFileCache};
+
+    #[test]
+    fn test_read_range() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_read_range"), 32, 32 * 2)
+            .checksum(Crc32)
+            .build();
+        let response = cache.write_bytes((0..10).collect()).unwrap();
+        // The next record follows on the same page, it must not leak into the range
+        cache.write_bytes(vec![0xff; 10]).unwrap();
+
+        let range = cache.read_range(&response, 2, 3).unwrap().unwrap();
+        assert_eq!(range, vec![2, 3, 4]);
+        // Crossing the end of the record, the footer isn't returned
+        let range = cache.read_range(&response, 7, 100).unwrap().unwrap();
+        assert_eq!(range, vec![7, 8, 9]);
+        let range = cache.read_range(&response, 0, usize::MAX).unwrap().unwrap();
+        assert_eq!(range, (0..10).collect::<Vec<u8>>());
+        assert!(cache
+            .read_range(&response, 10, 4)
+            .unwrap()
+            .unwrap()
+            .is_empty());
+        assert!(cache
+            .read_range(&response, 50, 4)
+            .unwrap()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_read_range_unwritten_tail() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_read_range_tail"), 16, 16 * 2);
+        // Nothing was written after the record, the file ends with it
+        let response = cache.write_bytes(vec![1, 2, 3, 4]).unwrap();
+        let range = cache.read_range(&response, 2, 16).unwrap().unwrap();
+        assert_eq!(range, vec![3, 4]);
+
+        // Recycle the page
+        cache.write_bytes(vec![0; 16]).unwrap();
+        cache.write_bytes(vec![0; 16]).unwrap();
+        assert_eq!(cache.read_range(&response, 0, 2).unwrap(), None);
+    }
+}
@@ -0,0 +1,127 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::workload::WorkloadRng;
+use crate::{FifoFileCache, WriteResponse};
+
+// The size of the file the benchmark writes over, rounded down to whole pages
+const RAW_IO_CAPACITY: usize = 64 << 20;
+
+/// The bandwidth and IOPS measured by [`FifoFileCache::bench_raw_io`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawIoBenchResult {
+    pub seq_write_mbps: f64,
+    pub seq_read_mbps: f64,
+    /// Thousands of page reads per second, at random pages.
+    pub rand_read_kiops: f64,
+}
+
+impl FifoFileCache {
+    /// Measure the device under `path` with the cache's own write and read paths, to pick
+    /// the page size: large pages suit a device good at sequential I/O, small pages one
+    /// good at random reads. Each pass runs for `duration_secs` seconds:
+    ///
+    /// 1. sequential writes of one record per page, wrapping around a file of up to
+    ///    64 MiB,
+    /// 2. sequential reads of the pages, from the oldest,
+    /// 3. reads of random pages.
+    ///
+    /// The file at `path` is unlinked once opened. On Linux the written pages are dropped
+    /// from the page cache before the reads, elsewhere the reads may be served from
+    /// memory. It panics on I/O errors, like the builder.
+    pub fn bench_raw_io(path: &Path, page_size: usize, duration_secs: u64) -> RawIoBenchResult {
+        let page_num = (RAW_IO_CAPACITY / page_size).max(2);
+        let cache = FifoFileCache::builder(path.to_path_buf(), page_size, page_num * page_size)
+            .ephemeral(true)
+            .build();
+        let duration = Duration::from_secs(duration_secs);
+        let mb_per_sec =
+            |bytes: u64, elapsed: Duration| bytes as f64 / (1 << 20) as f64 / elapsed.as_secs_f64();
+
+        // The response of each page, the last lap overwrote the older ones
+        let mut pages: Vec<Option<WriteResponse>> = vec![None; page_num];
+        let mut rng = WorkloadRng::new(0);
+        let page: Vec<u8> = (0..page_size).map(|_| rng.next_u64() as u8).collect();
+        let start = Instant::now();
+        let mut written = 0;
+        while start.elapsed() < duration || written == 0 {
+            let response = cache
+                .write_bytes(page.clone())
+                .expect("Failed to write page");
+            written += page_size as u64;
+            let page_id = response.page_id as usize;
+            pages[page_id] = Some(response);
+        }
+        let seq_write_mbps = mb_per_sec(written, start.elapsed());
+        cache.drop_page_cache();
+
+        // From the page after the cursor, the oldest
+        let oldest = (cache.lock_manager().write_page_id as usize + 1) % page_num;
+        let live: Vec<WriteResponse> = (0..page_num)
+            .filter_map(|step| pages[(oldest + step) % page_num].clone())
+            .collect();
+        let read = |response: &WriteResponse| {
+            cache
+                .read_record(response)
+                .expect("Failed to read page")
+                .expect("the pages written are live");
+        };
+        let start = Instant::now();
+        let mut bytes_read = 0;
+        for response in live.iter().cycle() {
+            if start.elapsed() >= duration && bytes_read > 0 {
+                break;
+            }
+            read(response);
+            bytes_read += page_size as u64;
+        }
+        let seq_read_mbps = mb_per_sec(bytes_read, start.elapsed());
+        cache.drop_page_cache();
+
+        let start = Instant::now();
+        let mut reads = 0;
+        while start.elapsed() < duration || reads == 0 {
+            read(&live[rng.below(live.len() as u64) as usize]);
+            reads += 1;
+        }
+        let rand_read_kiops = reads as f64 / 1000.0 / start.elapsed().as_secs_f64();
+
+        RawIoBenchResult {
+            seq_write_mbps,
+            seq_read_mbps,
+            rand_read_kiops,
+        }
+    }
+
+    // Write back and evict the pages of the file from the page cache, so the next reads
+    // go to the device
+    fn drop_page_cache(&self) {
+        self.read_file.sync_data().expect("Failed to sync file");
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::io::AsRawFd;
+            // Only advice, an error means the reads may hit the page cache
+            unsafe {
+                libc::posix_fadvise(self.read_file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::FifoFileCache;
+
+    #[test]
+    fn test_bench_raw_io() {
+        let dir = tempdir().unwrap();
+        let result = FifoFileCache::bench_raw_io(&dir.path().join("test_bench_raw_io"), 4096, 1);
+        assert!(result.seq_write_mbps > 0.0);
+        assert!(result.seq_read_mbps > 0.0);
+        assert!(result.rand_read_kiops > 0.0);
+        // The file is unlinked
+        assert!(!dir.path().join("test_bench_raw_io").exists());
+    }
+}
@@ -0,0 +1,168 @@
+// The read handles held on each page, the writer waits for a page's handles to be
+// dropped before recycling it.
+//
+// The writer waits with the write lock held, so a holder of a handle must not take the
+// write lock: reads don't, as the write buffer is flushed before the wait and stays
+// empty until it's over, but writes do. The registry lock is only taken after the write
+// lock, never the other way around.
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::{Condvar, Mutex};
+
+use crate::{FifoFileCache, PageID, WriteResponse};
+
+pub(crate) struct ReaderRegistry {
+    counts: Box<[AtomicUsize]>,
+    // Taken to wait for a count to reach 0 and to signal it, so no release is missed
+    lock: Mutex<()>,
+    released: Condvar,
+}
+
+impl ReaderRegistry {
+    pub(crate) fn new(page_num: usize) -> Self {
+        Self {
+            counts: (0..page_num).map(|_| AtomicUsize::new(0)).collect(),
+            lock: Mutex::new(()),
+            released: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn count(&self, page_id: PageID) -> usize {
+        self.counts[page_id as usize].load(std::sync::atomic::Ordering::Acquire)
+    }
+
+    fn acquire(&self, page_id: PageID) {
+        self.counts[page_id as usize].fetch_add(1, std::sync::atomic::Ordering::AcqRel);
+    }
+
+    fn release(&self, page_id: PageID) {
+        if self.counts[page_id as usize].fetch_sub(1, std::sync::atomic::Ordering::AcqRel) == 1 {
+            let _lock = self.lock.lock().unwrap();
+            self.released.notify_all();
+        }
+    }
+
+    // Block until no handle is held on the page
+    pub(crate) fn wait_released(&self, page_id: PageID) {
+        if self.count(page_id) == 0 {
+            return;
+        }
+        let mut lock = self.lock.lock().unwrap();
+        while self.count(page_id) > 0 {
+            lock = self.released.wait(lock).unwrap();
+        }
+    }
+}
+
+/// Keeps the page of a record from being recycled while it's held, see
+/// [`FifoFileCache::acquire_read_handle`].
+pub struct ReadHandle<'a> {
+    cache: &'a FifoFileCache,
+    request: WriteResponse,
+}
+
+impl ReadHandle<'_> {
+    pub fn request(&self) -> &WriteResponse {
+        &self.request
+    }
+}
+
+impl Drop for ReadHandle<'_> {
+    fn drop(&mut self) {
+        self.cache.readers.release(self.request.page_id);
+    }
+}
+
+impl FifoFileCache {
+    /// Hold the page of `request` until the handle is dropped, so its records can be read
+    /// any number of times without being recycled in between. A record recycled before
+    /// the call stays a miss.
+    ///
+    /// The writer that reaches a held page waits for its handles with the write lock
+    /// held, which stalls every write meanwhile. A thread must not write while holding a
+    /// handle, it may wait for itself. The request must come from this cache.
+    pub fn acquire_read_handle(&self, request: WriteResponse) -> ReadHandle<'_> {
+        assert!(
+            request.page_id < self.pages.len() as u64,
+            "the request should come from this cache"
+        );
+        self.readers.acquire(request.page_id);
+        ReadHandle {
+            cache: self,
+            request,
+        }
+    }
+
+    /// The read handles currently held on the page.
+    pub fn outstanding_readers(&self, page_id: PageID) -> usize {
+        self.readers.count(page_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage};
+
+    #[test]
+    fn test_read_handle_with_write_buffer() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_read_handle_buffer"), 16, 16 * 3)
+            .write_buffer(16)
+            .build();
+        let response = cache.write(TestValue::from(1)).unwrap();
+        let handle = cache.acquire_read_handle(response);
+        // The last one stays in the write buffer
+        let last = (2..=6)
+            .map(|i| cache.write(TestValue::from(i)).unwrap())
+            .last()
+            .unwrap();
+
+        std::thread::scope(|scope| {
+            let writer = scope.spawn(|| cache.write(TestValue::from(7)).unwrap());
+            std::thread::sleep(Duration::from_millis(100));
+            // The writer waits for the handle, the buffered record is read without the lock
+            let value: TestValue = cache.read(&last).unwrap().unwrap();
+            assert_eq!(value.value, 6);
+            drop(handle);
+            let response = writer.join().unwrap();
+            assert_eq!(response.page_id, 0);
+        });
+    }
+
+    #[test]
+    fn test_read_handle_blocks_writer() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_read_handle"), 16, 16 * 3);
+        let response = cache.write(TestValue::from(1)).unwrap();
+        let handle = cache.acquire_read_handle(response);
+        assert_eq!(cache.outstanding_readers(0), 1);
+        // Fill the other pages, the next switch goes back to page 0
+        for i in 0..5 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+
+        let done = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            let writer = scope.spawn(|| {
+                let response = cache.write(TestValue::from(7)).unwrap();
+                done.store(true, Ordering::Release);
+                response
+            });
+            std::thread::sleep(Duration::from_millis(100));
+            assert!(!done.load(Ordering::Acquire));
+            let value: TestValue = cache.read(handle.request()).unwrap().unwrap();
+            assert_eq!(value.value, 1);
+
+            drop(handle);
+            let response = writer.join().unwrap();
+            assert_eq!(response.page_id, 0);
+        });
+        assert_eq!(cache.outstanding_readers(0), 0);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{PageID, PageOffset, WriteResponse};
+
+// What a cache opened with an initial state knows of the records written before. The file
+// may be shorter than the capacity, e.g. the cache never filled it before the shutdown, so
+// the bytes of a page still at the version it was opened with are only those in the file,
+// up to the cursor on its page. Reads past them are misses instead of short reads.
+pub(crate) struct RecoveredExtents {
+    // The version of each page at open
+    versions: Vec<u64>,
+    // The bytes of each page that were written at open
+    extents: Vec<u64>,
+    cursor: (PageID, PageOffset),
+}
+
+impl RecoveredExtents {
+    pub(crate) fn new(
+        versions: &[u64],
+        cursor: (PageID, PageOffset),
+        page_size: usize,
+        file_len: u64,
+    ) -> Self {
+        let page_size = page_size as u64;
+        let extents = (0..versions.len() as u64)
+            .map(|page_id| {
+                let extent = file_len.saturating_sub(page_id * page_size).min(page_size);
+                match page_id == cursor.0 {
+                    true => extent.min(cursor.1),
+                    false => extent,
+                }
+            })
+            .collect();
+        Self {
+            versions: versions.to_vec(),
+            extents,
+            cursor,
+        }
+    }
+
+    // Whether `request` points past the bytes written before open, in a page that wasn't
+    // recycled since
+    pub(crate) fn is_unwritten(&self, request: &WriteResponse) -> bool {
+        let page = request.page_id as usize;
+        if request.version != self.versions[page] {
+            return false;
+        }
+        // The cursor page keeps its version, past the cursor are the records written since
+        if request.page_id == self.cursor.0 && request.page_offset >= self.cursor.1 {
+            return false;
+        }
+        request.page_offset + request.length as u64 > self.extents[page]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage, WriteResponse};
+
+    #[test]
+    fn test_open_short_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_open_short_file");
+        // Three values over the first two of four pages, the file is 24 bytes long
+        let (responses, checkpoint) = {
+            let cache = FifoFileCache::new(path.clone(), 16, 16 * 4);
+            let responses: Vec<_> = (0..3)
+                .map(|i| cache.write(TestValue::from(i)).unwrap())
+                .collect();
+            (responses, cache.checkpoint())
+        };
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 24);
+        let versions = vec![0, 1, 0, 0];
+        let cursor = (checkpoint.page_id, checkpoint.page_offset);
+        let cache = FifoFileCache::open_with_versions(path.clone(), 16, 16 * 4, versions, cursor);
+        for (i, response) in responses.iter().enumerate() {
+            let value: TestValue = cache.read(response).unwrap().unwrap();
+            assert_eq!(value.value, i as u64);
+        }
+
+        // The pages past the cursor were never written
+        for page_id in 2..4 {
+            let request = WriteResponse {
+                page_id,
+                page_offset: 0,
+                version: 0,
+                length: 8,
+            };
+            let value: Option<TestValue> = cache.read(&request).unwrap();
+            assert!(value.is_none());
+        }
+        assert_eq!(cache.stats().read_misses, 2);
+
+        // Writes resume at the cursor and are readable
+        let response = cache.write(TestValue::from(3)).unwrap();
+        assert_eq!((response.page_id, response.page_offset), (1, 8));
+        let value: TestValue = cache.read(&response).unwrap().unwrap();
+        assert_eq!(value.value, 3);
+        drop(cache);
+
+        // A record lost with the end of the file is a miss too
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(20)
+            .unwrap();
+        let cache = FifoFileCache::open_with_versions(path, 16, 16 * 4, vec![0, 1, 0, 0], (1, 16));
+        let value: Option<TestValue> = cache.read(&responses[2]).unwrap();
+        assert!(value.is_none());
+        let value: TestValue = cache.read(&responses[0]).unwrap().unwrap();
+        assert_eq!(value.value, 0);
+    }
+
+    #[test]
+    fn test_open_short_file_other_reads() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_open_short_file_other_reads");
+        let (written, checkpoint) = {
+            let cache = FifoFileCache::new(path.clone(), 16, 16 * 4);
+            let written = cache.write_bytes(vec![7; 8]).unwrap();
+            (written, cache.checkpoint())
+        };
+        let cursor = (checkpoint.page_id, checkpoint.page_offset);
+        let cache = FifoFileCache::open_with_versions(path, 16, 16 * 4, vec![0; 4], cursor);
+        let unwritten = WriteResponse {
+            page_id: 2,
+            page_offset: 0,
+            version: 0,
+            length: 8,
+        };
+
+        // Every read path misses instead of a short read
+        let value: Option<TestValue> = cache
+            .read_with_deadline(&unwritten, Duration::from_secs(10))
+            .unwrap();
+        assert!(value.is_none());
+        assert!(cache.read_range(&unwritten, 0, 8).unwrap().is_none());
+        let values: Vec<Option<TestValue>> = cache
+            .read_many(&[unwritten.clone(), written.clone()])
+            .unwrap();
+        assert_eq!(values.len(), 2);
+        assert!(values[0].is_none());
+        let bytes = cache.read_range(&written, 0, 8).unwrap().unwrap();
+        assert_eq!(bytes, vec![7; 8]);
+        assert_eq!(cache.stats().read_misses, 3);
+    }
+}
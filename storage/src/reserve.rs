@@ -0,0 +1,213 @@
+use std::io::{self, Write};
+use std::os::unix::fs::FileExt;
+
+use crate::{FifoFileCache, StorageError, WriteResponse};
+
+/// A slot claimed by [`reserve`](FifoFileCache::reserve), to fill with
+/// [`commit`](FifoFileCache::commit). Dropping it without committing wastes the slot, it
+/// stays zeroed until its page is recycled.
+#[derive(Debug)]
+pub struct Reservation {
+    response: WriteResponse,
+}
+
+impl Reservation {
+    /// The bytes reserved, the commit must write exactly as many.
+    pub fn len(&self) -> usize {
+        self.response.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.response.length == 0
+    }
+}
+
+/// Writes the bytes of a [`Reservation`] in place, in order, see
+/// [`commit`](FifoFileCache::commit).
+pub struct ReservationWriter<'a> {
+    cache: &'a FifoFileCache,
+    response: &'a WriteResponse,
+    written: usize,
+}
+
+impl Write for ReservationWriter<'_> {
+    // Past the reserved bytes it writes nothing, so `write_all` fails with `WriteZero`
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.response.length - self.written);
+        if len == 0 {
+            return Ok(0);
+        }
+        // Under the write lock, the page can't be recycled while the bytes land
+        let mut manager = self.cache.lock_manager();
+        if !self.cache.is_current(self.response) {
+            return Err(io::Error::other("the reserved page was recycled"));
+        }
+        // The zeros of the slot may still be in the write buffer
+        if !manager.file.buffer().is_empty() {
+            manager.flush_buffer()?;
+        }
+        let offset = self.cache.page_start(self.response.page_id)
+            + self.response.page_offset
+            + self.written as u64;
+        manager.file.get_ref().write_all_at(&buf[..len], offset)?;
+        self.written += len;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl FifoFileCache {
+    /// Claim `len` bytes at the write cursor, for a value produced piece by piece that
+    /// shouldn't be buffered whole in memory.
+    ///
+    /// The slot is written with zeros and the cursor moves past it, so the writes that
+    /// follow don't wait for the value. The slot can't be read until
+    /// [`commit`](Self::commit) fills it and hands out its response, and scans skip it
+    /// until then. The bytes are stored as is, like [`write_bytes`](Self::write_bytes):
+    /// the cache can't have a checksum nor transforms, as those need the whole record.
+    pub fn reserve(&self, len: usize) -> Result<Reservation, StorageError> {
+        assert!(
+            self.checksum.is_none() && self.transforms.is_empty(),
+            "reserved slots are written as is, without checksum or transforms"
+        );
+        assert!(len > 0, "the reservation should not be empty");
+        if len > self.page_size {
+            return Err(StorageError::ValueTooLarge {
+                size: len,
+                limit: self.page_size,
+            });
+        }
+        let mut manager = self.lock_manager();
+        let response = manager.append(vec![0; len]);
+        if let Ok(response) = &response {
+            manager
+                .directory
+                .supersede(response.page_id, response.page_offset);
+        }
+        self.finish_write(manager, response)
+            .map(|response| Reservation { response })
+    }
+
+    /// Fill a slot of [`reserve`](Self::reserve) through `write`, which must write all its
+    /// bytes, and return the response to read it. Returns None if the slot's page was
+    /// recycled before the value was complete, the cursor went around the whole file:
+    /// the bytes written after that fail with an error. The reservation must come from
+    /// this cache.
+    pub fn commit<F>(
+        &self,
+        reservation: Reservation,
+        write: F,
+    ) -> Result<Option<WriteResponse>, StorageError>
+    where
+        F: FnOnce(&mut ReservationWriter<'_>) -> io::Result<()>,
+    {
+        let response = reservation.response;
+        let mut writer = ReservationWriter {
+            cache: self,
+            response: &response,
+            written: 0,
+        };
+        let result = write(&mut writer);
+        let written = writer.written;
+        if !self.is_current(&response) {
+            return Ok(None);
+        }
+        result?;
+        if written < response.length {
+            return Err(StorageError::Io(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "{} of the {} reserved bytes written",
+                    written, response.length
+                ),
+            )));
+        }
+        let mut manager = self.lock_manager();
+        if !self.is_current(&response) {
+            return Ok(None);
+        }
+        manager
+            .directory
+            .publish(response.page_id, response.page_offset);
+        self.finish_write(manager, Ok(Some(response)))
+    }
+
+    fn is_current(&self, response: &WriteResponse) -> bool {
+        self.pages[response.page_id as usize].load(std::sync::atomic::Ordering::Relaxed)
+            == response.version
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::tempdir;
+
+    use crate::FifoFileCache;
+
+    #[test]
+    fn test_reserve_commit() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_reserve"), 32, 32 * 3)
+            .write_buffer(32)
+            .build();
+        let reservation = cache.reserve(12).unwrap();
+        assert_eq!(reservation.len(), 12);
+        // Writes go on while the value is produced
+        let after = cache.write_bytes(vec![9; 4]).unwrap();
+        assert_eq!(after.page_offset, 12);
+        // Not listed before the commit
+        assert_eq!(cache.iter_live().count(), 1);
+
+        let response = cache
+            .commit(reservation, |writer| {
+                writer.write_all(b"hello ")?;
+                writer.write_all(b"world!")
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!((response.page_offset, response.length), (0, 12));
+        let bytes = cache.read_range(&response, 0, 12).unwrap().unwrap();
+        assert_eq!(bytes, b"hello world!");
+        let bytes = cache.read_range(&after, 0, 4).unwrap().unwrap();
+        assert_eq!(bytes, vec![9; 4]);
+        assert_eq!(cache.iter_live().count(), 2);
+
+        // Too many or too few bytes
+        let reservation = cache.reserve(4).unwrap();
+        let result = cache.commit(reservation, |writer| writer.write_all(b"hello"));
+        assert!(result.is_err());
+        let reservation = cache.reserve(4).unwrap();
+        let result = cache.commit(reservation, |writer| writer.write_all(b"hi"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_abandoned_reservation() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_abandoned"), 16, 16 * 2);
+        // Abandoned
+        let _ = cache.reserve(8).unwrap();
+        let response = cache.write_bytes(vec![1; 8]).unwrap();
+        assert_eq!(response.page_offset, 8);
+        assert_eq!(cache.iter_live().count(), 1);
+        let file = std::fs::read(dir.path().join("test_abandoned")).unwrap();
+        assert_eq!(file[..8], [0; 8]);
+
+        // The cursor goes around the file before the commit
+        let reservation = cache.reserve(8).unwrap();
+        for _ in 0..4 {
+            cache.write_bytes(vec![2; 8]).unwrap();
+        }
+        let result = cache
+            .commit(reservation, |writer| writer.write_all(&[3; 8]))
+            .unwrap();
+        assert!(result.is_none());
+        let bytes = cache.read_range(&response, 0, 8).unwrap();
+        assert!(bytes.is_none());
+    }
+}
@@ -0,0 +1,124 @@
+// Pages retired after repeated I/O errors, e.g. on a disk failing at some offsets. The
+// writer skips them when switching pages, recycling them empty, and reads of their
+// records fail with `StorageError::Retired` without touching the file.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64};
+
+use crate::PageID;
+
+// The pages left in service, the writer needs one to write to and one to switch to
+const MIN_PAGES_IN_SERVICE: u64 = 2;
+
+pub(crate) struct PageHealth {
+    // The I/O errors of each page since the cache was opened
+    errors: Box<[AtomicU32]>,
+    retired: Box<[AtomicBool]>,
+    retired_count: AtomicU64,
+    // The errors after which a page is retired, never if None
+    threshold: Option<u32>,
+}
+
+impl PageHealth {
+    pub(crate) fn new(page_num: usize, threshold: Option<u32>) -> Self {
+        Self {
+            errors: (0..page_num).map(|_| AtomicU32::new(0)).collect(),
+            retired: (0..page_num).map(|_| AtomicBool::new(false)).collect(),
+            retired_count: AtomicU64::new(0),
+            threshold,
+        }
+    }
+
+    pub(crate) fn is_retired(&self, page_id: PageID) -> bool {
+        self.retired[page_id as usize].load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    // Count an I/O error on the page, retire it once it reaches the threshold unless
+    // that would leave too few pages in service
+    pub(crate) fn record_error(&self, page_id: PageID) {
+        let Some(threshold) = self.threshold else {
+            return;
+        };
+        let errors =
+            self.errors[page_id as usize].fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+        if errors < threshold || self.is_retired(page_id) {
+            return;
+        }
+        let max_retired = self.retired.len() as u64 - MIN_PAGES_IN_SERVICE;
+        let reserved = self.retired_count.fetch_update(
+            std::sync::atomic::Ordering::Relaxed,
+            std::sync::atomic::Ordering::Relaxed,
+            |count| (count < max_retired).then_some(count + 1),
+        );
+        if reserved.is_ok()
+            && self.retired[page_id as usize].swap(true, std::sync::atomic::Ordering::Relaxed)
+        {
+            // Another thread retired it first
+            self.retired_count
+                .fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn retired_pages(&self) -> Vec<PageID> {
+        (0..self.retired.len() as PageID)
+            .filter(|&page_id| self.is_retired(page_id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::PageHealth;
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage, StorageError};
+
+    #[test]
+    fn test_retire_page() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_retire_page");
+        let cache = FifoFileCache::builder(path.clone(), 16, 16 * 4)
+            .retire_after_errors(2)
+            .build();
+        let responses: Vec<_> = (0..5)
+            .map(|i| cache.write(TestValue::from(i)).unwrap())
+            .collect();
+        // The second page fails to read, as if the disk lost it
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(16)
+            .unwrap();
+        for _ in 0..2 {
+            let result: Result<Option<TestValue>, _> = cache.read(&responses[2]);
+            assert!(matches!(result, Err(StorageError::Read { page_id: 1, .. })));
+        }
+        let result: Result<Option<TestValue>, _> = cache.read(&responses[3]);
+        assert!(matches!(result, Err(StorageError::Retired { page_id: 1 })));
+        let stats = cache.stats();
+        assert_eq!(stats.retired_pages, vec![1]);
+        assert_eq!(stats.capacity_bytes, 16 * 3);
+
+        // The writer goes around the retired page
+        let page_ids: Vec<_> = (0..6)
+            .map(|i| cache.write(TestValue::from(i)).unwrap().page_id)
+            .collect();
+        assert_eq!(page_ids, vec![2, 3, 3, 0, 0, 2]);
+        let value: Option<TestValue> = cache.read(&responses[0]).unwrap();
+        assert!(value.is_none());
+    }
+
+    #[test]
+    fn test_retire_keeps_two_pages() {
+        let health = PageHealth::new(3, Some(1));
+        for page_id in 0..3 {
+            health.record_error(page_id);
+        }
+        assert_eq!(health.retired_pages(), vec![0]);
+
+        let health = PageHealth::new(3, None);
+        health.record_error(0);
+        assert!(!health.is_retired(0));
+    }
+}
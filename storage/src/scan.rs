@@ -0,0 +1,197 @@
+use std::collections::VecDeque;
+
+use crate::directory::DirectoryEntry;
+use crate::{FifoFileCache, PageID, StorageError, WriteResponse};
+
+/// A record yielded by [`FifoFileCache::iter_live`].
+#[derive(Debug, Clone)]
+pub struct ScanEntry {
+    pub response: WriteResponse,
+    /// The stored bytes, without the checksum footer.
+    pub data: Vec<u8>,
+}
+
+/// Iterator over the live records oldest to newest, see [`FifoFileCache::iter_live`].
+pub struct LiveIter<'a> {
+    cache: &'a FifoFileCache,
+    walk: PageWalk,
+}
+
+// Walks the pages from the oldest, scanning them one after the other
+pub(crate) struct PageWalk {
+    // The next page to scan and the pages left, the cursor's page is the last one
+    next_page: PageID,
+    pages_left: usize,
+    entries: VecDeque<ScanEntry>,
+}
+
+impl PageWalk {
+    pub(crate) fn new(cache: &FifoFileCache) -> Self {
+        let write_page_id = cache.manager.lock().unwrap().write_page_id;
+        Self {
+            next_page: (write_page_id + 1) % cache.pages.len() as u64,
+            pages_left: cache.pages.len(),
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn next(
+        &mut self,
+        cache: &FifoFileCache,
+    ) -> Option<Result<ScanEntry, StorageError>> {
+        while self.entries.is_empty() {
+            if self.pages_left == 0 {
+                return None;
+            }
+            let page_id = self.next_page;
+            self.next_page = (page_id + 1) % cache.pages.len() as u64;
+            self.pages_left -= 1;
+            match cache.scan_page(page_id) {
+                Ok(entries) => self.entries = entries.into(),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.entries.pop_front().map(Ok)
+    }
+}
+
+impl FifoFileCache {
+    /// Walk the live records, from the page after the write cursor to the cursor's page.
+    ///
+    /// Each page is read as a whole once its version and entries are captured, and its
+    /// records are dropped if the page was recycled by the time they were read, so a
+    /// concurrent write never shows up under an old version. Records superseded by an
+    /// [`update`](Self::update) or failing their checksum are skipped. Pages are
+    /// captured one after the other, not all at once: with concurrent writes, a page
+    /// recycled during the walk is seen with its new records.
+    pub fn iter_live(&self) -> LiveIter<'_> {
+        LiveIter {
+            cache: self,
+            walk: PageWalk::new(self),
+        }
+    }
+
+    // The live records of a page, empty if it was recycled while reading it
+    fn scan_page(&self, page_id: PageID) -> Result<Vec<ScanEntry>, StorageError> {
+        let (version, entries): (u64, Vec<DirectoryEntry>) = {
+            let manager = self.manager.lock().unwrap();
+            let version = self.pages[page_id as usize].load(std::sync::atomic::Ordering::Relaxed);
+            (version, manager.directory.entries(page_id).to_vec())
+        };
+        let Some(last) = entries.last() else {
+            return Ok(Vec::new());
+        };
+        let mut buffer = vec![0; last.page_offset as usize + last.length];
+        self.read_exact_at(&mut buffer, page_id, 0)?;
+        if self.pages[page_id as usize].load(std::sync::atomic::Ordering::Relaxed) != version {
+            return Ok(Vec::new());
+        }
+
+        let footer = self.checksum.as_ref().map_or(0, |checksum| checksum.size());
+        entries
+            .iter()
+            .filter(|entry| !entry.superseded)
+            .filter_map(|entry| {
+                let start = entry.page_offset as usize;
+                let record = &buffer[start..start + entry.length];
+                let payload_len = record.len().checked_sub(footer)?;
+                if let Some(checksum) = &self.checksum {
+                    if checksum.compute(&record[..payload_len]) != record[payload_len..] {
+                        return None;
+                    }
+                }
+                Some(ScanEntry {
+                    response: WriteResponse {
+                        page_id,
+                        page_offset: entry.page_offset,
+                        version,
+                        length: entry.length,
+                    },
+                    data: record[..payload_len].to_vec(),
+                })
+            })
+            .map(|mut entry| {
+                entry.data = self.decode_record(entry.data)?;
+                Ok(entry)
+            })
+            .collect()
+    }
+}
+
+impl Iterator for LiveIter<'_> {
+    type Item = Result<ScanEntry, StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.walk.next(self.cache)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    use tempfile::tempdir;
+
+    use super::ScanEntry;
+    use crate::tests::TestValue;
+    use crate::{Crc32, FifoFileCache, Storage};
+
+    #[test]
+    fn test_iter_live_order() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_iter_live"), 16, 16 * 3);
+        // Wraps once, the first page holds the newest values
+        for i in 0..8 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        let values: Vec<u64> = cache
+            .iter_live()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                bincode::deserialize::<TestValue>(&entry.data)
+                    .unwrap()
+                    .value
+            })
+            .collect();
+        assert_eq!(values, vec![2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_iter_live_concurrent_writes() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_iter_live"), 48, 48 * 4)
+            .checksum(Crc32)
+            .build();
+        let written = Mutex::new(HashMap::new());
+        let done = AtomicBool::new(false);
+        let scans: Vec<Vec<ScanEntry>> = std::thread::scope(|s| {
+            for writer in 0..2u64 {
+                let (cache, written, done) = (&cache, &written, &done);
+                s.spawn(move || {
+                    for i in 0..2000 {
+                        let response = cache.write(TestValue::from(writer << 32 | i)).unwrap();
+                        let key = (response.page_id, response.page_offset, response.version);
+                        written.lock().unwrap().insert(key, writer << 32 | i);
+                    }
+                    done.store(true, Ordering::Relaxed);
+                });
+            }
+            let mut scans = Vec::new();
+            while !done.load(Ordering::Relaxed) {
+                scans.push(cache.iter_live().collect::<Result<_, _>>().unwrap());
+            }
+            scans
+        });
+
+        // Every record seen is the one written under its version, never a newer one
+        let written = written.into_inner().unwrap();
+        for entry in scans.into_iter().flatten() {
+            let response = &entry.response;
+            let key = (response.page_id, response.page_offset, response.version);
+            let value: TestValue = bincode::deserialize(&entry.data).unwrap();
+            assert_eq!(written[&key], value.value);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use serde::de::DeserializeOwned;
+
+use crate::{FifoFileCache, StorageError, Value, WriteResponse};
+
+// The schema version prefixed to the serialized value, little-endian
+const HEADER_SIZE: usize = 2;
+
+/// A value whose layout evolves, written with
+/// [`write_versioned`](FifoFileCache::write_versioned) so the records of older layouts
+/// can be upgraded on read.
+pub trait VersionedValue: Value {
+    /// Bump it whenever the serialized layout changes.
+    const SCHEMA_VERSION: u16;
+}
+
+type Upgrade<V> = Box<dyn Fn(&[u8]) -> Result<V, StorageError> + Send + Sync>;
+
+/// How to upgrade the records of older schema versions to `V`, see
+/// [`read_versioned`](FifoFileCache::read_versioned).
+pub struct Migrator<V> {
+    upgrades: HashMap<u16, Upgrade<V>>,
+    rewrite: bool,
+}
+
+/// A value read by [`read_versioned`](FifoFileCache::read_versioned).
+#[derive(Debug)]
+pub struct VersionedRead<V> {
+    pub value: V,
+    /// Where the upgraded value was written, if it was read from an older schema and
+    /// the migrator rewrites them.
+    pub rewritten: Option<WriteResponse>,
+}
+
+impl<V: VersionedValue> Migrator<V> {
+    pub fn new() -> Self {
+        Self {
+            upgrades: HashMap::new(),
+            rewrite: false,
+        }
+    }
+
+    /// Read the records of schema `version` as `Old` and convert them with `upgrade`.
+    pub fn register<Old, F>(mut self, version: u16, upgrade: F) -> Self
+    where
+        Old: DeserializeOwned,
+        F: Fn(Old) -> V + Send + Sync + 'static,
+    {
+        assert!(
+            version < V::SCHEMA_VERSION,
+            "only older schema versions can be upgraded"
+        );
+        let upgrade = move |bytes: &[u8]| crate::value::deserialize(bytes).map(&upgrade);
+        self.upgrades.insert(version, Box::new(upgrade));
+        self
+    }
+
+    /// Write the upgraded values again at the head, so the next reads of the new
+    /// response don't convert them. The old response stays readable until recycled.
+    pub fn rewrite(mut self, rewrite: bool) -> Self {
+        self.rewrite = rewrite;
+        self
+    }
+}
+
+impl<V: VersionedValue> Default for Migrator<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FifoFileCache {
+    /// Write `value` prefixed with its schema version, in 2 bytes.
+    pub fn write_versioned<V: VersionedValue>(
+        &self,
+        value: &V,
+    ) -> Result<WriteResponse, StorageError> {
+        let mut data = V::SCHEMA_VERSION.to_le_bytes().to_vec();
+        bincode::serialize_into(&mut data, value).map_err(StorageError::Serialize)?;
+        self.write_record(data)
+    }
+
+    /// Read a record written with [`write_versioned`](Self::write_versioned), upgrading
+    /// it with `migrator` if it was written with an older schema. A version that is
+    /// neither the current one nor registered fails with
+    /// [`StorageError::UnknownSchema`].
+    pub fn read_versioned<V: VersionedValue>(
+        &self,
+        request: &WriteResponse,
+        migrator: &Migrator<V>,
+    ) -> Result<Option<VersionedRead<V>>, StorageError> {
+        let Some(data) = self.read_record(request)? else {
+            return Ok(None);
+        };
+        // bincode encodes a u16 as its 2 little-endian bytes
+        let version: u16 = crate::value::deserialize(&data[..data.len().min(HEADER_SIZE)])?;
+        let bytes = &data[HEADER_SIZE..];
+        if version == V::SCHEMA_VERSION {
+            let value = crate::value::deserialize(bytes)?;
+            return Ok(Some(VersionedRead {
+                value,
+                rewritten: None,
+            }));
+        }
+        let upgrade = migrator
+            .upgrades
+            .get(&version)
+            .ok_or(StorageError::UnknownSchema { version })?;
+        let value = upgrade(bytes)?;
+        let rewritten = match migrator.rewrite {
+            true => Some(self.write_versioned(&value)?),
+            false => None,
+        };
+        Ok(Some(VersionedRead { value, rewritten }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use tempfile::tempdir;
+
+    use super::{Migrator, VersionedValue};
+    use crate::{FifoFileCache, StorageError, Value};
+
+    #[derive(Serialize, Deserialize)]
+    struct UserV1 {
+        name: String,
+    }
+
+    impl Value for UserV1 {}
+
+    impl VersionedValue for UserV1 {
+        const SCHEMA_VERSION: u16 = 1;
+    }
+
+    // Version 2 added the age
+    #[derive(Serialize, Deserialize)]
+    struct UserV2 {
+        name: String,
+        age: u32,
+    }
+
+    impl Value for UserV2 {}
+
+    impl VersionedValue for UserV2 {
+        const SCHEMA_VERSION: u16 = 2;
+    }
+
+    #[test]
+    fn test_read_versioned() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_versioned"), 64, 64 * 4);
+        // Written before the deploy
+        let old = cache
+            .write_versioned(&UserV1 {
+                name: "ada".to_string(),
+            })
+            .unwrap();
+        let new = cache
+            .write_versioned(&UserV2 {
+                name: "bob".to_string(),
+                age: 7,
+            })
+            .unwrap();
+
+        let migrator = Migrator::<UserV2>::new()
+            .register(1, |old: UserV1| UserV2 {
+                name: old.name,
+                age: 0,
+            })
+            .rewrite(true);
+        let read = cache.read_versioned(&new, &migrator).unwrap().unwrap();
+        assert_eq!((read.value.name.as_str(), read.value.age), ("bob", 7));
+        assert!(read.rewritten.is_none());
+
+        let read = cache.read_versioned(&old, &migrator).unwrap().unwrap();
+        assert_eq!((read.value.name.as_str(), read.value.age), ("ada", 0));
+        // The upgraded record is read without converting it
+        let rewritten = read.rewritten.unwrap();
+        let read = cache
+            .read_versioned(&rewritten, &Migrator::<UserV2>::new())
+            .unwrap()
+            .unwrap();
+        assert_eq!(read.value.name, "ada");
+        assert!(read.rewritten.is_none());
+
+        // Without the upgrade the old record is an unknown schema, not a decode error
+        let result = cache.read_versioned(&old, &Migrator::<UserV2>::new());
+        assert!(matches!(
+            result,
+            Err(StorageError::UnknownSchema { version: 1 })
+        ));
+    }
+}
@@ -0,0 +1,190 @@
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+
+use crate::{FifoFileCache, PageID, StorageError, WriteManger};
+
+// Overwrite `length` bytes from `offset` with zeros
+fn write_zeros(file: &File, offset: u64, length: u64, chunk_size: usize) -> std::io::Result<()> {
+    let zeros = vec![0; chunk_size];
+    let mut written = 0;
+    while written < length {
+        let chunk = (length - written).min(chunk_size as u64) as usize;
+        file.write_all_at(&zeros[..chunk], offset + written)?;
+        written += chunk as u64;
+    }
+    Ok(())
+}
+
+impl WriteManger {
+    // Erase the previous occupant of a page being switched to, its version is already
+    // bumped so readers of the old records miss instead of reading zeros
+    pub(crate) fn zero_page(&self, page_id: PageID) -> std::io::Result<()> {
+        write_zeros(
+            self.file.get_ref(),
+            self.page_start(page_id),
+            self.page_size as u64,
+            self.page_size,
+        )
+    }
+}
+
+impl FifoFileCache {
+    /// Overwrite the whole backing file with zeros and sync it, for decommissioning a
+    /// cache that held sensitive data. A partition of a
+    /// [`PartitionedCache`](crate::PartitionedCache) only overwrites its own pages. Every
+    /// record is dropped, reads of earlier responses miss, and the cache can still be
+    /// written to. It waits for the [read handles](Self::acquire_read_handle) held.
+    pub fn scrub_entire_file(&self) -> Result<(), StorageError> {
+        let mut manager = self.lock_manager();
+        let result = scrub(&mut manager);
+        if let Some(seqlock) = &manager.seqlock {
+            for (page, version) in seqlock.iter().zip(manager.pages.iter()) {
+                page.end_recycle(version.load(std::sync::atomic::Ordering::Relaxed));
+            }
+        }
+        self.finish_write(manager, result)
+    }
+}
+
+fn scrub(manager: &mut WriteManger) -> Result<(), StorageError> {
+    // Recycle every page first, like a page switch does
+    for page_id in 0..manager.pages.len() as PageID {
+        manager.readers.wait_released(page_id);
+        let version =
+            manager.pages[page_id as usize].load(std::sync::atomic::Ordering::Relaxed) + 1;
+        if let Some(eviction) = &mut manager.eviction {
+            let entries = manager.directory.entries(page_id);
+            eviction.capture(page_id, version - 1, manager.page_size, entries)?;
+        }
+        if let Some(version_table) = &manager.version_table {
+            version_table
+                .store(page_id, version)
+                .map_err(StorageError::from_write)?;
+        }
+        if let Some(seqlock) = &manager.seqlock {
+            seqlock[page_id as usize].begin_recycle();
+        }
+        manager.pages[page_id as usize].store(version, std::sync::atomic::Ordering::Relaxed);
+        manager.watchers.notify(page_id, version - 1, version);
+        let (recycled, padding) = manager.directory.recycle(page_id);
+        manager.recycled.extend(recycled);
+        manager.stats.record_gap_recycled(padding);
+        if let Some(dedup) = &mut manager.dedup {
+            dedup.recycle(page_id);
+        }
+    }
+    manager.write_offset = 0;
+    manager.seek_to_cursor().map_err(StorageError::from_write)?;
+
+    // Only the cache's part of a shared file
+    let file = manager.file.get_ref();
+    let start = manager.page_start(0);
+    let end = manager
+        .region_end
+        .unwrap_or(u64::MAX)
+        .min(file.metadata()?.len());
+    write_zeros(file, start, end.saturating_sub(start), manager.page_size)
+        .map_err(StorageError::from_write)?;
+    file.sync_data()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::RwLock;
+
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage};
+
+    #[test]
+    fn test_scrub_entire_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_scrub");
+        let cache = FifoFileCache::new(path.clone(), 16, 16 * 3);
+        let responses: Vec<_> = (1..6)
+            .map(|i| cache.write(TestValue::from(i)).unwrap())
+            .collect();
+        cache.scrub_entire_file().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), 40);
+        assert!(bytes.iter().all(|&byte| byte == 0));
+        for response in &responses {
+            let value: Option<TestValue> = cache.read(response).unwrap();
+            assert!(value.is_none());
+        }
+        assert!(cache.is_empty());
+
+        // Still usable afterwards
+        let response = cache.write(TestValue::from(7)).unwrap();
+        let value: TestValue = cache.read(&response).unwrap().unwrap();
+        assert_eq!(value.value, 7);
+    }
+
+    // Readers loop on saved responses while a writer adds more and another thread keeps
+    // clearing the file, every read must be the value written or a miss
+    fn scrub_stress(seqlock_reads: bool) {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_scrub_stress"), 64, 64 * 4)
+            .seqlock_reads(seqlock_reads)
+            .build();
+        let responses = RwLock::new(Vec::new());
+        let done = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    while !done.load(Ordering::Relaxed) {
+                        let saved = responses.read().unwrap().clone();
+                        for (i, response) in saved.iter().enumerate() {
+                            let value: Option<TestValue> = cache.read(response).unwrap();
+                            if let Some(value) = value {
+                                assert_eq!(value.value, i as u64);
+                            }
+                        }
+                    }
+                });
+            }
+            scope.spawn(|| {
+                for i in 0..2000 {
+                    let response = cache.write(TestValue::from(i)).unwrap();
+                    responses.write().unwrap().push(response);
+                }
+                done.store(true, Ordering::Relaxed);
+            });
+            while !done.load(Ordering::Relaxed) {
+                cache.scrub_entire_file().unwrap();
+            }
+        });
+        cache.scrub_entire_file().unwrap();
+        for response in responses.into_inner().unwrap() {
+            let value: Option<TestValue> = cache.read(&response).unwrap();
+            assert!(value.is_none());
+        }
+    }
+
+    #[test]
+    fn test_scrub_concurrent_reads() {
+        scrub_stress(false);
+        scrub_stress(true);
+    }
+
+    #[test]
+    fn test_zero_fill_on_eviction() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_zero_fill");
+        let cache = FifoFileCache::builder(path.clone(), 16, 16 * 2)
+            .zero_fill_on_eviction(true)
+            .build();
+        for i in 1..5 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        // Page 0 is recycled, only the new value is left on it
+        cache.write(TestValue::from(5)).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[..8], &5u64.to_le_bytes());
+        assert!(bytes[8..16].iter().all(|&byte| byte == 0));
+    }
+}
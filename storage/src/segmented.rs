@@ -0,0 +1,204 @@
+use std::fs::{File, OpenOptions};
+use std::io::ErrorKind;
+use std::os::unix::fs::FileExt;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::Mutex;
+
+use crate::{PageID, PageVersion, Storage, StorageError, Value, WriteResponse};
+
+/// A FIFO cache storing each page in its own file, `dir/{page_id}.page`.
+///
+/// Recycling a page deletes its file and starts a blank one, so the space of evicted
+/// values goes back to the filesystem right away. It suits filesystems that cope well
+/// with many files, not object stores.
+pub struct SegmentedLogStorage {
+    dir: PathBuf,
+    page_size: usize,
+    page_count: usize,
+    // The version of each page, incremented when the page is recycled
+    pages: Box<[PageVersion]>,
+    writer: Mutex<SegmentWriter>,
+}
+
+struct SegmentWriter {
+    page_id: PageID,
+    offset: u64,
+    // The file of `page_id`
+    file: File,
+}
+
+impl SegmentedLogStorage {
+    pub fn new(dir: PathBuf, page_size: usize, page_count: usize) -> Self {
+        assert!(page_size > 0);
+        assert!(page_count > 1);
+        std::fs::create_dir_all(&dir).expect("Failed to create directory");
+        let file = create_page(&dir, 0).expect("Failed to create page");
+        let pages = (0..page_count).map(|_| AtomicU64::new(0)).collect();
+        Self {
+            dir,
+            page_size,
+            page_count,
+            pages,
+            writer: Mutex::new(SegmentWriter {
+                page_id: 0,
+                offset: 0,
+                file,
+            }),
+        }
+    }
+
+    fn page_path(&self, page_id: PageID) -> PathBuf {
+        page_path(&self.dir, page_id)
+    }
+
+    fn version(&self, page_id: PageID) -> u64 {
+        self.pages[page_id as usize].load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    // Delete the next page's file and start a blank one in its place
+    fn switch_page(&self, writer: &mut SegmentWriter) -> std::io::Result<()> {
+        let next_page_id = (writer.page_id + 1) % self.page_count as u64;
+        // Readers of the old records see the new version and report a miss
+        self.pages[next_page_id as usize].fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        match std::fs::remove_file(self.page_path(next_page_id)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        writer.file = create_page(&self.dir, next_page_id)?;
+        writer.page_id = next_page_id;
+        writer.offset = 0;
+        Ok(())
+    }
+}
+
+fn page_path(dir: &std::path::Path, page_id: PageID) -> PathBuf {
+    dir.join(format!("{}.page", page_id))
+}
+
+fn create_page(dir: &std::path::Path, page_id: PageID) -> std::io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(page_path(dir, page_id))
+}
+
+impl<V> Storage<V> for SegmentedLogStorage
+where
+    V: Value,
+{
+    type Error = StorageError;
+
+    fn read(&self, request: &WriteResponse) -> Result<Option<V>, StorageError> {
+        if request.page_id >= self.page_count as u64 {
+            return Err(StorageError::InvalidRequest {
+                field: "page_id",
+                value: request.page_id,
+                constraint: "less than the page count",
+            });
+        }
+        let end = request.page_offset.checked_add(request.length as u64);
+        if end.is_none_or(|end| end > self.page_size as u64) {
+            return Err(StorageError::InvalidRequest {
+                field: "page_offset + length",
+                value: end.unwrap_or(u64::MAX),
+                constraint: "at most the page size",
+            });
+        }
+        if self.version(request.page_id) != request.version {
+            return Ok(None);
+        }
+        let mut buffer = vec![0; request.length];
+        let read = File::open(self.page_path(request.page_id))
+            .and_then(|file| file.read_exact_at(&mut buffer, request.page_offset));
+        // Check the version after the read, the page may have been recycled meanwhile,
+        // in which case a missing or short file is expected
+        if self.version(request.page_id) != request.version {
+            return Ok(None);
+        }
+        read?;
+        let value = crate::value::deserialize(&buffer)?;
+        Ok(Some(value))
+    }
+
+    fn write(&self, value: V) -> Result<WriteResponse, StorageError> {
+        let data = bincode::serialize(&value).map_err(StorageError::Serialize)?;
+        if data.len() > self.page_size {
+            return Err(StorageError::ValueTooLarge {
+                size: data.len(),
+                limit: self.page_size,
+            });
+        }
+        let mut writer = self.writer.lock().unwrap();
+        if writer.offset + data.len() as u64 > self.page_size as u64 {
+            self.switch_page(&mut writer)
+                .map_err(StorageError::from_write)?;
+        }
+        writer
+            .file
+            .write_all_at(&data, writer.offset)
+            .map_err(StorageError::from_write)?;
+        let response = WriteResponse {
+            page_id: writer.page_id,
+            page_offset: writer.offset,
+            version: self.version(writer.page_id),
+            length: data.len(),
+        };
+        writer.offset += data.len() as u64;
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::MetadataExt;
+
+    use tempfile::tempdir;
+
+    use super::SegmentedLogStorage;
+    use crate::tests::TestValue;
+    use crate::{Storage, StorageError, WriteResponse};
+
+    #[test]
+    fn test_segmented_eviction() {
+        let dir = tempdir().unwrap();
+        let storage = SegmentedLogStorage::new(dir.path().join("segments"), 16, 2);
+        let first = storage.write(TestValue::from(1)).unwrap();
+        storage.write(TestValue::from(2)).unwrap();
+        storage.write(TestValue::from(3)).unwrap();
+        let page_file = std::fs::File::open(dir.path().join("segments/0.page")).unwrap();
+        assert_eq!(page_file.metadata().unwrap().nlink(), 1);
+
+        storage.write(TestValue::from(4)).unwrap();
+        // The fifth value doesn't fit on page 1, page 0 is recycled
+        let fifth = storage.write(TestValue::from(5)).unwrap();
+        assert_eq!(fifth.page_id, 0);
+        // The file was deleted, the path is a new file
+        assert_eq!(page_file.metadata().unwrap().nlink(), 0);
+        let page_path = dir.path().join("segments/0.page");
+        assert_eq!(std::fs::metadata(page_path).unwrap().len(), 8);
+
+        let stale: Option<TestValue> = storage.read(&first).unwrap();
+        assert!(stale.is_none());
+        let value: TestValue = storage.read(&fifth).unwrap().unwrap();
+        assert_eq!(value.value, 5);
+    }
+
+    #[test]
+    fn test_segmented_invalid_request() {
+        let dir = tempdir().unwrap();
+        let storage = SegmentedLogStorage::new(dir.path().join("segments"), 16, 2);
+        let response = storage.write(TestValue::from(1)).unwrap();
+        for (page_id, page_offset) in [(2, 0), (0, 12), (0, u64::MAX - 2)] {
+            let request = WriteResponse {
+                page_id,
+                page_offset,
+                ..response.clone()
+            };
+            let result: Result<Option<TestValue>, _> = storage.read(&request);
+            assert!(matches!(result, Err(StorageError::InvalidRequest { .. })));
+        }
+    }
+}
@@ -0,0 +1,110 @@
+// Invariants of the write manager, checked after each append in debug builds. A
+// violation panics naming the broken invariant, release builds compile it out.
+
+use crate::WriteManger;
+
+impl WriteManger {
+    pub(crate) fn check_invariants(&self) {
+        let page_num = self.pages.len() as u64;
+        assert!(
+            self.write_page_id < page_num,
+            "self check: write page {} is out of the {} pages",
+            self.write_page_id,
+            page_num
+        );
+        assert!(
+            self.write_offset <= self.page_size as u64,
+            "self check: write offset {} is past the page size {}",
+            self.write_offset,
+            self.page_size
+        );
+
+        // From the oldest page to the cursor, a page was recycled at most once more
+        // than the previous one since the cache was opened. The versions it was opened
+        // with can be anything, they come from outside with an initial state.
+        let version_of = |page_id: u64| {
+            self.pages[page_id as usize]
+                .load(std::sync::atomic::Ordering::Relaxed)
+                .wrapping_sub(self.opened_versions[page_id as usize])
+        };
+        let oldest = (self.write_page_id + 1) % page_num;
+        let first = version_of(oldest);
+        let mut previous = first;
+        for step in 1..page_num {
+            let page_id = (oldest + step) % page_num;
+            let version = version_of(page_id);
+            assert!(
+                version >= previous && version <= first + 1,
+                "self check: page {} was recycled {} times after {}, the oldest page {}",
+                page_id,
+                version,
+                previous,
+                first
+            );
+            previous = version;
+        }
+
+        if let Some(last) = self.directory.entries(self.write_page_id).last() {
+            assert!(
+                last.page_offset + last.length as u64 <= self.write_offset,
+                "self check: record at offset {} of page {} ends past the write offset {}",
+                last.page_offset,
+                self.write_page_id,
+                self.write_offset
+            );
+        }
+        // The bytes in the write buffer aren't in the file yet. A page switched to right
+        // after a write, see `switch_slack`, may start past the end of the file.
+        let extent = self.page_start(self.write_page_id) + self.write_offset
+            - self.file.buffer().len() as u64;
+        if let (true, Ok(metadata)) = (self.write_offset > 0, self.file.get_ref().metadata()) {
+            assert!(
+                metadata.len() >= extent,
+                "self check: file length {} is short of the written extent {}",
+                metadata.len(),
+                extent
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage};
+
+    #[test]
+    fn test_self_check_passes() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_self_check"), 16, 16 * 3);
+        for i in 0..20 {
+            cache.write(TestValue::from(i)).unwrap();
+            cache.manager.lock().unwrap().check_invariants();
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "self check: page 2 was recycled 5 times")]
+    fn test_self_check_fires() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_self_check_fires"), 16, 16 * 3);
+        cache.write(TestValue::from(1)).unwrap();
+        cache.pages[2].store(5, std::sync::atomic::Ordering::Relaxed);
+        cache.write(TestValue::from(2)).unwrap();
+    }
+
+    #[test]
+    fn test_self_check_initial_state() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_self_check_initial_state");
+        // Not in recycling order, as an outside store may keep them
+        let versions = vec![3, 0, 7, 1];
+        let cache = FifoFileCache::open_with_versions(path, 16, 16 * 4, versions, (2, 0));
+        for i in 0..20 {
+            cache.write(TestValue::from(i)).unwrap();
+            cache.manager.lock().unwrap().check_invariants();
+        }
+    }
+}
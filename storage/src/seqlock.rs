@@ -0,0 +1,137 @@
+// Sequence counters validating reads without the version array, see
+// `FifoFileCacheBuilder::seqlock_reads`.
+//
+// Each page has a counter `seq`, equal to `2 * version` while the page holds the records
+// of that version, and odd while the writer recycles it. The writer, under the write lock:
+//
+//   1. `begin_recycle`: seq = 2v + 1, then a release fence
+//   2. bumps the version to v + 1 and recycles the page, zero filling it if enabled
+//   3. `end_recycle`: seq = 2v + 2 with a release store
+//   4. later appends the records of version v + 1 to the page
+//
+// A reader of a record of version v:
+//
+//   a. `read_begin`: acquire load of seq, gives up unless it's 2v
+//   b. reads the bytes from the file
+//   c. `read_validate`: acquire fence, then load of seq, accepts the bytes if it's still 2v
+//
+// Correctness: the bytes of the record are only overwritten by writes of step 2 (zero
+// fill) or 4 of a later recycle, which are ordered after the store of an odd value in
+// step 1 by the release fence. The file itself is coherent (a pread observing a pwrite
+// happens after it, the kernel serializes them on the page cache), so if the read in b
+// observed any overwritten byte, the store of step 1 happens before the load in c, which
+// then can't return 2v: counters only grow while the cache is open. Conversely if c loads
+// 2v, no recycle of the page started before the read completed, so all the bytes read are
+// the record's. Step a only saves the I/O of a read that would fail c anyway.
+//
+// A changed counter never gets back to 2v, so the reader doesn't retry, the record is
+// gone. The counters are padded to a cache line each, so bumping one doesn't invalidate
+// the lines of the neighbouring pages that readers are loading.
+
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+
+#[derive(Default)]
+#[repr(align(64))]
+pub(crate) struct SeqLockPage {
+    seq: AtomicU64,
+}
+
+impl SeqLockPage {
+    // Writer side, under the write lock: mark the page as being recycled
+    pub(crate) fn begin_recycle(&self) {
+        self.seq.fetch_or(1, Ordering::Relaxed);
+        fence(Ordering::Release);
+    }
+
+    // Writer side, under the write lock: the page now holds the records of `version`
+    pub(crate) fn end_recycle(&self, version: u64) {
+        self.seq.store(version * 2, Ordering::Release);
+    }
+
+    // Whether the records of `version` may be read from the page
+    pub(crate) fn read_begin(&self, version: u64) -> bool {
+        self.seq.load(Ordering::Acquire) == version * 2
+    }
+
+    // Whether the bytes read since `read_begin` are the records of `version`
+    pub(crate) fn read_validate(&self, version: u64) -> bool {
+        fence(Ordering::Acquire);
+        self.seq.load(Ordering::Relaxed) == version * 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use rand::{Rng, SeedableRng};
+    use serde::{Deserialize, Serialize};
+    use tempfile::tempdir;
+
+    use crate::{FifoFileCache, Storage, Value, WriteResponse};
+
+    // A value that is all the same byte, a torn read mixing two values would show
+    #[derive(Serialize, Deserialize)]
+    struct Fill([u8; 24]);
+
+    impl Value for Fill {}
+
+    #[test]
+    fn test_seqlock_reads() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_seqlock"), 24, 24 * 2)
+            .seqlock_reads(true)
+            .build();
+        let first = cache.write(Fill([1; 24])).unwrap();
+        let value: Fill = cache.read(&first).unwrap().unwrap();
+        assert_eq!(value.0, [1; 24]);
+        cache.write(Fill([2; 24])).unwrap();
+        cache.write(Fill([3; 24])).unwrap();
+        let value: Option<Fill> = cache.read(&first).unwrap();
+        assert!(value.is_none());
+        let stats = cache.stats();
+        assert_eq!((stats.read_hits, stats.read_misses), (1, 1));
+    }
+
+    #[test]
+    fn test_seqlock_races() {
+        let dir = tempdir().unwrap();
+        // Few small pages so the readers keep racing with recycling
+        let cache = FifoFileCache::builder(dir.path().join("test_seqlock_races"), 48, 48 * 3)
+            .seqlock_reads(true)
+            .zero_fill_on_eviction(true)
+            .build();
+        let written: Mutex<HashMap<u8, WriteResponse>> = Mutex::default();
+        let cache = Arc::new(cache);
+        std::thread::scope(|s| {
+            for writer in 0..2u8 {
+                let (cache, written) = (&cache, &written);
+                s.spawn(move || {
+                    for i in 0..2000u32 {
+                        let byte = (i % 100) as u8 + writer * 100 + 1;
+                        let response = cache.write(Fill([byte; 24])).unwrap();
+                        written.lock().unwrap().insert(byte, response);
+                    }
+                });
+            }
+            for reader in 0..4 {
+                let (cache, written) = (&cache, &written);
+                s.spawn(move || {
+                    let mut rng = rand::rngs::StdRng::seed_from_u64(reader);
+                    for _ in 0..20_000 {
+                        let byte = rng.gen_range(1..=200);
+                        let Some(response) = written.lock().unwrap().get(&byte).cloned() else {
+                            continue;
+                        };
+                        let value: Option<Fill> = cache.read(&response).unwrap();
+                        if let Some(value) = value {
+                            assert_eq!(value.0, [byte; 24]);
+                        }
+                    }
+                });
+            }
+        });
+        assert!(cache.stats().read_hits > 0);
+    }
+}
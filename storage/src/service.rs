@@ -0,0 +1,225 @@
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use crate::{FifoFileCache, Storage, StorageError, Value, WriteResponse};
+
+pub enum CacheRequest<V> {
+    Read(WriteResponse),
+    Write(V),
+}
+
+#[derive(Debug)]
+pub enum CacheResponse<V> {
+    ReadResult(Option<V>),
+    WriteResult(WriteResponse),
+}
+
+/// A [`tower::Service`] over a cache, to compose cache access with tower middleware
+/// like timeouts or rate limits.
+///
+/// Reads and writes are blocking file I/O, each call runs on the tokio blocking pool, so
+/// it has to be polled from within a tokio runtime.
+///
+/// Dropping the future of a call is safe at any point. If the operation hasn't started
+/// on the blocking pool yet it's skipped. Once started it runs to completion, a blocking
+/// read or write can't be interrupted: a dropped read just discards the value, and a
+/// dropped write is in the cache all the same, only its response is lost. The cursor
+/// and the records are never left half updated.
+pub struct CacheService<V> {
+    cache: Arc<FifoFileCache>,
+    _value: PhantomData<fn(V) -> V>,
+}
+
+impl<V> CacheService<V> {
+    pub fn new(cache: Arc<FifoFileCache>) -> Self {
+        Self {
+            cache,
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<V> Clone for CacheService<V> {
+    fn clone(&self) -> Self {
+        Self::new(self.cache.clone())
+    }
+}
+
+impl<V> tower::Service<CacheRequest<V>> for CacheService<V>
+where
+    V: Value + Send + 'static,
+{
+    type Response = CacheResponse<V>;
+    type Error = StorageError;
+    type Future = Pin<Box<dyn Future<Output = Result<CacheResponse<V>, StorageError>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), StorageError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: CacheRequest<V>) -> Self::Future {
+        let cache = self.cache.clone();
+        let cancel = CancelOnDrop(Arc::new(AtomicBool::new(false)));
+        let cancelled = cancel.0.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            if cancelled.load(std::sync::atomic::Ordering::Acquire) {
+                return Err(StorageError::Io(std::io::ErrorKind::Interrupted.into()));
+            }
+            match request {
+                CacheRequest::Read(request) => {
+                    Storage::<V>::read(cache.as_ref(), &request).map(CacheResponse::ReadResult)
+                }
+                CacheRequest::Write(value) => cache.write(value).map(CacheResponse::WriteResult),
+            }
+        });
+        Box::pin(async move {
+            let _cancel = cancel;
+            task.await
+                .map_err(|e| StorageError::Io(std::io::Error::other(e)))?
+        })
+    }
+}
+
+// Flags the call as cancelled when its future is dropped, polled or not
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, std::sync::atomic::Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tempfile::tempdir;
+    use tower::{Service, ServiceExt};
+
+    use super::{CacheRequest, CacheResponse, CacheService};
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage};
+
+    #[test]
+    fn test_cache_service() {
+        let dir = tempdir().unwrap();
+        let cache = Arc::new(FifoFileCache::new(
+            dir.path().join("test_cache_service"),
+            8,
+            8 * 2,
+        ));
+        let service = CacheService::<TestValue>::new(cache);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let response = service
+                .clone()
+                .oneshot(CacheRequest::Write(TestValue::from(5)))
+                .await
+                .unwrap();
+            let CacheResponse::WriteResult(response) = response else {
+                panic!("unexpected {:?}", response);
+            };
+            let value = service.oneshot(CacheRequest::Read(response)).await.unwrap();
+            let CacheResponse::ReadResult(Some(value)) = value else {
+                panic!("unexpected {:?}", value);
+            };
+            assert_eq!(value.value, 5);
+        });
+    }
+
+    #[test]
+    fn test_drop_before_start() {
+        let dir = tempdir().unwrap();
+        let cache = Arc::new(FifoFileCache::new(
+            dir.path().join("test_drop_before_start"),
+            8,
+            8 * 2,
+        ));
+        let mut service = CacheService::<TestValue>::new(cache.clone());
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .max_blocking_threads(1)
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            // Occupy the only blocking thread so the write waits in the queue
+            let (release, released) = std::sync::mpsc::channel::<()>();
+            let blocker = tokio::task::spawn_blocking(move || released.recv());
+            let write = service.call(CacheRequest::Write(TestValue::from(1)));
+            drop(write);
+            release.send(()).unwrap();
+            blocker.await.unwrap().unwrap();
+
+            // Queued behind the dropped write, which was skipped
+            let write = service.call(CacheRequest::Write(TestValue::from(2)));
+            let CacheResponse::WriteResult(response) = write.await.unwrap() else {
+                unreachable!()
+            };
+            assert_eq!(response.page_offset, 0);
+        });
+        assert_eq!(cache.stats().writes_total, 1);
+    }
+
+    #[test]
+    fn test_drop_while_waiting_for_lock() {
+        let dir = tempdir().unwrap();
+        let cache = Arc::new(FifoFileCache::new(
+            dir.path().join("test_drop_while_waiting"),
+            8,
+            8 * 2,
+        ));
+        let service = CacheService::<TestValue>::new(cache.clone());
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let response = runtime.block_on(async {
+            let written = service
+                .clone()
+                .oneshot(CacheRequest::Write(TestValue::from(1)))
+                .await
+                .unwrap();
+            let CacheResponse::WriteResult(response) = written else {
+                unreachable!()
+            };
+            // The write starts and waits for the lock, then its future is dropped
+            let (locked, release) = (mpsc::channel(), mpsc::channel::<()>());
+            let holder = {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    let _manager = cache.manager.lock().unwrap();
+                    locked.0.send(()).unwrap();
+                    release.1.recv().unwrap();
+                })
+            };
+            locked.1.recv().unwrap();
+            let write = service
+                .clone()
+                .oneshot(CacheRequest::Write(TestValue::from(2)));
+            let timed_out = tokio::time::timeout(Duration::from_millis(50), write).await;
+            assert!(timed_out.is_err());
+            release.0.send(()).unwrap();
+            holder.join().unwrap();
+            response
+        });
+
+        // The started write completes, the cache is left consistent
+        while cache.stats().writes_total < 2 {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        #[cfg(debug_assertions)]
+        cache.manager.lock().unwrap().check_invariants();
+        let value: TestValue = cache.read(&response).unwrap().unwrap();
+        assert_eq!(value.value, 1);
+        // The dropped write took the second page, the next one goes around
+        let response = cache.write(TestValue::from(3)).unwrap();
+        assert_eq!((response.page_id, response.page_offset), (0, 0));
+    }
+}
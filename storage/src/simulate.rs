@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use crate::{CacheConfig, FifoFileCache};
+
+/// An operation of a simulated workload, see [`FifoFileCache::simulate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimOp {
+    /// Read the last value written for the key hash.
+    Read(u64),
+    /// Write a value of `size` bytes, as serialized, for the key hash.
+    Write(u64, usize),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimResult {
+    pub total_ops: u64,
+    pub hits: u64,
+    pub misses: u64,
+    /// Hits over reads, 0 without reads.
+    pub hit_rate: f64,
+    /// Writes stored, values larger than a page are dropped like the real cache rejects
+    /// them.
+    pub write_count: u64,
+    /// Records dropped by page recycling.
+    pub eviction_count: u64,
+    /// The file space used, padding left at page switches included, over the bytes of
+    /// the values written.
+    pub write_amplification_estimate: f64,
+}
+
+// The write cursor and page versions of the real cache, without the file
+struct SimCache {
+    page_size: usize,
+    versions: Vec<u64>,
+    records: Vec<u64>,
+    write_page_id: usize,
+    write_offset: usize,
+    // The page and page version of the last write of each key
+    keys: HashMap<u64, (usize, u64)>,
+}
+
+impl SimCache {
+    fn write(&mut self, key: u64, size: usize, result: &mut SimResult) -> u64 {
+        let mut padding = 0;
+        if self.write_offset + size > self.page_size {
+            padding = (self.page_size - self.write_offset) as u64;
+            let next_page_id = (self.write_page_id + 1) % self.versions.len();
+            self.versions[next_page_id] += 1;
+            result.eviction_count += std::mem::take(&mut self.records[next_page_id]);
+            self.write_page_id = next_page_id;
+            self.write_offset = 0;
+        }
+        self.records[self.write_page_id] += 1;
+        self.write_offset += size;
+        let location = (self.write_page_id, self.versions[self.write_page_id]);
+        self.keys.insert(key, location);
+        padding
+    }
+
+    fn read(&self, key: u64) -> bool {
+        self.keys
+            .get(&key)
+            .is_some_and(|&(page_id, version)| self.versions[page_id] == version)
+    }
+}
+
+impl FifoFileCache {
+    /// Run `workload` against an in-memory model of a cache created with `config`, with
+    /// the same page switching and recycling, without creating any file. Only the
+    /// geometry of `config` is used.
+    pub fn simulate(config: CacheConfig, workload: impl Iterator<Item = SimOp>) -> SimResult {
+        assert!(config.page_size > 0);
+        assert!(config.page_count > 1);
+        let mut cache = SimCache {
+            page_size: config.page_size,
+            versions: vec![0; config.page_count],
+            records: vec![0; config.page_count],
+            write_page_id: 0,
+            write_offset: 0,
+            keys: HashMap::new(),
+        };
+        let mut result = SimResult::default();
+        let (mut bytes_written, mut padding) = (0u64, 0u64);
+        for op in workload {
+            result.total_ops += 1;
+            match op {
+                SimOp::Read(key) => {
+                    if cache.read(key) {
+                        result.hits += 1;
+                    } else {
+                        result.misses += 1;
+                    }
+                }
+                SimOp::Write(key, size) => {
+                    if size > config.page_size {
+                        continue;
+                    }
+                    result.write_count += 1;
+                    bytes_written += size as u64;
+                    padding += cache.write(key, size, &mut result);
+                }
+            }
+        }
+        let reads = result.hits + result.misses;
+        if reads > 0 {
+            result.hit_rate = result.hits as f64 / reads as f64;
+        }
+        if bytes_written > 0 {
+            result.write_amplification_estimate =
+                (bytes_written + padding) as f64 / bytes_written as f64;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::{Rng, SeedableRng};
+    use serde::{Deserialize, Serialize};
+    use tempfile::tempdir;
+
+    use super::SimOp;
+    use crate::{CacheConfig, FifoFileCache, Storage, Value, WriteResponse};
+
+    #[derive(Serialize, Deserialize)]
+    struct Blob(Vec<u8>);
+
+    impl Value for Blob {}
+
+    #[test]
+    fn test_simulate_matches_cache() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        // A quarter of writes of 100 to 300 bytes over 1000 keys
+        let workload: Vec<SimOp> = (0..10_000)
+            .map(|_| {
+                let key = rng.gen_range(0..1000);
+                if rng.gen_bool(0.25) {
+                    // A Vec is serialized with an 8 bytes length
+                    SimOp::Write(key, 8 + rng.gen_range(100..300))
+                } else {
+                    SimOp::Read(key)
+                }
+            })
+            .collect();
+
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_simulate"), 1024, 1024 * 100);
+        let mut index: HashMap<u64, WriteResponse> = HashMap::new();
+        let (mut hits, mut reads) = (0, 0);
+        for op in &workload {
+            match *op {
+                SimOp::Read(key) => {
+                    reads += 1;
+                    if let Some(response) = index.get(&key) {
+                        let value: Option<Blob> = cache.read(response).unwrap();
+                        hits += value.is_some() as u64;
+                    }
+                }
+                SimOp::Write(key, size) => {
+                    let response = cache.write(Blob(vec![0; size - 8])).unwrap();
+                    index.insert(key, response);
+                }
+            }
+        }
+        let measured = hits as f64 / reads as f64;
+
+        let config = CacheConfig::from(&cache);
+        let result = FifoFileCache::simulate(config, workload.into_iter());
+        assert_eq!(result.total_ops, 10_000);
+        assert_eq!(result.hits + result.misses, reads);
+        assert!(result.eviction_count > 0);
+        assert!(result.write_amplification_estimate > 1.0);
+        assert!(
+            (result.hit_rate - measured).abs() < 0.05,
+            "{} {}",
+            result.hit_rate,
+            measured
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{Checkpoint, FifoFileCache, StatsSnapshot};
+
+/// The counters, the write cursor and the page versions captured at the same instant,
+/// see [`FifoFileCache::snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub stats: StatsSnapshot,
+    pub cursor: Checkpoint,
+    /// The version of each page.
+    pub versions: Vec<u64>,
+}
+
+impl FifoFileCache {
+    /// Capture [`stats`](Self::stats), the write cursor and the page versions together,
+    /// to join them with a trace: taken apart, a write may land between two of them.
+    ///
+    /// They're read under the write lock, so the write counters match the cursor and the
+    /// versions. The read counters and the live entries of the recycled pages are
+    /// updated outside of it, a read or a write completing meanwhile may already count
+    /// or not. It copies a version per page and waits for an in-flight write, cheap
+    /// enough to call every second.
+    pub fn snapshot(&self) -> Snapshot {
+        let manager = self.manager.lock().unwrap();
+        let versions = self
+            .pages
+            .iter()
+            .map(|version| version.load(std::sync::atomic::Ordering::Relaxed))
+            .collect();
+        Snapshot {
+            stats: self.stats(),
+            cursor: Checkpoint {
+                page_id: manager.write_page_id,
+                page_offset: manager.write_offset,
+                sequence: self.stats.writes_total(),
+            },
+            versions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use tempfile::tempdir;
+
+    use crate::FifoFileCache;
+
+    #[test]
+    fn test_snapshot_consistent() {
+        let dir = tempdir().unwrap();
+        let page_size = 64;
+        let cache = FifoFileCache::new(dir.path().join("test_snapshot"), page_size, page_size * 8);
+        let initial: u64 = cache.snapshot().versions.iter().sum();
+        let done = AtomicBool::new(false);
+        let snapshots = std::thread::scope(|scope| {
+            for writer in 0..2 {
+                let (cache, done) = (&cache, &done);
+                scope.spawn(move || {
+                    for i in 0..5000 {
+                        cache.write_bytes(vec![writer; 1 + i % 40]).unwrap();
+                    }
+                    done.store(true, Ordering::Relaxed);
+                });
+            }
+            let mut snapshots = Vec::new();
+            while !done.load(Ordering::Relaxed) {
+                snapshots.push(cache.snapshot());
+            }
+            snapshots
+        });
+
+        assert!(!snapshots.is_empty());
+        for snapshot in snapshots {
+            let (stats, cursor) = (&snapshot.stats, &snapshot.cursor);
+            // Each switch bumps the version of the page switched to
+            let switches = snapshot.versions.iter().sum::<u64>() - initial;
+            assert_eq!(cursor.page_id, switches % 8);
+            // The cursor moved over every byte written and every page tail left
+            assert_eq!(
+                stats.bytes_written + stats.padding_bytes,
+                switches * page_size as u64 + cursor.page_offset
+            );
+            assert_eq!(cursor.sequence, stats.writes_total);
+        }
+    }
+}
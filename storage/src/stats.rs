@@ -0,0 +1,537 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::directory::DirectoryEntry;
+use crate::predictor::HitRatePredictor;
+use crate::throughput::ThroughputTracker;
+
+// Counters updated on the read and write paths, all relaxed, they're only used for reporting
+#[derive(Default)]
+pub(crate) struct CacheStats {
+    read_hits: AtomicU64,
+    read_misses: AtomicU64,
+    // Bytes of the values served from the cache
+    read_hit_bytes: AtomicU64,
+    // Bytes the stale requests asked for, the length is known from the `WriteResponse`
+    read_miss_bytes: AtomicU64,
+    checksum_failures: AtomicU64,
+    // Incremented under the write lock once a record is in the file, so it doubles as
+    // the sequence number of the last completed write
+    writes_total: AtomicU64,
+    // Raw bytes of all the records written, checksum footers included
+    bytes_written: AtomicU64,
+    // Bytes left unused at the end of a page when the writer switched to the next one
+    padding_bytes: AtomicU64,
+    // The padding of the pages that weren't recycled yet
+    tail_gap_bytes: AtomicU64,
+    capacity: u64,
+    // Records on pages that haven't been recycled yet
+    live_entries: AtomicU64,
+    live_bytes: AtomicU64,
+    // Writes that switched pages or waited for the lock while another writer did
+    switch_delayed_writes: AtomicU64,
+    // Page switches made right after the write that filled the page, and the ones made
+    // by a write that didn't fit
+    eager_switches: AtomicU64,
+    on_demand_switches: AtomicU64,
+    // Records sent to the background prefetch, and the ones read after it
+    prefetch_issued: AtomicU64,
+    prefetch_useful: AtomicU64,
+    // fdatasync calls made for the sync mode
+    syncs: AtomicU64,
+    // Writes answered with an identical live record in dedup mode
+    dedup_hits: AtomicU64,
+    // Reads with a deadline the caller stopped waiting for
+    read_timeouts: AtomicU64,
+    // Values the write-through store failed to persist
+    write_through_failures: AtomicU64,
+    // Set by the writer while it switches pages under the write lock
+    switching: AtomicBool,
+    pub(crate) write_throughput: ThroughputTracker,
+    pub(crate) read_throughput: ThroughputTracker,
+    pub(crate) hit_rate: HitRatePredictor,
+}
+
+impl CacheStats {
+    pub(crate) fn new(
+        throughput_window: Duration,
+        hit_rate: HitRatePredictor,
+        capacity: u64,
+    ) -> Self {
+        Self {
+            capacity,
+            write_throughput: ThroughputTracker::new(throughput_window),
+            read_throughput: ThroughputTracker::new(throughput_window),
+            hit_rate,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn record_hit(&self, length: usize) {
+        self.read_hits.fetch_add(1, Ordering::Relaxed);
+        self.read_hit_bytes
+            .fetch_add(length as u64, Ordering::Relaxed);
+        self.read_throughput.record(length as u64);
+        self.hit_rate.record_read(true);
+        #[cfg(feature = "metrics")]
+        self.emit_read("hit");
+    }
+
+    pub(crate) fn record_miss(&self, length: usize) {
+        self.read_misses.fetch_add(1, Ordering::Relaxed);
+        self.read_miss_bytes
+            .fetch_add(length as u64, Ordering::Relaxed);
+        self.hit_rate.record_read(false);
+        #[cfg(feature = "metrics")]
+        self.emit_read("miss");
+    }
+
+    #[cfg(feature = "metrics")]
+    fn emit_read(&self, result: &'static str) {
+        metrics::counter!("cache.reads", "result" => result).increment(1);
+        let hits = self.read_hits.load(Ordering::Relaxed);
+        let misses = self.read_misses.load(Ordering::Relaxed);
+        metrics::gauge!("cache.hit_ratio").set(ratio(hits, hits + misses));
+    }
+
+    // A corrupted record is served as a miss
+    pub(crate) fn record_checksum_failure(&self, length: usize) {
+        self.checksum_failures.fetch_add(1, Ordering::Relaxed);
+        self.record_miss(length);
+    }
+
+    pub(crate) fn record_written(&self, length: usize) {
+        self.bytes_written
+            .fetch_add(length as u64, Ordering::Relaxed);
+        self.writes_total.fetch_add(1, Ordering::Release);
+        self.live_entries.fetch_add(1, Ordering::Relaxed);
+        self.live_bytes.fetch_add(length as u64, Ordering::Relaxed);
+        self.write_throughput.record(length as u64);
+        self.hit_rate.record_insert(length as u64);
+        #[cfg(feature = "metrics")]
+        metrics::histogram!("cache.write.bytes").record(length as f64);
+    }
+
+    // The writer moved to the next page, leaving `padding` bytes unused in the previous one
+    // and dropped the `recycled_padding` of the next one by recycling it
+    pub(crate) fn record_page_switch(&self, padding: u64, recycled_padding: u64) {
+        self.padding_bytes.fetch_add(padding, Ordering::Relaxed);
+        self.tail_gap_bytes.fetch_add(padding, Ordering::Relaxed);
+        self.record_gap_recycled(recycled_padding);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("cache.page_recycles").increment(1);
+    }
+
+    pub(crate) fn record_gap_recycled(&self, padding: u64) {
+        self.tail_gap_bytes.fetch_sub(padding, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_recycled(&self, entries: &[DirectoryEntry]) {
+        let bytes: usize = entries.iter().map(|entry| entry.length).sum();
+        self.live_entries
+            .fetch_sub(entries.len() as u64, Ordering::Relaxed);
+        self.live_bytes.fetch_sub(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_switch_delay(&self) {
+        self.switch_delayed_writes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_switch(&self, eager: bool) {
+        match eager {
+            true => self.eager_switches.fetch_add(1, Ordering::Relaxed),
+            false => self.on_demand_switches.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    pub(crate) fn record_dedup_hit(&self) {
+        self.dedup_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_read_timeout(&self) {
+        self.read_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_write_through_failure(&self) {
+        self.write_through_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_prefetch(&self, records: u64) {
+        self.prefetch_issued.fetch_add(records, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_prefetch_useful(&self) {
+        self.prefetch_useful.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_sync(&self) {
+        self.syncs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_switching(&self, switching: bool) {
+        self.switching.store(switching, Ordering::Relaxed);
+    }
+
+    pub(crate) fn switching(&self) -> bool {
+        self.switching.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn writes_total(&self) -> u64 {
+        self.writes_total.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn live_entries(&self) -> u64 {
+        self.live_entries.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn live_bytes(&self) -> u64 {
+        self.live_bytes.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            read_hits: self.read_hits.load(Ordering::Relaxed),
+            read_misses: self.read_misses.load(Ordering::Relaxed),
+            read_hit_bytes: self.read_hit_bytes.load(Ordering::Relaxed),
+            read_miss_bytes: self.read_miss_bytes.load(Ordering::Relaxed),
+            checksum_failures: self.checksum_failures.load(Ordering::Relaxed),
+            writes_total: self.writes_total(),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            padding_bytes: self.padding_bytes.load(Ordering::Relaxed),
+            tail_gap_bytes: self.tail_gap_bytes.load(Ordering::Relaxed),
+            capacity_bytes: self.capacity,
+            live_entries: self.live_entries(),
+            live_bytes: self.live_bytes(),
+            switch_delayed_writes: self.switch_delayed_writes.load(Ordering::Relaxed),
+            eager_switches: self.eager_switches.load(Ordering::Relaxed),
+            on_demand_switches: self.on_demand_switches.load(Ordering::Relaxed),
+            syncs: self.syncs.load(Ordering::Relaxed),
+            prefetch_issued: self.prefetch_issued.load(Ordering::Relaxed),
+            prefetch_useful: self.prefetch_useful.load(Ordering::Relaxed),
+            dedup_hits: self.dedup_hits.load(Ordering::Relaxed),
+            read_timeouts: self.read_timeouts.load(Ordering::Relaxed),
+            write_through_failures: self.write_through_failures.load(Ordering::Relaxed),
+            retired_pages: Vec::new(),
+        }
+    }
+}
+
+/// A point-in-time copy of the cache counters.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StatsSnapshot {
+    pub read_hits: u64,
+    pub read_misses: u64,
+    pub read_hit_bytes: u64,
+    pub read_miss_bytes: u64,
+    pub checksum_failures: u64,
+    pub writes_total: u64,
+    pub bytes_written: u64,
+    pub padding_bytes: u64,
+    /// The bytes left unused at the end of the pages that weren't recycled yet.
+    pub tail_gap_bytes: u64,
+    pub capacity_bytes: u64,
+    pub live_entries: u64,
+    pub live_bytes: u64,
+    pub switch_delayed_writes: u64,
+    /// The page switches made right after a write, see the `switch_slack` option of the
+    /// builder.
+    pub eager_switches: u64,
+    /// The page switches made by a write that didn't fit in the page.
+    pub on_demand_switches: u64,
+    pub syncs: u64,
+    pub prefetch_issued: u64,
+    pub prefetch_useful: u64,
+    pub dedup_hits: u64,
+    pub read_timeouts: u64,
+    pub write_through_failures: u64,
+    /// The pages taken out of service after repeated I/O errors, their bytes are left out
+    /// of the capacity.
+    pub retired_pages: Vec<u64>,
+}
+
+impl StatsSnapshot {
+    /// Fraction of reads that returned a value, 0 if nothing was read.
+    pub fn object_hit_ratio(&self) -> f64 {
+        ratio(self.read_hits, self.read_hits + self.read_misses)
+    }
+
+    /// Fraction of the requested bytes that were served from the cache, 0 if nothing was read.
+    pub fn byte_hit_ratio(&self) -> f64 {
+        ratio(
+            self.read_hit_bytes,
+            self.read_hit_bytes + self.read_miss_bytes,
+        )
+    }
+
+    /// Bytes wasted at page ends, the internal fragmentation of the file.
+    pub fn fragmentation_bytes(&self) -> u64 {
+        self.padding_bytes
+    }
+
+    /// Fraction of the bytes the write cursor moved over that were wasted at page ends.
+    pub fn fragmentation_fraction(&self) -> f64 {
+        ratio(self.padding_bytes, self.bytes_written + self.padding_bytes)
+    }
+
+    /// Fraction of the capacity taken by live records, the tail gaps left out.
+    pub fn fill_fraction(&self) -> f32 {
+        ratio(self.live_bytes, self.capacity_bytes) as f32
+    }
+
+    pub fn average_value_size(&self) -> f64 {
+        ratio(self.bytes_written, self.writes_total)
+    }
+
+    /// The counters and the ratios derived from them in a JSON object, for the dashboards
+    /// scraped without the `metrics` feature.
+    pub fn to_json(&self) -> String {
+        let json = StatsJson {
+            counters: self,
+            object_hit_ratio: self.object_hit_ratio(),
+            byte_hit_ratio: self.byte_hit_ratio(),
+            fragmentation_fraction: self.fragmentation_fraction(),
+            fill_fraction: self.fill_fraction(),
+            average_value_size: self.average_value_size(),
+        };
+        serde_json::to_string(&json).expect("the stats serialize to JSON")
+    }
+}
+
+#[derive(Serialize)]
+struct StatsJson<'a> {
+    #[serde(flatten)]
+    counters: &'a StatsSnapshot,
+    object_hit_ratio: f64,
+    byte_hit_ratio: f64,
+    fragmentation_fraction: f64,
+    fill_fraction: f32,
+    average_value_size: f64,
+}
+
+fn ratio(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage, Value};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Blob(Vec<u8>);
+
+    impl Value for Blob {}
+
+    #[test]
+    fn test_byte_hit_ratio() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_byte_hit_ratio");
+        let cache = FifoFileCache::new(path, 128, 128 * 2);
+
+        // bincode prefixes the vec with a u64 length
+        let small = cache.write(Blob(vec![1; 8])).unwrap();
+        let large = cache.write(Blob(vec![2; 88])).unwrap();
+        assert_eq!(small.length, 16);
+        assert_eq!(large.length, 96);
+        // Fill the second page, then the third write recycles the first one
+        cache.write(Blob(vec![3; 88])).unwrap();
+        cache.write(Blob(vec![4; 88])).unwrap();
+
+        let small_value: Option<Blob> = cache.read(&small).unwrap();
+        assert!(small_value.is_none());
+        let large_value: Option<Blob> = cache.read(&large).unwrap();
+        assert!(large_value.is_none());
+
+        let fresh = cache.write(Blob(vec![5; 8])).unwrap();
+        let fresh_value: Option<Blob> = cache.read(&fresh).unwrap();
+        assert_eq!(fresh_value.unwrap().0, vec![5; 8]);
+
+        let stats = cache.stats();
+        assert_eq!(stats.read_hits, 1);
+        assert_eq!(stats.read_misses, 2);
+        assert_eq!(stats.read_hit_bytes, 16);
+        assert_eq!(stats.read_miss_bytes, 16 + 96);
+        assert!((stats.object_hit_ratio() - 1.0 / 3.0).abs() < 1e-9);
+        assert!((stats.byte_hit_ratio() - 16.0 / 128.0).abs() < 1e-9);
+    }
+
+    #[cfg(feature = "metrics")]
+    mod metrics_recorder {
+        use std::collections::HashMap;
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::sync::{Arc, Mutex};
+
+        use metrics::{
+            Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key, KeyName, Metadata,
+            Recorder, SharedString, Unit,
+        };
+
+        // Keeps every value emitted, keyed by the metric name and labels
+        #[derive(Default)]
+        pub(super) struct CaptureRecorder {
+            pub(super) counters: Mutex<HashMap<String, Arc<AtomicU64>>>,
+            pub(super) gauges: Mutex<HashMap<String, Arc<Capture>>>,
+            pub(super) histograms: Mutex<HashMap<String, Arc<Capture>>>,
+        }
+
+        #[derive(Default)]
+        pub(super) struct Capture(pub(super) Mutex<Vec<f64>>);
+
+        impl Capture {
+            // Record the gauge moved by `delta` from its last value
+            fn add(&self, delta: f64) {
+                let mut values = self.0.lock().unwrap();
+                let last = values.last().copied().unwrap_or(0.0);
+                values.push(last + delta);
+            }
+        }
+
+        impl GaugeFn for Capture {
+            fn increment(&self, value: f64) {
+                self.add(value);
+            }
+
+            fn decrement(&self, value: f64) {
+                self.add(-value);
+            }
+
+            fn set(&self, value: f64) {
+                self.0.lock().unwrap().push(value);
+            }
+        }
+
+        impl HistogramFn for Capture {
+            fn record(&self, value: f64) {
+                self.0.lock().unwrap().push(value);
+            }
+        }
+
+        struct CaptureCounter(Arc<AtomicU64>);
+
+        impl CounterFn for CaptureCounter {
+            fn increment(&self, value: u64) {
+                self.0.fetch_add(value, Ordering::Relaxed);
+            }
+
+            fn absolute(&self, value: u64) {
+                self.0.store(value, Ordering::Relaxed);
+            }
+        }
+
+        fn name(key: &Key) -> String {
+            let mut name = key.name().to_string();
+            for label in key.labels() {
+                name.push_str(&format!(",{}={}", label.key(), label.value()));
+            }
+            name
+        }
+
+        impl Recorder for CaptureRecorder {
+            fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+            fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+            fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+            fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+                let mut counters = self.counters.lock().unwrap();
+                let value = counters.entry(name(key)).or_default().clone();
+                Counter::from_arc(Arc::new(CaptureCounter(value)))
+            }
+
+            fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+                let mut gauges = self.gauges.lock().unwrap();
+                Gauge::from_arc(gauges.entry(name(key)).or_default().clone())
+            }
+
+            fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+                let mut histograms = self.histograms.lock().unwrap();
+                Histogram::from_arc(histograms.entry(name(key)).or_default().clone())
+            }
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics() {
+        use std::sync::atomic::Ordering;
+
+        let recorder = metrics_recorder::CaptureRecorder::default();
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_metrics"), 16, 16 * 2);
+        metrics::with_local_recorder(&recorder, || {
+            // Every write fills a page, the ones after the first switch pages
+            let first = cache.write(Blob(vec![1; 8])).unwrap();
+            cache.write(Blob(vec![2; 8])).unwrap();
+            cache.write(Blob(vec![3; 8])).unwrap();
+            let second = cache.write(Blob(vec![4; 0])).unwrap();
+            assert!(Storage::<Blob>::read(&cache, &first).unwrap().is_none());
+            assert!(Storage::<Blob>::read(&cache, &second).unwrap().is_some());
+        });
+
+        let counter = |name: &str| recorder.counters.lock().unwrap()[name].load(Ordering::Relaxed);
+        assert_eq!(counter("cache.reads,result=hit"), 1);
+        assert_eq!(counter("cache.reads,result=miss"), 1);
+        assert_eq!(counter("cache.page_recycles"), 3);
+        let histograms = recorder.histograms.lock().unwrap();
+        let sizes = histograms["cache.write.bytes"].0.lock().unwrap();
+        assert_eq!(*sizes, vec![16.0, 16.0, 16.0, 8.0]);
+        let gauges = recorder.gauges.lock().unwrap();
+        let ratios = gauges["cache.hit_ratio"].0.lock().unwrap();
+        assert_eq!(*ratios, vec![0.0, 0.5]);
+    }
+
+    #[test]
+    fn test_fill_fraction() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_fill_fraction");
+        // Two 8 byte values per page, 4 bytes left at the end
+        let cache = FifoFileCache::new(path, 20, 20 * 3);
+        for i in 0..5 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        assert_eq!(cache.live_bytes(), 40);
+        assert_eq!(cache.fill_fraction(), 40.0 / 60.0);
+        // The first two pages are full, the third one is still written
+        assert_eq!(cache.stats().tail_gap_bytes, 8);
+
+        // Recycling the first page drops its values and its gap
+        for i in 0..2 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        let stats = cache.stats();
+        assert_eq!(cache.live_bytes(), 40);
+        assert_eq!((stats.tail_gap_bytes, stats.capacity_bytes), (8, 60));
+        assert_eq!(stats.fill_fraction(), cache.fill_fraction());
+    }
+
+    #[test]
+    fn test_stats_to_json() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_stats_to_json"), 16, 16 * 2);
+        let responses: Vec<_> = (0..5)
+            .map(|i| cache.write(TestValue::from(i)).unwrap())
+            .collect();
+        for response in &responses {
+            let _: Option<TestValue> = cache.read(response).unwrap();
+        }
+        let json: serde_json::Value = serde_json::from_str(&cache.stats().to_json()).unwrap();
+        assert_eq!(json["writes_total"], 5);
+        assert_eq!(json["read_hits"], 3);
+        assert_eq!(json["read_misses"], 2);
+        assert_eq!(json["capacity_bytes"], 32);
+        assert_eq!(json["retired_pages"], serde_json::json!([]));
+        assert_eq!(json["object_hit_ratio"], 0.6);
+        assert_eq!(json["average_value_size"], 8.0);
+        for key in ["fill_fraction", "fragmentation_fraction", "byte_hit_ratio"] {
+            assert!(json[key].is_number(), "{} is missing", key);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::fs::File;
+use std::io;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::stats::CacheStats;
+
+/// When a write is made durable with `fdatasync` before it returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncMode {
+    /// Leave it to the kernel, a crash may lose recent writes.
+    #[default]
+    None,
+    /// Each write syncs the file before returning.
+    EveryWrite,
+    /// Writes wait for a sync shared with the concurrent writes. The first waiting write
+    /// syncs once `max_batch` writes are waiting or `max_delay` has passed, whichever
+    /// comes first, and every write completed before the sync returns with it.
+    GroupCommit {
+        max_batch: usize,
+        max_delay: Duration,
+    },
+}
+
+impl SyncMode {
+    pub(crate) fn validate(&self) {
+        if let SyncMode::GroupCommit { max_batch, .. } = self {
+            assert!(*max_batch > 0, "group commit batch should not be empty");
+        }
+    }
+}
+
+#[derive(Default)]
+struct GroupState {
+    // The sequence number of the last write known to be durable
+    synced: u64,
+    // Whether a writer is leading a batch
+    leading: bool,
+}
+
+// Makes the writes durable according to the sync mode, outside the write lock
+pub(crate) struct Syncer {
+    mode: SyncMode,
+    // A handle to the cache file, the data written through any handle is synced
+    file: File,
+    group: Mutex<GroupState>,
+    group_changed: Condvar,
+}
+
+impl Syncer {
+    pub(crate) fn new(mode: SyncMode, file: File) -> Self {
+        Self {
+            mode,
+            file,
+            group: Mutex::default(),
+            group_changed: Condvar::new(),
+        }
+    }
+
+    // Return once the write with the given sequence number is durable
+    pub(crate) fn sync(&self, sequence: u64, stats: &CacheStats) -> io::Result<()> {
+        match self.mode {
+            SyncMode::None => Ok(()),
+            SyncMode::EveryWrite => {
+                stats.record_sync();
+                self.file.sync_data()
+            }
+            SyncMode::GroupCommit {
+                max_batch,
+                max_delay,
+            } => self.group_commit(sequence, stats, max_batch as u64, max_delay),
+        }
+    }
+
+    // Sync whatever the mode, for a write that asked for it
+    pub(crate) fn sync_now(&self, stats: &CacheStats) -> io::Result<()> {
+        stats.record_sync();
+        self.file.sync_data()
+    }
+
+    fn group_commit(
+        &self,
+        sequence: u64,
+        stats: &CacheStats,
+        max_batch: u64,
+        max_delay: Duration,
+    ) -> io::Result<()> {
+        let mut group = self.group.lock().unwrap();
+        let mut joined = false;
+        loop {
+            if group.synced >= sequence {
+                return Ok(());
+            }
+            if group.leading {
+                if !joined {
+                    // Let the leader know the batch grew
+                    self.group_changed.notify_all();
+                    joined = true;
+                }
+                group = self.group_changed.wait(group).unwrap();
+                continue;
+            }
+
+            group.leading = true;
+            let deadline = Instant::now() + max_delay;
+            loop {
+                let now = Instant::now();
+                if stats.writes_total() - group.synced >= max_batch || now >= deadline {
+                    break;
+                }
+                group = self
+                    .group_changed
+                    .wait_timeout(group, deadline - now)
+                    .unwrap()
+                    .0;
+            }
+            // Every write counted here is already in the file
+            let target = stats.writes_total();
+            drop(group);
+            stats.record_sync();
+            let result = self.file.sync_data();
+            group = self.group.lock().unwrap();
+            group.leading = false;
+            if result.is_ok() {
+                group.synced = group.synced.max(target);
+            }
+            self.group_changed.notify_all();
+            // The waiting writes retry with a new leader if the sync failed
+            result?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Barrier;
+    use std::time::Duration;
+
+    use tempfile::tempdir;
+
+    use super::SyncMode;
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage};
+
+    #[test]
+    fn test_every_write() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_every_write"), 8, 8 * 4)
+            .sync_mode(SyncMode::EveryWrite)
+            .build();
+        for i in 0..3 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        assert_eq!(cache.stats().syncs, 3);
+    }
+
+    #[test]
+    fn test_group_commit() {
+        let dir = tempdir().unwrap();
+        let threads = 8;
+        let cache = FifoFileCache::builder(dir.path().join("test_group_commit"), 8, 8 * 64)
+            .sync_mode(SyncMode::GroupCommit {
+                max_batch: threads,
+                max_delay: Duration::from_millis(200),
+            })
+            .build();
+        let barrier = Barrier::new(threads);
+        std::thread::scope(|s| {
+            for i in 0..threads {
+                let (cache, barrier) = (&cache, &barrier);
+                s.spawn(move || {
+                    barrier.wait();
+                    let response = cache.write(TestValue::from(i as u64)).unwrap();
+                    let value: TestValue = cache.read(&response).unwrap().unwrap();
+                    assert_eq!(value.value, i as u64);
+                });
+            }
+        });
+        let stats = cache.stats();
+        assert_eq!(stats.writes_total, threads as u64);
+        assert!(stats.syncs >= 1);
+        assert!(stats.syncs < threads as u64, "{} syncs", stats.syncs);
+    }
+}
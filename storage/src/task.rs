@@ -0,0 +1,204 @@
+// The background threads of a cache. They stop at the next message once the cache is
+// closed, and a panic in one of them is caught and marks the cache degraded instead of
+// unwinding further.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::FifoFileCache;
+
+// How long closing waits for the background threads, the ones still busy, e.g. on a
+// stalled read, are left to finish on their own
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Handed to each background thread, to check between two messages
+#[derive(Clone, Default)]
+pub(crate) struct ShutdownToken {
+    shutdown: Arc<AtomicBool>,
+    // The failpoints turned on, to inject panics in tests
+    #[cfg(test)]
+    failpoints: Arc<Mutex<std::collections::HashSet<&'static str>>>,
+}
+
+impl ShutdownToken {
+    pub(crate) fn is_shutdown(&self) -> bool {
+        self.shutdown.load(std::sync::atomic::Ordering::Acquire)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn fail_point(&self, name: &'static str) {
+        if self.failpoints.lock().unwrap().contains(name) {
+            panic!("failpoint {} hit", name);
+        }
+    }
+
+    #[cfg(not(test))]
+    pub(crate) fn fail_point(&self, _name: &'static str) {}
+}
+
+#[derive(Default)]
+pub(crate) struct TaskGroup {
+    token: ShutdownToken,
+    handles: Mutex<Vec<JoinHandle<()>>>,
+    // The background threads that panicked
+    panics: Arc<AtomicU64>,
+}
+
+impl TaskGroup {
+    // Start a background thread running `task`, None once the group is shut down or if
+    // the thread couldn't be started
+    pub(crate) fn spawn<F>(&self, name: String, task: F) -> Option<()>
+    where
+        F: FnOnce(&ShutdownToken) + Send + 'static,
+    {
+        // Under the lock, so no thread is started after a shutdown took the handles
+        let mut handles = self.handles.lock().unwrap();
+        if self.token.is_shutdown() {
+            return None;
+        }
+        let token = self.token.clone();
+        let panics = self.panics.clone();
+        let handle = std::thread::Builder::new()
+            .name(name)
+            .spawn(move || {
+                if catch_unwind(AssertUnwindSafe(|| task(&token))).is_err() {
+                    panics.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                }
+            })
+            .ok()?;
+        handles.push(handle);
+        Some(())
+    }
+
+    pub(crate) fn shutdown(&self) {
+        let _handles = self.handles.lock().unwrap();
+        self.token
+            .shutdown
+            .store(true, std::sync::atomic::Ordering::Release);
+    }
+
+    // Wait for the threads to exit, return false if some are still running at the
+    // deadline. The panics were caught in the threads, joining can't fail.
+    pub(crate) fn join(&self, timeout: Duration) -> bool {
+        let handles = std::mem::take(&mut *self.handles.lock().unwrap());
+        let deadline = Instant::now() + timeout;
+        while handles.iter().any(|handle| !handle.is_finished()) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let mut stopped = true;
+        for handle in handles {
+            match handle.is_finished() {
+                true => {
+                    let _ = handle.join();
+                }
+                false => stopped = false,
+            }
+        }
+        stopped
+    }
+
+    pub(crate) fn panics(&self) -> u64 {
+        self.panics.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn enable_failpoint(&self, name: &'static str, enabled: bool) {
+        let mut failpoints = self.token.failpoints.lock().unwrap();
+        match enabled {
+            true => failpoints.insert(name),
+            false => failpoints.remove(name),
+        };
+    }
+}
+
+impl FifoFileCache {
+    /// Stop the background threads, the prefetcher, the threads of
+    /// [`read_with_deadline`](Self::read_with_deadline) and the one of an async
+    /// [write-through](crate::WriteThroughMode), waiting up to a second for them.
+    /// Returns false if some didn't stop in time, they are then left to finish on their
+    /// own. The cache stays usable: prefetching becomes a no-op, reads with a deadline
+    /// run on the caller's thread and the values written after aren't written through,
+    /// they count as failures. It's called on drop.
+    pub fn close(&self) -> bool {
+        self.tasks.shutdown();
+        self.prefetcher.wake();
+        self.deadline_reader.wake();
+        if let Some(write_through) = &self.write_through {
+            write_through.wake();
+        }
+        self.tasks.join(CLOSE_TIMEOUT)
+    }
+
+    /// Whether a background thread panicked. The cache keeps serving, without the
+    /// background work of the thread that died.
+    pub fn is_degraded(&self) -> bool {
+        self.tasks.panics() > 0
+    }
+}
+
+impl Drop for FifoFileCache {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage, StorageError};
+
+    #[test]
+    fn test_close() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_close"), 16, 16 * 3);
+        let response = cache.write(TestValue::from(1)).unwrap();
+        cache.prefetch(std::slice::from_ref(&response));
+        let value: Option<TestValue> = cache
+            .read_with_deadline(&response, Duration::from_secs(10))
+            .unwrap();
+        assert!(value.is_some());
+        assert!(cache.close());
+        assert!(!cache.is_degraded());
+
+        // Still usable, on the caller's thread
+        cache.prefetch(std::slice::from_ref(&response));
+        let value: Option<TestValue> = cache
+            .read_with_deadline(&response, Duration::from_secs(10))
+            .unwrap();
+        assert!(value.is_some());
+        assert!(cache.close());
+    }
+
+    #[test]
+    fn test_background_panic() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_background_panic"), 16, 16 * 3);
+        let response = cache.write(TestValue::from(1)).unwrap();
+
+        cache.tasks.enable_failpoint("read-job", true);
+        let result: Result<Option<TestValue>, _> =
+            cache.read_with_deadline(&response, Duration::from_secs(10));
+        assert!(matches!(result, Err(StorageError::Io(_))));
+        cache.tasks.enable_failpoint("read-job", false);
+        // The other read threads keep serving
+        let value: Option<TestValue> = cache
+            .read_with_deadline(&response, Duration::from_secs(10))
+            .unwrap();
+        assert!(value.is_some());
+
+        cache.tasks.enable_failpoint("prefetch", true);
+        cache.prefetch(std::slice::from_ref(&response));
+        assert!(cache.close());
+        assert!(cache.is_degraded());
+        assert_eq!(cache.tasks.panics(), 2);
+        let value: Option<TestValue> = cache.read(&response).unwrap();
+        assert!(value.is_some());
+    }
+}
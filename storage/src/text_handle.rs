@@ -0,0 +1,93 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+use crate::WriteResponse;
+
+// The bytes of `to_bytes` followed by their CRC32, little-endian
+const TEXT_HANDLE_SIZE: usize = WriteResponse::ENCODED_SIZE + 4;
+
+/// Why [`WriteResponse::from_base64`] rejected a string.
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeError {
+    #[error("invalid base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+    #[error("expected {TEXT_HANDLE_SIZE} bytes, got {0}")]
+    Length(usize),
+    // The bytes decode but don't match their CRC32, the string was altered
+    #[error("checksum mismatch")]
+    Checksum,
+}
+
+impl WriteResponse {
+    /// The [`to_bytes`](Self::to_bytes) encoding followed by its CRC32, in URL-safe base64
+    /// without padding, 38 characters safe in URLs and HTTP headers.
+    pub fn to_base64(&self) -> String {
+        let mut bytes = [0; TEXT_HANDLE_SIZE];
+        bytes[..Self::ENCODED_SIZE].copy_from_slice(&self.to_bytes());
+        let crc = crc32fast::hash(&bytes[..Self::ENCODED_SIZE]);
+        bytes[Self::ENCODED_SIZE..].copy_from_slice(&crc.to_le_bytes());
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    /// Decode a string of [`to_base64`](Self::to_base64), checking its CRC32.
+    pub fn from_base64(s: &str) -> Result<Self, DecodeError> {
+        let bytes = URL_SAFE_NO_PAD.decode(s)?;
+        let bytes: [u8; TEXT_HANDLE_SIZE] = bytes
+            .as_slice()
+            .try_into()
+            .map_err(|_| DecodeError::Length(bytes.len()))?;
+        let (response, crc) = bytes.split_at(Self::ENCODED_SIZE);
+        if crc32fast::hash(response).to_le_bytes() != crc {
+            return Err(DecodeError::Checksum);
+        }
+        Ok(Self::from_bytes(response.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DecodeError;
+    use crate::WriteResponse;
+
+    #[test]
+    fn test_base64_round_trip() {
+        let response = WriteResponse {
+            page_id: 3,
+            page_offset: 4096,
+            version: u64::MAX,
+            length: 100,
+        };
+        let text = response.to_base64();
+        assert_eq!(text.len(), 38);
+        assert!(text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        let decoded = WriteResponse::from_base64(&text).unwrap();
+        assert_eq!(decoded.to_bytes(), response.to_bytes());
+    }
+
+    #[test]
+    fn test_base64_corrupted() {
+        let response = WriteResponse {
+            page_id: 3,
+            page_offset: 16,
+            version: 7,
+            length: 8,
+        };
+        let text = response.to_base64();
+        // Another page id, the checksum no longer matches
+        let corrupted = format!("B{}", &text[1..]);
+        assert!(matches!(
+            WriteResponse::from_base64(&corrupted),
+            Err(DecodeError::Checksum)
+        ));
+        assert!(matches!(
+            WriteResponse::from_base64(&text[..20]),
+            Err(DecodeError::Length(15))
+        ));
+        assert!(matches!(
+            WriteResponse::from_base64("not base64!"),
+            Err(DecodeError::Base64(_))
+        ));
+    }
+}
@@ -0,0 +1,195 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::{FifoFileCache, WriteResponse};
+
+const BUCKET_COUNT: u64 = 50;
+pub(crate) const DEFAULT_WINDOW: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct Bucket {
+    // The tick the bytes were counted in, ticks are bucket-sized steps since the start
+    tick: AtomicU64,
+    bytes: AtomicU64,
+}
+
+// Bytes counted over a sliding window split in fixed buckets, without locks. The bucket
+// of the current tick is reset by the first writer that sees it stale, a concurrent add
+// racing with the reset may be lost, which is fine for reporting.
+pub(crate) struct ThroughputTracker {
+    start: Instant,
+    bucket_width: Duration,
+    buckets: [Bucket; BUCKET_COUNT as usize],
+}
+
+impl ThroughputTracker {
+    pub(crate) fn new(window: Duration) -> Self {
+        let bucket_width = window / BUCKET_COUNT as u32;
+        assert!(
+            bucket_width > Duration::ZERO,
+            "throughput window is too short"
+        );
+        Self {
+            start: Instant::now(),
+            bucket_width,
+            buckets: std::array::from_fn(|_| Bucket::default()),
+        }
+    }
+
+    fn tick(&self, elapsed: Duration) -> u64 {
+        (elapsed.as_nanos() / self.bucket_width.as_nanos()) as u64
+    }
+
+    pub(crate) fn record(&self, bytes: u64) {
+        let tick = self.tick(self.start.elapsed());
+        let bucket = &self.buckets[(tick % BUCKET_COUNT) as usize];
+        let stamp = bucket.tick.load(Ordering::Acquire);
+        if stamp != tick
+            && bucket
+                .tick
+                .compare_exchange(stamp, tick, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        {
+            bucket.bytes.store(0, Ordering::Release);
+        }
+        bucket.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    // Bytes per second over the window, or since the start if the window is longer
+    pub(crate) fn bytes_per_second(&self) -> f64 {
+        let elapsed = self.start.elapsed();
+        let tick = self.tick(elapsed);
+        let oldest = (tick + 1).saturating_sub(BUCKET_COUNT);
+        let bytes: u64 = self
+            .buckets
+            .iter()
+            .filter(|bucket| (oldest..=tick).contains(&bucket.tick.load(Ordering::Acquire)))
+            .map(|bucket| bucket.bytes.load(Ordering::Relaxed))
+            .sum();
+        // The current bucket is only partly elapsed
+        let span = elapsed - self.bucket_width * oldest as u32;
+        if span.is_zero() {
+            return 0.0;
+        }
+        bytes as f64 / span.as_secs_f64()
+    }
+}
+
+impl Default for ThroughputTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW)
+    }
+}
+
+impl FifoFileCache {
+    /// Bytes written per second over the throughput window, checksum footers included.
+    pub fn write_throughput_bps(&self) -> f64 {
+        self.stats.write_throughput.bytes_per_second()
+    }
+
+    /// Bytes served by read hits per second over the throughput window.
+    pub fn read_throughput_bps(&self) -> f64 {
+        self.stats.read_throughput.bytes_per_second()
+    }
+
+    /// Roughly how long until the page of `response` is recycled, from the bytes left to
+    /// write before the cursor reaches it and the write throughput. This is a statistical
+    /// estimate assuming the recent write rate holds, not a guarantee. Returns `None`
+    /// before anything was written in the throughput window or if `response` points past
+    /// the pages of the cache, and zero if the entry was already recycled.
+    pub fn estimated_eviction(&self, response: &WriteResponse) -> Option<Duration> {
+        let page_version = self
+            .pages
+            .get(response.page_id as usize)?
+            .load(std::sync::atomic::Ordering::Relaxed);
+        if page_version != response.version {
+            return Some(Duration::ZERO);
+        }
+        let remaining = {
+            let manager = self.manager.lock().unwrap();
+            let page_num = self.pages.len() as u64;
+            // The page is recycled when the cursor switches to it, the page of the cursor
+            // itself after going around the whole file
+            let distance = match (response.page_id + page_num - manager.write_page_id) % page_num {
+                0 => page_num,
+                distance => distance,
+            };
+            (self.page_size as u64 - manager.write_offset) + (distance - 1) * self.page_size as u64
+        };
+        let rate = self.write_throughput_bps();
+        if rate <= 0.0 {
+            return None;
+        }
+        Some(Duration::from_secs_f64(remaining as f64 / rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage, WriteResponse};
+
+    #[test]
+    fn test_write_throughput() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_write_throughput"), 64, 64 * 16);
+        // 8 bytes every 10ms for 2 seconds, i.e. 800 bytes per second
+        let start = Instant::now();
+        for i in 1..=200 {
+            cache.write(TestValue::from(i)).unwrap();
+            let next = start + Duration::from_millis(10 * i);
+            std::thread::sleep(next.saturating_duration_since(Instant::now()));
+        }
+        let rate = cache.write_throughput_bps();
+        assert!((rate - 800.0).abs() < 80.0, "{} bytes per second", rate);
+        assert_eq!(cache.read_throughput_bps(), 0.0);
+    }
+
+    #[test]
+    fn test_estimated_eviction() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_estimated_eviction"), 64, 64 * 16);
+        let first = cache.write(TestValue::from(0)).unwrap();
+        // 8 bytes every 10ms for 1 second, i.e. 800 bytes per second
+        let start = Instant::now();
+        let mut last = first.clone();
+        for i in 1..100 {
+            last = cache.write(TestValue::from(i)).unwrap();
+            let next = start + Duration::from_millis(10 * i);
+            std::thread::sleep(next.saturating_duration_since(Instant::now()));
+        }
+
+        // The cursor is at offset 32 of page 12, 224 bytes from recycling page 0
+        let estimate = cache.estimated_eviction(&first).unwrap().as_secs_f64();
+        assert!((0.2..0.4).contains(&estimate), "{}", estimate);
+        // The current page goes last, after 992 bytes
+        let estimate = cache.estimated_eviction(&last).unwrap().as_secs_f64();
+        assert!((1.0..1.5).contains(&estimate), "{}", estimate);
+
+        for i in 0..30 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        assert_eq!(cache.estimated_eviction(&first), Some(Duration::ZERO));
+        // Not a page of this cache
+        let out_of_bounds = WriteResponse {
+            page_id: 16,
+            ..first
+        };
+        assert_eq!(cache.estimated_eviction(&out_of_bounds), None);
+    }
+
+    #[test]
+    fn test_estimated_eviction_without_writes() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_no_estimate"), 64, 64 * 4)
+            .throughput_window(Duration::from_millis(50))
+            .build();
+        let response = cache.write(TestValue::from(1)).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(cache.estimated_eviction(&response), None);
+    }
+}
@@ -0,0 +1,225 @@
+use std::fmt;
+
+use crate::{FifoFileCache, StorageError};
+
+/// A transformation of the bytes of the records, e.g. compression or encryption, applied
+/// after serializing a value and before deserializing it, see
+/// [`FifoFileCacheBuilder::transform`](crate::FifoFileCacheBuilder::transform).
+pub trait RecordTransform: Send + Sync {
+    /// Recorded in the records the transform wrote, so they are read back with the same
+    /// transform even if the chain changed. It must be unique in a chain.
+    fn id(&self) -> u8;
+    fn on_write(&self, bytes: Vec<u8>) -> Vec<u8>;
+    fn on_read(&self, bytes: Vec<u8>) -> Result<Vec<u8>, TransformError>;
+}
+
+#[derive(Debug)]
+pub enum TransformError {
+    /// The record was written by a transform that isn't in the chain.
+    UnknownTransform(u8),
+    /// The frame of the record is cut short.
+    Truncated,
+    /// The transform rejected the bytes, e.g. a decryption failed.
+    Invalid(String),
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransformError::UnknownTransform(id) => write!(f, "unknown transform id {}", id),
+            TransformError::Truncated => write!(f, "truncated transform frame"),
+            TransformError::Invalid(reason) => write!(f, "invalid transformed record: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+// The transforms of a cache in the order they are applied on write.
+//
+// A record written through the chain is framed as [count][id of each transform in the
+// order applied][bytes], reads undo the transforms named in the frame, last first, by
+// looking them up in the chain.
+#[derive(Default)]
+pub(crate) struct TransformChain {
+    transforms: Vec<Box<dyn RecordTransform>>,
+}
+
+impl TransformChain {
+    pub(crate) fn push(&mut self, transform: Box<dyn RecordTransform>) {
+        assert!(
+            self.find(transform.id()).is_none(),
+            "transform id {} is already in the chain",
+            transform.id()
+        );
+        assert!(
+            self.transforms.len() < u8::MAX as usize,
+            "too many transforms"
+        );
+        self.transforms.push(transform);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+
+    // The bytes the frame adds to a record, none without transforms
+    pub(crate) fn frame_size(&self) -> usize {
+        match self.transforms.len() {
+            0 => 0,
+            len => 1 + len,
+        }
+    }
+
+    fn find(&self, id: u8) -> Option<&dyn RecordTransform> {
+        self.transforms
+            .iter()
+            .find(|transform| transform.id() == id)
+            .map(|transform| transform.as_ref())
+    }
+
+    pub(crate) fn encode(&self, data: Vec<u8>) -> Vec<u8> {
+        let data = self
+            .transforms
+            .iter()
+            .fold(data, |data, transform| transform.on_write(data));
+        let mut record = Vec::with_capacity(1 + self.transforms.len() + data.len());
+        record.push(self.transforms.len() as u8);
+        record.extend(self.transforms.iter().map(|transform| transform.id()));
+        record.extend_from_slice(&data);
+        record
+    }
+
+    // The frame of a record that skipped the transforms
+    pub(crate) fn encode_untransformed(data: Vec<u8>) -> Vec<u8> {
+        let mut record = Vec::with_capacity(1 + data.len());
+        record.push(0);
+        record.extend_from_slice(&data);
+        record
+    }
+
+    pub(crate) fn decode(&self, record: Vec<u8>) -> Result<Vec<u8>, TransformError> {
+        let count = *record.first().ok_or(TransformError::Truncated)? as usize;
+        let ids = record.get(1..1 + count).ok_or(TransformError::Truncated)?;
+        let transforms = ids
+            .iter()
+            .map(|&id| self.find(id).ok_or(TransformError::UnknownTransform(id)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let data = record[1 + count..].to_vec();
+        transforms
+            .into_iter()
+            .rev()
+            .try_fold(data, |data, transform| transform.on_read(data))
+    }
+}
+
+impl FifoFileCache {
+    // Undo the transforms of a record read from the file, its checksum footer stripped
+    pub(crate) fn decode_record(&self, data: Vec<u8>) -> Result<Vec<u8>, StorageError> {
+        if self.transforms.is_empty() {
+            return Ok(data);
+        }
+        self.transforms
+            .decode(data)
+            .map_err(StorageError::Transform)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::{RecordTransform, TransformChain, TransformError};
+    use crate::tests::TestValue;
+    use crate::{Crc32, FifoFileCache, Storage};
+
+    // Flips every bit, to tell the transformed bytes apart
+    struct Invert;
+
+    impl RecordTransform for Invert {
+        fn id(&self) -> u8 {
+            1
+        }
+
+        fn on_write(&self, bytes: Vec<u8>) -> Vec<u8> {
+            bytes.into_iter().map(|byte| !byte).collect()
+        }
+
+        fn on_read(&self, bytes: Vec<u8>) -> Result<Vec<u8>, TransformError> {
+            Ok(self.on_write(bytes))
+        }
+    }
+
+    // Prefixes a magic byte, rejects the records without it
+    struct Magic;
+
+    impl RecordTransform for Magic {
+        fn id(&self) -> u8 {
+            2
+        }
+
+        fn on_write(&self, mut bytes: Vec<u8>) -> Vec<u8> {
+            bytes.insert(0, 0xab);
+            bytes
+        }
+
+        fn on_read(&self, mut bytes: Vec<u8>) -> Result<Vec<u8>, TransformError> {
+            match bytes.first() {
+                Some(0xab) => {
+                    bytes.remove(0);
+                    Ok(bytes)
+                }
+                _ => Err(TransformError::Invalid("bad magic".to_string())),
+            }
+        }
+    }
+
+    #[test]
+    fn test_transform_chain() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_transform");
+        let cache = FifoFileCache::builder(path.clone(), 32, 32 * 2)
+            .checksum(Crc32)
+            .transform(Invert)
+            .transform(Magic)
+            .build();
+        let response = cache.write(TestValue::from(7)).unwrap();
+        // Frame, value inverted then prefixed, checksum
+        assert_eq!(response.length, 3 + 1 + 8 + 4);
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[..4], &[2, 1, 2, 0xab]);
+        assert_eq!(bytes[4], !7);
+
+        let value: TestValue = cache.read(&response).unwrap().unwrap();
+        assert_eq!(value.value, 7);
+    }
+
+    #[test]
+    fn test_transform_frame() {
+        let mut written = TransformChain::default();
+        written.push(Box::new(Invert));
+        written.push(Box::new(Magic));
+        let record = written.encode(vec![1, 2, 3]);
+
+        // A chain in another order still reads it, one missing a transform doesn't
+        let mut reordered = TransformChain::default();
+        reordered.push(Box::new(Magic));
+        reordered.push(Box::new(Invert));
+        assert_eq!(reordered.decode(record.clone()).unwrap(), vec![1, 2, 3]);
+        let mut partial = TransformChain::default();
+        partial.push(Box::new(Invert));
+        assert!(matches!(
+            partial.decode(record.clone()),
+            Err(TransformError::UnknownTransform(2))
+        ));
+        assert!(matches!(
+            written.decode(record[..2].to_vec()),
+            Err(TransformError::Truncated)
+        ));
+
+        let mut corrupted = record;
+        corrupted[3] = 0;
+        let err = written.decode(corrupted).unwrap_err();
+        assert_eq!(err.to_string(), "invalid transformed record: bad magic");
+    }
+}
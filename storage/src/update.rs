@@ -0,0 +1,174 @@
+use crate::{FifoFileCache, Storage, StorageError, Value, WriteResponse};
+
+impl FifoFileCache {
+    /// Read the value of `request`, apply `f` and write the result.
+    ///
+    /// The write only happens if, under the write lock, the page of `request` still has
+    /// its version and no other update was based on the same record. Otherwise nothing is
+    /// written and `None` is returned, the caller should read the newest record and retry.
+    /// Only `update` marks records as superseded, plain writes never do.
+    pub fn update<V, F>(
+        &self,
+        request: &WriteResponse,
+        f: F,
+    ) -> Result<Option<WriteResponse>, StorageError>
+    where
+        V: Value,
+        F: Fn(V) -> V,
+    {
+        let Some(value) = Storage::<V>::read(self, request)? else {
+            return Ok(None);
+        };
+        let serialized = bincode::serialize(&f(value)).map_err(StorageError::Serialize)?;
+        let data = self.encode_record(serialized)?;
+
+        let mut manager = self.lock_manager();
+        let version =
+            self.pages[request.page_id as usize].load(std::sync::atomic::Ordering::Relaxed);
+        if version != request.version
+            || manager
+                .directory
+                .is_superseded(request.page_id, request.page_offset)
+        {
+            return Ok(None);
+        }
+        let response = manager.append(data);
+        if response.is_ok() {
+            manager
+                .directory
+                .supersede(request.page_id, request.page_offset);
+        }
+        self.finish_write(manager, response).map(Some)
+    }
+
+    /// Append `value` only if, under the write lock, the page of `request` still has its
+    /// version, i.e. the record the caller based its decision on wasn't evicted. Returns
+    /// `None` without writing otherwise.
+    ///
+    /// Unlike [`update`](Self::update), the record of `request` is neither read nor
+    /// marked as superseded, so several writes based on the same live record all succeed.
+    pub fn write_if_version<V: Value>(
+        &self,
+        request: &WriteResponse,
+        value: V,
+    ) -> Result<Option<WriteResponse>, StorageError> {
+        if !self.check_request(request)? {
+            return Ok(None);
+        }
+        let serialized = bincode::serialize(&value).map_err(StorageError::Serialize)?;
+        let data = self.encode_record(serialized)?;
+
+        let mut manager = self.lock_manager();
+        let version =
+            self.pages[request.page_id as usize].load(std::sync::atomic::Ordering::Relaxed);
+        if version != request.version {
+            return Ok(None);
+        }
+        let response = self.append_record(&mut manager, data);
+        self.finish_write(manager, response).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Barrier;
+
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage, WriteResponse};
+
+    fn increment(value: TestValue) -> TestValue {
+        TestValue::from(value.value + 1)
+    }
+
+    #[test]
+    fn test_update() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_update"), 8, 8 * 2);
+        let first = cache.write(TestValue::from(1)).unwrap();
+        let second = cache.update(&first, increment).unwrap().unwrap();
+        let value: TestValue = cache.read(&second).unwrap().unwrap();
+        assert_eq!(value.value, 2);
+
+        // The first record was already updated
+        assert!(cache.update(&first, increment).unwrap().is_none());
+        // Recycling the page of the record fails the update as well
+        cache.write(TestValue::from(3)).unwrap();
+        cache.write(TestValue::from(4)).unwrap();
+        assert!(cache.update(&second, increment).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_concurrent_update() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_concurrent_update");
+        let cache = FifoFileCache::new(path, 64, 64 * 16);
+        let mut current = cache.write(TestValue::from(0)).unwrap();
+
+        let generations = 50;
+        for _ in 0..generations {
+            let barrier = Barrier::new(2);
+            let results: Vec<Option<WriteResponse>> = std::thread::scope(|s| {
+                let handles: Vec<_> = (0..2)
+                    .map(|_| {
+                        s.spawn(|| {
+                            barrier.wait();
+                            cache.update(&current, increment).unwrap()
+                        })
+                    })
+                    .collect();
+                handles.into_iter().map(|h| h.join().unwrap()).collect()
+            });
+            let winners: Vec<WriteResponse> = results.into_iter().flatten().collect();
+            assert_eq!(winners.len(), 1);
+            current = winners[0].clone();
+        }
+        let value: TestValue = cache.read(&current).unwrap().unwrap();
+        assert_eq!(value.value, generations);
+    }
+
+    #[test]
+    fn test_write_if_version() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_write_if_version");
+        // One value per page, the fourth write recycles the page of the first
+        let cache = FifoFileCache::new(path, 8, 8 * 3);
+        let stale = cache.write(TestValue::from(1)).unwrap();
+        cache.write(TestValue::from(2)).unwrap();
+        cache.write(TestValue::from(3)).unwrap();
+        let current = cache.write(TestValue::from(4)).unwrap();
+        assert_eq!(
+            (stale.page_id, current.page_id, current.version),
+            (0, 0, stale.version + 1)
+        );
+
+        // Writers based on the evicted record race the one based on the current record
+        let barrier = Barrier::new(5);
+        let results: Vec<Option<WriteResponse>> = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..5)
+                .map(|i| {
+                    let (barrier, cache) = (&barrier, &cache);
+                    let request = match i {
+                        0 => current.clone(),
+                        _ => stale.clone(),
+                    };
+                    s.spawn(move || {
+                        barrier.wait();
+                        cache
+                            .write_if_version(&request, TestValue::from(10 + i))
+                            .unwrap()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert!(results[1..].iter().all(Option::is_none));
+        let written = results[0].clone().unwrap();
+        let value: TestValue = cache.read(&written).unwrap().unwrap();
+        assert_eq!(value.value, 10);
+        // The record it was based on is still live
+        let value: TestValue = cache.read(&current).unwrap().unwrap();
+        assert_eq!(value.value, 4);
+    }
+}
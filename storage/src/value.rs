@@ -1,4 +1,14 @@
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
+use crate::StorageError;
+
 pub trait Value: Serialize + DeserializeOwned {}
+
+// Deserialize a stored value, the error tells the size of the bytes
+pub(crate) fn deserialize<V: DeserializeOwned>(bytes: &[u8]) -> Result<V, StorageError> {
+    bincode::deserialize(bytes).map_err(|source| StorageError::Deserialize {
+        length: bytes.len(),
+        source,
+    })
+}
@@ -0,0 +1,157 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+use crate::PageID;
+
+// Layout of the `.meta` sidecar, all integers little-endian:
+//
+// | magic "CRVT" | format u32 | page_size u64 | page_count u64 | reserved u64 |
+// | slot 0 | slot 1 | ... | slot page_count-1 |
+//
+// Each slot is | version u64 | crc32(page_id ++ version) u32 | zero u32 |, so a torn
+// slot write is detected by the checksum instead of yielding a bogus version.
+const MAGIC: &[u8; 4] = b"CRVT";
+const FORMAT: u32 = 1;
+const HEADER_SIZE: u64 = 32;
+const SLOT_SIZE: u64 = 16;
+
+fn slot_bytes(page_id: PageID, version: u64) -> [u8; SLOT_SIZE as usize] {
+    let mut slot = [0; SLOT_SIZE as usize];
+    slot[0..8].copy_from_slice(&version.to_le_bytes());
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&page_id.to_le_bytes());
+    hasher.update(&version.to_le_bytes());
+    slot[8..12].copy_from_slice(&hasher.finalize().to_le_bytes());
+    slot
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// The page versions persisted next to a cache file, for observers that don't share
+/// the cache's memory. Enabled with [`FifoFileCacheBuilder::persist_versions`].
+///
+/// [`FifoFileCacheBuilder::persist_versions`]: crate::FifoFileCacheBuilder::persist_versions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionTable {
+    pub page_size: usize,
+    // None if the slot was torn by a crash in the middle of its write
+    pub versions: Vec<Option<u64>>,
+}
+
+impl VersionTable {
+    /// The sidecar of the cache at `path`, i.e. `path` with `.meta` appended.
+    pub fn sidecar_path(path: &Path) -> PathBuf {
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(".meta");
+        sidecar.into()
+    }
+
+    /// Load the version table of the cache at `path`.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = File::open(Self::sidecar_path(path))?;
+        let mut header = [0; HEADER_SIZE as usize];
+        file.read_exact_at(&mut header, 0)?;
+        if &header[0..4] != MAGIC {
+            return Err(invalid_data("not a version table"));
+        }
+        if u32::from_le_bytes(header[4..8].try_into().unwrap()) != FORMAT {
+            return Err(invalid_data("unsupported version table format"));
+        }
+        let page_size = u64::from_le_bytes(header[8..16].try_into().unwrap()) as usize;
+        let page_count = u64::from_le_bytes(header[16..24].try_into().unwrap());
+
+        let mut slots = vec![0; (page_count * SLOT_SIZE) as usize];
+        file.read_exact_at(&mut slots, HEADER_SIZE)?;
+        let versions = slots
+            .chunks_exact(SLOT_SIZE as usize)
+            .enumerate()
+            .map(|(page_id, slot)| {
+                let version = u64::from_le_bytes(slot[0..8].try_into().unwrap());
+                (slot_bytes(page_id as PageID, version) == slot).then_some(version)
+            })
+            .collect();
+        Ok(Self {
+            page_size,
+            versions,
+        })
+    }
+}
+
+// Owned by the write manager, one positional write per page switch
+pub(crate) struct VersionTableWriter {
+    file: File,
+}
+
+impl VersionTableWriter {
+    pub(crate) fn create(path: &Path, page_size: usize, page_count: usize) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(VersionTable::sidecar_path(path))?;
+        let mut data = Vec::with_capacity((HEADER_SIZE + SLOT_SIZE * page_count as u64) as usize);
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&FORMAT.to_le_bytes());
+        data.extend_from_slice(&(page_size as u64).to_le_bytes());
+        data.extend_from_slice(&(page_count as u64).to_le_bytes());
+        data.extend_from_slice(&[0; 8]);
+        for page_id in 0..page_count {
+            data.extend_from_slice(&slot_bytes(page_id as PageID, 0));
+        }
+        file.write_all_at(&data, 0)?;
+        Ok(Self { file })
+    }
+
+    pub(crate) fn store(&self, page_id: PageID, version: u64) -> io::Result<()> {
+        self.file.write_all_at(
+            &slot_bytes(page_id, version),
+            HEADER_SIZE + page_id * SLOT_SIZE,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage};
+
+    #[test]
+    fn test_version_table() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test_version_table");
+        let cache = FifoFileCache::builder(path.clone(), 8, 8 * 4)
+            .persist_versions(true)
+            .build();
+        let table = VersionTable::load(&path).unwrap();
+        assert_eq!(table.page_size, 8);
+        assert_eq!(table.versions, vec![Some(0); 4]);
+
+        for i in 0..6 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        let table = VersionTable::load(&path).unwrap();
+        let versions: Vec<Option<u64>> = cache
+            .pages
+            .iter()
+            .map(|version| Some(version.load(std::sync::atomic::Ordering::Relaxed)))
+            .collect();
+        assert_eq!(table.versions, versions);
+        assert_eq!(table.versions, vec![Some(1), Some(2), Some(1), Some(1)]);
+
+        // Simulate a torn write of page 1's slot
+        let file = OpenOptions::new()
+            .write(true)
+            .open(VersionTable::sidecar_path(&path))
+            .unwrap();
+        file.write_all_at(&[9], HEADER_SIZE + SLOT_SIZE).unwrap();
+        let table = VersionTable::load(&path).unwrap();
+        assert_eq!(table.versions, vec![Some(1), None, Some(1), Some(1)]);
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{FifoFileCache, PageID};
+
+// Events buffered per subscriber, the events past it are dropped for that subscriber
+const WATCH_BUFFER: usize = 1024;
+
+/// A page recycled by the writer, see [`FifoFileCache::watch_all_evictions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvictionEvent {
+    pub page_id: PageID,
+    pub old_version: u64,
+    pub new_version: u64,
+    // Nanoseconds since the unix epoch
+    pub timestamp_ns: u64,
+}
+
+// The subscribers of the eviction events, notified under the write lock
+#[derive(Default)]
+pub(crate) struct EvictionWatchers {
+    senders: Vec<SyncSender<EvictionEvent>>,
+}
+
+impl EvictionWatchers {
+    fn subscribe(&mut self) -> Receiver<EvictionEvent> {
+        let (sender, receiver) = sync_channel(WATCH_BUFFER);
+        self.senders.push(sender);
+        receiver
+    }
+
+    // Never blocks the writer: a full subscriber misses the event, a dropped one is
+    // removed
+    pub(crate) fn notify(&mut self, page_id: PageID, old_version: u64, new_version: u64) {
+        if self.senders.is_empty() {
+            return;
+        }
+        let timestamp_ns = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        let event = EvictionEvent {
+            page_id,
+            old_version,
+            new_version,
+            timestamp_ns,
+        };
+        self.senders
+            .retain(|sender| !matches!(sender.try_send(event), Err(TrySendError::Disconnected(_))));
+    }
+}
+
+impl FifoFileCache {
+    /// Subscribe to the recycling of every page, in the order the pages are recycled.
+    ///
+    /// Each subscriber buffers up to 1024 events, a subscriber that falls further behind
+    /// misses the events until it catches up, the writer never waits for it. Dropping the
+    /// receiver unsubscribes it at the next eviction.
+    pub fn watch_all_evictions(&self) -> Receiver<EvictionEvent> {
+        self.lock_manager().watchers.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage};
+
+    #[test]
+    fn test_watch_all_evictions() {
+        let dir = tempdir().unwrap();
+        // Two values per page
+        let cache = FifoFileCache::new(dir.path().join("test_watch"), 16, 16 * 3);
+        let first = cache.watch_all_evictions();
+        let second = cache.watch_all_evictions();
+        for i in 0..8 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        // Three switches, the pages in FIFO order
+        let events: Vec<_> = first.try_iter().collect();
+        let pages: Vec<_> = events
+            .iter()
+            .map(|event| (event.page_id, event.old_version, event.new_version))
+            .collect();
+        assert_eq!(pages, vec![(1, 0, 1), (2, 0, 1), (0, 0, 1)]);
+        assert!(events
+            .windows(2)
+            .all(|pair| pair[0].timestamp_ns <= pair[1].timestamp_ns));
+        assert_eq!(second.try_iter().collect::<Vec<_>>(), events);
+
+        // Dropping a subscriber doesn't affect the other one
+        drop(first);
+        for i in 0..2 {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        let pages: Vec<_> = second.try_iter().map(|event| event.page_id).collect();
+        assert_eq!(pages, vec![1]);
+        assert_eq!(cache.manager.lock().unwrap().watchers.senders.len(), 1);
+    }
+}
@@ -0,0 +1,226 @@
+//! Deterministic workloads for the bench, the soak test and the simulation.
+//!
+//! Everything is driven by [`WorkloadRng`] seeded from the [`WorkloadSpec`], so two
+//! processes given the same spec replay the same operations and values.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{SimOp, Value};
+
+/// SplitMix64, small and stable across versions, unlike the generators of `rand`.
+#[derive(Debug, Clone)]
+pub struct WorkloadRng {
+    state: u64,
+}
+
+impl WorkloadRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, bound)`, `bound` should not be 0.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        ((self.next_u64() as u128 * bound as u128) >> 64) as u64
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyDistribution {
+    Uniform,
+    /// Key `k` is requested in proportion to `1 / (k + 1)^exponent`.
+    Zipf(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeDistribution {
+    Fixed(usize),
+    /// Uniform in `[min, max]`.
+    Uniform {
+        min: usize,
+        max: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkloadSpec {
+    pub key_count: u64,
+    pub key_dist: KeyDistribution,
+    pub value_size_dist: SizeDistribution,
+    /// The fraction of operations that are writes.
+    pub write_ratio: f64,
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Get(u64),
+    /// Write a value of the given size for the key.
+    Set(u64, usize),
+}
+
+impl From<Op> for SimOp {
+    fn from(op: Op) -> Self {
+        match op {
+            Op::Get(key) => SimOp::Read(key),
+            Op::Set(key, size) => SimOp::Write(key, size),
+        }
+    }
+}
+
+impl WorkloadSpec {
+    /// The endless operations of the workload.
+    pub fn ops(&self) -> Ops {
+        assert!(self.key_count > 0, "key count should not be 0");
+        assert!((0.0..=1.0).contains(&self.write_ratio));
+        let zipf_cdf = match self.key_dist {
+            KeyDistribution::Uniform => Vec::new(),
+            KeyDistribution::Zipf(exponent) => {
+                let mut total = 0.0;
+                (0..self.key_count)
+                    .map(|key| {
+                        total += ((key + 1) as f64).powf(-exponent);
+                        total
+                    })
+                    .collect()
+            }
+        };
+        Ops {
+            spec: self.clone(),
+            rng: WorkloadRng::new(self.seed),
+            zipf_cdf,
+        }
+    }
+}
+
+/// Iterator over the operations of a [`WorkloadSpec`].
+pub struct Ops {
+    spec: WorkloadSpec,
+    rng: WorkloadRng,
+    // The cumulative weights of the keys for a Zipf distribution
+    zipf_cdf: Vec<f64>,
+}
+
+impl Ops {
+    /// The generator the operations are drawn from, to generate their values.
+    pub fn rng(&mut self) -> &mut WorkloadRng {
+        &mut self.rng
+    }
+
+    fn key(&mut self) -> u64 {
+        match self.zipf_cdf.last() {
+            None => self.rng.below(self.spec.key_count),
+            Some(&total) => {
+                let sample = self.rng.next_f64() * total;
+                let key = self.zipf_cdf.partition_point(|&weight| weight <= sample);
+                (key as u64).min(self.spec.key_count - 1)
+            }
+        }
+    }
+
+    fn size(&mut self) -> usize {
+        match self.spec.value_size_dist {
+            SizeDistribution::Fixed(size) => size,
+            SizeDistribution::Uniform { min, max } => {
+                assert!(min <= max);
+                min + self.rng.below((max - min) as u64 + 1) as usize
+            }
+        }
+    }
+}
+
+impl Iterator for Ops {
+    type Item = Op;
+
+    fn next(&mut self) -> Option<Op> {
+        let write = self.rng.next_f64() < self.spec.write_ratio;
+        let key = self.key();
+        if write {
+            Some(Op::Set(key, self.size()))
+        } else {
+            Some(Op::Get(key))
+        }
+    }
+}
+
+/// A value of random bytes with their crc32, to check what is read back.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestValue {
+    pub check_sum: u32,
+    pub value: Vec<u8>,
+}
+
+impl Value for TestValue {}
+
+impl TestValue {
+    pub fn generate(size: usize, rng: &mut WorkloadRng) -> Self {
+        let value: Vec<u8> = (0..size).map(|_| rng.next_u64() as u8).collect();
+        let check_sum = crc32fast::hash(&value);
+        Self { check_sum, value }
+    }
+
+    pub fn validate(&self) {
+        let check_sum = crc32fast::hash(&self.value);
+        assert_eq!(check_sum, self.check_sum);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyDistribution, Op, SizeDistribution, TestValue, WorkloadSpec};
+
+    fn spec(key_dist: KeyDistribution) -> WorkloadSpec {
+        WorkloadSpec {
+            key_count: 1000,
+            key_dist,
+            value_size_dist: SizeDistribution::Uniform { min: 10, max: 20 },
+            write_ratio: 0.2,
+            seed: 42,
+        }
+    }
+
+    #[test]
+    fn test_ops_are_deterministic() {
+        let spec = spec(KeyDistribution::Uniform);
+        let first: Vec<Op> = spec.ops().take(1000).collect();
+        let second: Vec<Op> = spec.clone().ops().take(1000).collect();
+        assert_eq!(first, second);
+        let writes = first
+            .iter()
+            .filter(|op| matches!(op, Op::Set(_, 10..=20)))
+            .count();
+        assert!((150..250).contains(&writes), "{}", writes);
+
+        let mut ops = spec.ops();
+        let value = TestValue::generate(100, ops.rng());
+        value.validate();
+        assert_eq!(value, TestValue::generate(100, spec.ops().rng()));
+    }
+
+    #[test]
+    fn test_zipf_keys() {
+        let ops: Vec<Op> = spec(KeyDistribution::Zipf(1.0))
+            .ops()
+            .take(10_000)
+            .collect();
+        let key_of = |op: &Op| match *op {
+            Op::Get(key) | Op::Set(key, _) => key,
+        };
+        // The first key gets about 1 / H(1000) = 13% of the requests
+        let first = ops.iter().filter(|op| key_of(op) == 0).count();
+        assert!((1100..1600).contains(&first), "{}", first);
+        assert!(ops.iter().all(|op| key_of(op) < 1000));
+    }
+}
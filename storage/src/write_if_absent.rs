@@ -0,0 +1,186 @@
+use crate::{FifoFileCache, StorageError, Value, WriteResponse};
+
+/// The outcome of [`FifoFileCache::write_if_absent`].
+#[derive(Debug)]
+pub enum WriteIfAbsentResult<V> {
+    Written(WriteResponse),
+    // The existing entry is still live, nothing was written
+    AlreadyPresent(V),
+}
+
+impl FifoFileCache {
+    /// Write `value` unless `existing` still points to a live entry, in which case that
+    /// entry's value is returned instead.
+    ///
+    /// `existing` is checked under the write lock, so it can't be recycled between the
+    /// check and the decision not to write. An entry that was superseded by
+    /// [`update`](Self::update) or fails its checksum counts as absent.
+    pub fn write_if_absent<V: Value>(
+        &self,
+        value: V,
+        existing: Option<&WriteResponse>,
+    ) -> Result<WriteIfAbsentResult<V>, StorageError> {
+        // An invalid response is an error, or absent when the cache is lenient
+        let existing = match existing {
+            Some(existing) if self.check_request(existing)? => Some(existing),
+            _ => None,
+        };
+        let serialized = bincode::serialize(&value).map_err(StorageError::Serialize)?;
+        let value = self.write_through.as_ref().map(|_| serialized.clone());
+        let data = self.encode_record(serialized)?;
+
+        let mut manager = self.lock_manager();
+        if let Some(existing) = existing {
+            let version =
+                self.pages[existing.page_id as usize].load(std::sync::atomic::Ordering::Relaxed);
+            if version == existing.version
+                && !manager
+                    .directory
+                    .is_superseded(existing.page_id, existing.page_offset)
+            {
+                // The record may still be in the write buffer, the read can't take the lock
+                if !manager.file.buffer().is_empty() {
+                    manager.flush_buffer()?;
+                }
+                // Pages are only recycled under the write lock, the read can't race with it
+                if let Some(buffer) = self.read_record(existing)? {
+                    let value = crate::value::deserialize(&buffer)?;
+                    return Ok(WriteIfAbsentResult::AlreadyPresent(value));
+                }
+            }
+        }
+        let response = self.append_record(&mut manager, data);
+        let response = self.finish_write(manager, response)?;
+        if let Some(value) = value {
+            self.write_through(&response, value)?;
+        }
+        Ok(WriteIfAbsentResult::Written(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Barrier, Mutex};
+
+    use tempfile::tempdir;
+
+    use super::WriteIfAbsentResult;
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage, StorageError, WriteResponse};
+
+    #[test]
+    fn test_write_if_absent() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_write_if_absent"), 8, 8 * 2);
+        let first = match cache.write_if_absent(TestValue::from(1), None).unwrap() {
+            WriteIfAbsentResult::Written(response) => response,
+            result => panic!("unexpected {:?}", result),
+        };
+        match cache
+            .write_if_absent(TestValue::from(2), Some(&first))
+            .unwrap()
+        {
+            WriteIfAbsentResult::AlreadyPresent(value) => assert_eq!(value.value, 1),
+            result => panic!("unexpected {:?}", result),
+        }
+
+        // Recycle the page of `first`, the stale response no longer blocks the write
+        cache.write(TestValue::from(3)).unwrap();
+        cache.write(TestValue::from(4)).unwrap();
+        let second = match cache
+            .write_if_absent(TestValue::from(5), Some(&first))
+            .unwrap()
+        {
+            WriteIfAbsentResult::Written(response) => response,
+            result => panic!("unexpected {:?}", result),
+        };
+        let value: TestValue = cache.read(&second).unwrap().unwrap();
+        assert_eq!(value.value, 5);
+    }
+
+    #[test]
+    fn test_concurrent_write_if_absent() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(
+            dir.path().join("test_concurrent_write_if_absent"),
+            8,
+            8 * 16,
+        );
+        // The caller's index entry for a single key
+        let slot: Mutex<Option<WriteResponse>> = Mutex::new(None);
+        let barrier = Barrier::new(10);
+        let written: usize = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..10)
+                .map(|i| {
+                    let (cache, slot, barrier) = (&cache, &slot, &barrier);
+                    s.spawn(move || {
+                        barrier.wait();
+                        let mut slot = slot.lock().unwrap();
+                        match cache
+                            .write_if_absent(TestValue::from(i), slot.as_ref())
+                            .unwrap()
+                        {
+                            WriteIfAbsentResult::Written(response) => {
+                                *slot = Some(response);
+                                1
+                            }
+                            WriteIfAbsentResult::AlreadyPresent(_) => 0,
+                        }
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+        assert_eq!(written, 1);
+        assert_eq!(cache.stats().writes_total, 1);
+        let response = slot.into_inner().unwrap().unwrap();
+        assert!(Storage::<TestValue>::read(&cache, &response)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_write_if_absent_buffered() {
+        let dir = tempdir().unwrap();
+        let cache =
+            FifoFileCache::builder(dir.path().join("test_write_if_absent_buffered"), 32, 32 * 2)
+                .write_buffer(32)
+                .dedup(true)
+                .build();
+        let first = cache.write(TestValue::from(1)).unwrap();
+        // Still in the write buffer
+        match cache
+            .write_if_absent(TestValue::from(2), Some(&first))
+            .unwrap()
+        {
+            WriteIfAbsentResult::AlreadyPresent(value) => assert_eq!(value.value, 1),
+            result => panic!("unexpected {:?}", result),
+        }
+
+        // Written like `write`, deduplicated against the same bytes
+        let again = match cache.write_if_absent(TestValue::from(1), None).unwrap() {
+            WriteIfAbsentResult::Written(response) => response,
+            result => panic!("unexpected {:?}", result),
+        };
+        assert_eq!(
+            (again.page_id, again.page_offset, again.version),
+            (first.page_id, first.page_offset, first.version)
+        );
+        assert_eq!(cache.stats().dedup_hits, 1);
+    }
+
+    #[test]
+    fn test_write_if_absent_invalid_response() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::new(dir.path().join("test_write_if_absent_invalid"), 8, 8 * 2);
+        let invalid = WriteResponse {
+            page_id: 9,
+            page_offset: 0,
+            version: 0,
+            length: 8,
+        };
+        let result = cache.write_if_absent(TestValue::from(1), Some(&invalid));
+        assert!(matches!(result, Err(StorageError::InvalidRequest { .. })));
+        assert_eq!(cache.stats().writes_total, 0);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{FifoFileCache, IoPriority, StorageError, Value, WriteResponse};
+
+/// Overrides of the cache settings for a single write, see
+/// [`write_with_options`](FifoFileCache::write_with_options). A field left to None keeps
+/// the setting of the cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// Whether to apply the [transforms](crate::RecordTransform), e.g. to skip the
+    /// compression of a latency sensitive write. The record names the transforms it went
+    /// through, so it's read back right either way.
+    pub transforms: Option<bool>,
+    /// The I/O priority of the writer thread for this write, it keeps it afterwards like
+    /// with the cache setting.
+    pub io_priority: Option<IoPriority>,
+    /// True syncs the file before returning whatever the [`SyncMode`](crate::SyncMode),
+    /// false returns without waiting for the sync the mode would make.
+    pub sync: Option<bool>,
+}
+
+impl FifoFileCache {
+    /// Write `value` with some of the cache settings overridden. The checksum can't be
+    /// overridden, reads expect the footer on every record of a cache that has one.
+    pub fn write_with_options<V: Value>(
+        &self,
+        value: V,
+        options: WriteOptions,
+    ) -> Result<WriteResponse, StorageError> {
+        if let Some(io_priority) = options.io_priority {
+            io_priority.validate();
+        }
+        let serialized = bincode::serialize(&value).map_err(StorageError::Serialize)?;
+        let data = self.encode_record_with(serialized, options.transforms.unwrap_or(true))?;
+        let mut manager = self.lock_manager();
+        let io_priority = manager.io_priority;
+        manager.io_priority = options.io_priority.unwrap_or(io_priority);
+        let mut response = self.append_record(&mut manager, data);
+        manager.io_priority = io_priority;
+        // The record may still be in the write buffer
+        if response.is_ok() && options.sync == Some(true) {
+            if let Err(e) = manager.flush_buffer() {
+                response = Err(e.into());
+            }
+        }
+        self.finish_write_with(manager, response, options.sync)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use tempfile::tempdir;
+
+    use super::WriteOptions;
+    use crate::{FifoFileCache, RecordTransform, Storage, TransformError, Value};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Page {
+        title: String,
+        body: Vec<u8>,
+    }
+
+    impl Value for Page {}
+
+    // Run-length encodes the bytes, a stand-in for a real compression
+    struct RunLength;
+
+    impl RecordTransform for RunLength {
+        fn id(&self) -> u8 {
+            7
+        }
+
+        fn on_write(&self, bytes: Vec<u8>) -> Vec<u8> {
+            let mut encoded = Vec::new();
+            for chunk in bytes.chunk_by(|a, b| a == b) {
+                for run in chunk.chunks(u8::MAX as usize) {
+                    encoded.extend_from_slice(&[run.len() as u8, run[0]]);
+                }
+            }
+            encoded
+        }
+
+        fn on_read(&self, bytes: Vec<u8>) -> Result<Vec<u8>, TransformError> {
+            if !bytes.len().is_multiple_of(2) {
+                return Err(TransformError::Invalid("odd run length encoding".into()));
+            }
+            Ok(bytes
+                .chunks(2)
+                .flat_map(|run| std::iter::repeat_n(run[1], run[0] as usize))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_write_with_options() {
+        let dir = tempdir().unwrap();
+        let cache = FifoFileCache::builder(dir.path().join("test_write_options"), 256, 256 * 2)
+            .transform(RunLength)
+            .write_buffer(256)
+            .build();
+        let page = || Page {
+            title: "zeros".to_string(),
+            body: vec![0; 100],
+        };
+        let compressed = cache.write(page()).unwrap();
+        let options = WriteOptions {
+            transforms: Some(false),
+            sync: Some(true),
+            ..Default::default()
+        };
+        let raw = cache.write_with_options(page(), options).unwrap();
+        assert!(compressed.length < 64);
+        assert!(raw.length > 100);
+        // Synced, so flushed out of the write buffer
+        assert_eq!(cache.stats().syncs, 1);
+        let file_len = std::fs::metadata(dir.path().join("test_write_options"))
+            .unwrap()
+            .len();
+        assert_eq!(file_len, (compressed.length + raw.length) as u64);
+
+        let read: Page = cache.read(&compressed).unwrap().unwrap();
+        assert_eq!(read, page());
+        let read: Page = cache.read(&raw).unwrap().unwrap();
+        assert_eq!(read, page());
+    }
+}
@@ -0,0 +1,185 @@
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, OnceLock};
+
+use crate::task::ShutdownToken;
+use crate::{FifoFileCache, StorageError, WriteResponse};
+
+/// The error of a [`WriteThrough`] store, whatever its client returns.
+pub type WriteThroughError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A durable store every value written to the cache also goes to, e.g. a database or an
+/// object storage the cache is a front for, see
+/// [`FifoFileCacheBuilder::write_through`](crate::FifoFileCacheBuilder::write_through).
+pub trait WriteThrough: Send + Sync {
+    /// Persist `value`, the serialized value of the record at `response`. The response
+    /// is the only name the cache knows the value by, the store should map it to its own
+    /// key if it has one.
+    fn write_through(
+        &self,
+        response: &WriteResponse,
+        value: &[u8],
+    ) -> Result<(), WriteThroughError>;
+}
+
+/// When a [`WriteThrough`] store is written to. Either way the cache is written first,
+/// so the value can be read from the cache before it's in the store, and a value the
+/// store failed to persist stays in the cache until its page is recycled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteThroughMode {
+    /// The write returns once the store persisted the value. A store failure fails the
+    /// write with [`StorageError::WriteThrough`], though the value was cached.
+    Sync,
+    /// A background thread writes the values to the store in write order, the write
+    /// returns once the value is cached. The store failures are only counted in
+    /// `write_through_failures` of [`stats`](FifoFileCache::stats), and the values still
+    /// queued when the cache is closed are written before the thread exits.
+    Async,
+}
+
+type Queued = Option<(WriteResponse, Vec<u8>)>;
+
+pub(crate) struct WriteThroughSink {
+    store: Arc<dyn WriteThrough>,
+    mode: WriteThroughMode,
+    // The values for the background thread in async mode, spawned on the first write.
+    // None if the thread couldn't be started, the values are then written in place. A
+    // None message wakes the thread to check for the shutdown.
+    queue: OnceLock<Option<Sender<Queued>>>,
+}
+
+impl WriteThroughSink {
+    pub(crate) fn new(store: Arc<dyn WriteThrough>, mode: WriteThroughMode) -> Self {
+        Self {
+            store,
+            mode,
+            queue: OnceLock::new(),
+        }
+    }
+
+    // Let the thread see the shutdown once it wrote the values queued before
+    pub(crate) fn wake(&self) {
+        if let Some(Some(sender)) = self.queue.get() {
+            let _ = sender.send(None);
+        }
+    }
+}
+
+impl FifoFileCache {
+    // Hand the value of a completed write to the store, if there's one
+    pub(crate) fn write_through(
+        &self,
+        response: &WriteResponse,
+        value: Vec<u8>,
+    ) -> Result<(), StorageError> {
+        let Some(sink) = &self.write_through else {
+            return Ok(());
+        };
+        if sink.mode == WriteThroughMode::Async {
+            let sender = sink.queue.get_or_init(|| {
+                let store = sink.store.clone();
+                let stats = self.stats.clone();
+                let (sender, receiver) = channel::<Queued>();
+                self.tasks.spawn(
+                    "cache-write-through".to_string(),
+                    move |token: &ShutdownToken| {
+                        for queued in receiver {
+                            let Some((response, value)) = queued else {
+                                if token.is_shutdown() {
+                                    return;
+                                }
+                                continue;
+                            };
+                            if store.write_through(&response, &value).is_err() {
+                                stats.record_write_through_failure();
+                            }
+                        }
+                    },
+                )?;
+                Some(sender)
+            });
+            if let Some(sender) = sender {
+                if sender.send(Some((response.clone(), value))).is_err() {
+                    // The thread is gone, after a close
+                    self.stats.record_write_through_failure();
+                }
+                return Ok(());
+            }
+        }
+        sink.store.write_through(response, &value).map_err(|e| {
+            self.stats.record_write_through_failure();
+            StorageError::WriteThrough(e)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tempfile::tempdir;
+
+    use super::{WriteThrough, WriteThroughError, WriteThroughMode};
+    use crate::tests::TestValue;
+    use crate::{FifoFileCache, Storage, StorageError, WriteResponse};
+
+    // Captures the calls, fails the ones for a value of 13
+    #[derive(Clone, Default)]
+    struct MockStore {
+        calls: Arc<Mutex<Vec<(u64, u64)>>>,
+    }
+
+    impl WriteThrough for MockStore {
+        fn write_through(
+            &self,
+            response: &WriteResponse,
+            value: &[u8],
+        ) -> Result<(), WriteThroughError> {
+            let value: TestValue = bincode::deserialize(value)?;
+            if value.value == 13 {
+                return Err("store unavailable".into());
+            }
+            let location = response.page_id << 32 | response.page_offset;
+            self.calls.lock().unwrap().push((location, value.value));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_through_sync() {
+        let dir = tempdir().unwrap();
+        let store = MockStore::default();
+        let cache = FifoFileCache::builder(dir.path().join("test_write_through"), 16, 16 * 3)
+            .write_through(store.clone(), WriteThroughMode::Sync)
+            .build();
+        cache.write(TestValue::from(1)).unwrap();
+        cache
+            .write_many(vec![TestValue::from(2), TestValue::from(3)])
+            .unwrap();
+        assert_eq!(
+            *store.calls.lock().unwrap(),
+            vec![(0, 1), (8, 2), (1 << 32, 3)]
+        );
+
+        // Cached, but the write fails
+        let result = cache.write(TestValue::from(13));
+        assert!(matches!(result, Err(StorageError::WriteThrough(_))));
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.stats().write_through_failures, 1);
+    }
+
+    #[test]
+    fn test_write_through_async() {
+        let dir = tempdir().unwrap();
+        let store = MockStore::default();
+        let cache = FifoFileCache::builder(dir.path().join("test_write_through"), 16, 16 * 3)
+            .write_through(store.clone(), WriteThroughMode::Async)
+            .build();
+        for i in [1, 13, 2] {
+            cache.write(TestValue::from(i)).unwrap();
+        }
+        // The queued values are written before the thread exits
+        assert!(cache.close());
+        assert_eq!(*store.calls.lock().unwrap(), vec![(0, 1), (1 << 32, 2)]);
+        assert_eq!(cache.stats().write_through_failures, 1);
+    }
+}